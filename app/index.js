@@ -331,12 +331,39 @@ onresize = () => {
     resize();
 };
 
+let gamepadButtons = [];
+
+function poll_gamepads() {
+    let pressed = [];
+    for (const gamepad of navigator.getGamepads()) {
+        if ((gamepad !== null) && (gamepad.mapping === "standard")) {
+            gamepad.buttons.forEach((button, index) => {
+                pressed[index] = pressed[index] || button.pressed;
+            });
+        }
+    }
+
+    for (let index = 0; index < Math.max(pressed.length, gamepadButtons.length); index++) {
+        const isPressed = pressed[index] === true;
+        const wasPressed = gamepadButtons[index] === true;
+
+        if (isPressed && !wasPressed) {
+            system.button_down(index);
+        } else if (!isPressed && wasPressed) {
+            system.button_up(index);
+        }
+    }
+
+    gamepadButtons = pressed;
+}
+
 function renderLoop(now) {
     if (currentTime === undefined) {
         currentTime = now;
     }
 
     if (running) {
+        poll_gamepads();
         elapsed += now - currentTime;
 
         if ((elapsed / millisecondsPerFrame) >= 5) {
@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use jam1emu_lib::assembler;
+use std::fmt::Write;
+
+const BLOCK_COUNT: usize = 2000;
+
+// A few thousand lines mixing labels, comments, registers and mnemonics,
+// small enough to still fit below the monitor
+fn generate_source() -> String {
+    let mut code = String::from(".section \"code\" 0x100\n");
+    for i in 0..BLOCK_COUNT {
+        writeln!(code, "block_{i}: // block {i}").unwrap();
+        writeln!(code, "    mov a, {}", i & 0xFF).unwrap();
+        writeln!(code, "    mov b, a").unwrap();
+        writeln!(code, "    push a").unwrap();
+        writeln!(code, "    pop b").unwrap();
+        writeln!(code, "    jmp block_{i}").unwrap();
+    }
+    code
+}
+
+fn assemble(c: &mut Criterion) {
+    let code = generate_source();
+
+    c.bench_function("assemble", |b| {
+        b.iter(|| {
+            assembler::assemble_code(&code, false).unwrap_or_else(|output| panic!("{output}"))
+        })
+    });
+}
+
+criterion_group!(benches, assemble);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use jam1emu_lib::{System, Terminal};
+
+struct NullTerminal;
+
+impl vte::Perform for NullTerminal {}
+
+impl Terminal for NullTerminal {
+    fn reset(&mut self) {}
+    fn flush(&mut self) {}
+}
+
+fn memory_view(c: &mut Criterion) {
+    let mut system = System::create(NullTerminal);
+    system.reset();
+
+    // Loading a program only touches a small part of memory, so this measures
+    // the cost of keeping the memory view up to date.
+    let program = [0u8; 256];
+    c.bench_function("memory_view", |b| {
+        b.iter(|| system.load_program(0, &program).unwrap())
+    });
+}
+
+criterion_group!(benches, memory_view);
+criterion_main!(benches);
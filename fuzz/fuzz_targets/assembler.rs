@@ -0,0 +1,9 @@
+#![no_main]
+
+use jam1emu_lib::assembler;
+use libfuzzer_sys::fuzz_target;
+
+// Any input has to either assemble or produce errors, never panic
+fuzz_target!(|code: &str| {
+    let _ = assembler::assemble_code(code, false);
+});
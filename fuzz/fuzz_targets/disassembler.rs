@@ -0,0 +1,27 @@
+#![no_main]
+
+use jam1emu_lib::disassembler;
+use libfuzzer_sys::fuzz_target;
+
+// Memory views decode whatever is in RAM, so every byte stream has to decode without panicking
+// and every instruction has to advance by at least one byte
+fuzz_target!(|bytes: &[u8]| {
+    if let Some(instruction) = disassembler::disassemble(bytes) {
+        let length = instruction.length() as usize;
+        assert!((length >= 1) && (length <= bytes.len()));
+    } else {
+        assert!(bytes.is_empty());
+    }
+
+    let lines = disassembler::disassemble_program(bytes, 0);
+    let decoded: usize = lines.iter().map(|line| line.bytes().len()).sum();
+    assert_eq!(decoded, bytes.len());
+
+    if !bytes.is_empty() {
+        let read = |address: u16| bytes[(address as usize) % bytes.len()];
+        let address = bytes.len() as u16;
+        let lines = disassembler::disassemble_around(read, address, 16);
+        assert_eq!(lines.len(), 16);
+        assert!(lines.iter().all(|line| !line.bytes().is_empty()));
+    }
+});
@@ -448,6 +448,14 @@ pub enum Expression {
     BitwiseAnd(Box<BinaryExpression>),
     BitwiseOr(Box<BinaryExpression>),
     BitwiseXor(Box<BinaryExpression>),
+    Equal(Box<BinaryExpression>),
+    NotEqual(Box<BinaryExpression>),
+    LessThan(Box<BinaryExpression>),
+    LessThanOrEqual(Box<BinaryExpression>),
+    GreaterThan(Box<BinaryExpression>),
+    GreaterThanOrEqual(Box<BinaryExpression>),
+    /// `$`, the address of the statement the expression appears in
+    CurrentAddress(Box<Punctuation>),
 }
 
 impl Debug for Expression {
@@ -455,6 +463,7 @@ impl Debug for Expression {
         match self {
             Self::Literal(expr) => Debug::fmt(expr, f),
             Self::Identifier(expr) => Debug::fmt(expr, f),
+            Self::CurrentAddress(expr) => Debug::fmt(expr, f),
             Self::Group(expr) => Debug::fmt(expr, f),
             Self::Identity(expr) | Self::Negation(expr) | Self::BitwiseNot(expr) => {
                 Debug::fmt(expr, f)
@@ -469,7 +478,13 @@ impl Debug for Expression {
             | Self::LogicalRightShift(expr)
             | Self::BitwiseAnd(expr)
             | Self::BitwiseOr(expr)
-            | Self::BitwiseXor(expr) => Debug::fmt(expr, f),
+            | Self::BitwiseXor(expr)
+            | Self::Equal(expr)
+            | Self::NotEqual(expr)
+            | Self::LessThan(expr)
+            | Self::LessThanOrEqual(expr)
+            | Self::GreaterThan(expr)
+            | Self::GreaterThanOrEqual(expr) => Debug::fmt(expr, f),
         }
     }
 }
@@ -479,6 +494,7 @@ impl Display for Expression {
         match self {
             Self::Literal(expr) => Display::fmt(expr, f),
             Self::Identifier(expr) => Display::fmt(expr, f),
+            Self::CurrentAddress(expr) => Display::fmt(expr, f),
             Self::Group(expr) => Display::fmt(expr, f),
             Self::Identity(expr) | Self::Negation(expr) | Self::BitwiseNot(expr) => {
                 Display::fmt(expr, f)
@@ -493,7 +509,13 @@ impl Display for Expression {
             | Self::LogicalRightShift(expr)
             | Self::BitwiseAnd(expr)
             | Self::BitwiseOr(expr)
-            | Self::BitwiseXor(expr) => Display::fmt(expr, f),
+            | Self::BitwiseXor(expr)
+            | Self::Equal(expr)
+            | Self::NotEqual(expr)
+            | Self::LessThan(expr)
+            | Self::LessThanOrEqual(expr)
+            | Self::GreaterThan(expr)
+            | Self::GreaterThanOrEqual(expr) => Display::fmt(expr, f),
         }
     }
 }
@@ -503,6 +525,7 @@ impl Spanned for Expression {
         match self {
             Self::Literal(expr) => expr.span(),
             Self::Identifier(expr) => expr.span(),
+            Self::CurrentAddress(expr) => expr.span(),
             Self::Group(expr) => expr.span(),
             Self::Identity(expr) | Self::Negation(expr) | Self::BitwiseNot(expr) => expr.span(),
             Self::Addition(expr)
@@ -515,7 +538,47 @@ impl Spanned for Expression {
             | Self::LogicalRightShift(expr)
             | Self::BitwiseAnd(expr)
             | Self::BitwiseOr(expr)
-            | Self::BitwiseXor(expr) => expr.span(),
+            | Self::BitwiseXor(expr)
+            | Self::Equal(expr)
+            | Self::NotEqual(expr)
+            | Self::LessThan(expr)
+            | Self::LessThanOrEqual(expr)
+            | Self::GreaterThan(expr)
+            | Self::GreaterThanOrEqual(expr) => expr.span(),
+        }
+    }
+}
+
+impl Expression {
+    /// Calls `f` for every identifier the expression references
+    pub fn visit_identifiers(&self, f: &mut impl FnMut(&Identifier)) {
+        match self {
+            Self::Literal(_) | Self::CurrentAddress(_) => {}
+            Self::Identifier(expr) => f(expr),
+            Self::Group(expr) => expr.inner().visit_identifiers(f),
+            Self::Identity(expr) | Self::Negation(expr) | Self::BitwiseNot(expr) => {
+                expr.inner().visit_identifiers(f)
+            }
+            Self::Addition(expr)
+            | Self::Subtraction(expr)
+            | Self::Multiplication(expr)
+            | Self::Division(expr)
+            | Self::Remainder(expr)
+            | Self::LeftShift(expr)
+            | Self::ArithmeticRightShift(expr)
+            | Self::LogicalRightShift(expr)
+            | Self::BitwiseAnd(expr)
+            | Self::BitwiseOr(expr)
+            | Self::BitwiseXor(expr)
+            | Self::Equal(expr)
+            | Self::NotEqual(expr)
+            | Self::LessThan(expr)
+            | Self::LessThanOrEqual(expr)
+            | Self::GreaterThan(expr)
+            | Self::GreaterThanOrEqual(expr) => {
+                expr.lhs().visit_identifiers(f);
+                expr.rhs().visit_identifiers(f);
+            }
         }
     }
 }
@@ -689,12 +752,12 @@ impl Spanned for OriginDirective {
 pub struct SectionDirective {
     directive: Directive,
     name: StringLiteral,
-    base: Option<IntegerLiteral>,
+    base: Option<Expression>,
 }
 
 impl SectionDirective {
     #[inline]
-    pub fn new(directive: Directive, name: StringLiteral, base: Option<IntegerLiteral>) -> Self {
+    pub fn new(directive: Directive, name: StringLiteral, base: Option<Expression>) -> Self {
         Self {
             directive,
             name,
@@ -713,7 +776,7 @@ impl SectionDirective {
     }
 
     #[inline]
-    pub fn base(&self) -> Option<&IntegerLiteral> {
+    pub fn base(&self) -> Option<&Expression> {
         self.base.as_ref()
     }
 }
@@ -765,6 +828,146 @@ impl Spanned for IncludeDirective {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct EntryDirective {
+    directive: Directive,
+    symbol: Identifier,
+}
+
+impl EntryDirective {
+    #[inline]
+    pub fn new(directive: Directive, symbol: Identifier) -> Self {
+        Self { directive, symbol }
+    }
+
+    #[inline]
+    pub fn directive(&self) -> &Directive {
+        &self.directive
+    }
+
+    #[inline]
+    pub fn symbol(&self) -> &Identifier {
+        &self.symbol
+    }
+}
+
+impl Display for EntryDirective {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} {}", self.directive, self.symbol)
+    }
+}
+
+impl Spanned for EntryDirective {
+    fn span(&self) -> TextSpan {
+        self.directive.span().join(&self.symbol.span())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EndDirective {
+    directive: Directive,
+    symbol: Option<Identifier>,
+}
+
+impl EndDirective {
+    #[inline]
+    pub fn new(directive: Directive, symbol: Option<Identifier>) -> Self {
+        Self { directive, symbol }
+    }
+
+    #[inline]
+    pub fn directive(&self) -> &Directive {
+        &self.directive
+    }
+
+    #[inline]
+    pub fn symbol(&self) -> Option<&Identifier> {
+        self.symbol.as_ref()
+    }
+
+    /// The entry point this directive sets, if it names one
+    pub fn entry(&self) -> Option<EntryDirective> {
+        self.symbol
+            .as_ref()
+            .map(|symbol| EntryDirective::new(self.directive.clone(), symbol.clone()))
+    }
+}
+
+impl Display for EndDirective {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.symbol {
+            Some(symbol) => write!(f, "{} {}", self.directive, symbol),
+            None => Display::fmt(&self.directive, f),
+        }
+    }
+}
+
+impl Spanned for EndDirective {
+    fn span(&self) -> TextSpan {
+        match &self.symbol {
+            Some(symbol) => self.directive.span().join(&symbol.span()),
+            None => self.directive.span(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AssertDirective {
+    directive: Directive,
+    condition: Expression,
+    message: Option<(Punctuation, StringLiteral)>,
+}
+
+impl AssertDirective {
+    #[inline]
+    pub fn new(
+        directive: Directive,
+        condition: Expression,
+        message: Option<(Punctuation, StringLiteral)>,
+    ) -> Self {
+        Self {
+            directive,
+            condition,
+            message,
+        }
+    }
+
+    #[inline]
+    pub fn directive(&self) -> &Directive {
+        &self.directive
+    }
+
+    #[inline]
+    pub fn condition(&self) -> &Expression {
+        &self.condition
+    }
+
+    #[inline]
+    pub fn message(&self) -> Option<&StringLiteral> {
+        self.message.as_ref().map(|(_, message)| message)
+    }
+}
+
+impl Display for AssertDirective {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} {}", self.directive, self.condition)?;
+        if let Some((comma, message)) = &self.message {
+            write!(f, "{comma} {message}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Spanned for AssertDirective {
+    fn span(&self) -> TextSpan {
+        if let Some((_, message)) = &self.message {
+            self.directive.span().join(&message.span())
+        } else {
+            self.directive.span().join(&self.condition.span())
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum MovDestination {
     Register(Register),
@@ -1507,12 +1710,17 @@ impl Spanned for TestInstruction {
     }
 }
 
+/// Besides 8 bit registers, `add ab, cd` and `add cd, ab` add the 16 bit register pairs
+/// (low byte in `a`/`c`, high byte in `b`/`d`) by emitting `add` for the low and `addc` for the
+/// high bytes. All flags are overwritten and describe the high byte only, so the zero flag is
+/// also set for results like `0x0012` and can't be used to test the whole pair for zero.
 #[derive(Clone, Debug)]
 pub struct AddInstruction {
     mnemonic: Mnemonic,
     destination: Register,
     comma: Punctuation,
     source: Register,
+    emit_size: u16,
 }
 
 impl AddInstruction {
@@ -1522,7 +1730,7 @@ impl AddInstruction {
         comma: Punctuation,
         source: Register,
     ) -> Option<Self> {
-        match (destination.kind, source.kind) {
+        let emit_size = match (destination.kind, source.kind) {
             (RegisterKind::A, RegisterKind::B)
             | (RegisterKind::A, RegisterKind::C)
             | (RegisterKind::A, RegisterKind::D)
@@ -1535,14 +1743,18 @@ impl AddInstruction {
             | (RegisterKind::C, RegisterKind::D)
             | (RegisterKind::D, RegisterKind::A)
             | (RegisterKind::D, RegisterKind::B)
-            | (RegisterKind::D, RegisterKind::C) => Some(Self {
-                mnemonic,
-                destination,
-                comma,
-                source,
-            }),
-            _ => None,
-        }
+            | (RegisterKind::D, RegisterKind::C) => 1,
+            (RegisterKind::AB, RegisterKind::CD) | (RegisterKind::CD, RegisterKind::AB) => 2,
+            _ => return None,
+        };
+
+        Some(Self {
+            mnemonic,
+            destination,
+            comma,
+            source,
+            emit_size,
+        })
     }
 
     pub fn encode(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
@@ -1560,6 +1772,9 @@ impl AddInstruction {
             (RegisterKind::D, RegisterKind::A) => writer.write_all(&[0x91]),
             (RegisterKind::D, RegisterKind::B) => writer.write_all(&[0x92]),
             (RegisterKind::D, RegisterKind::C) => writer.write_all(&[0x93]),
+            // The low bytes (`a`/`c`) are combined first, the carry is then propagated into the high bytes
+            (RegisterKind::AB, RegisterKind::CD) => writer.write_all(&[0x89, 0x99]),
+            (RegisterKind::CD, RegisterKind::AB) => writer.write_all(&[0x8E, 0x9E]),
             _ => unreachable!("invalid ADD operand"),
         }
     }
@@ -1655,12 +1870,15 @@ impl Spanned for AddcInstruction {
     }
 }
 
+/// Like `add`, `sub ab, cd` and `sub cd, ab` operate on 16 bit register pairs by emitting `sub`
+/// for the low and `subb` for the high bytes, with the same caveats about the flags.
 #[derive(Clone, Debug)]
 pub struct SubInstruction {
     mnemonic: Mnemonic,
     destination: Register,
     comma: Punctuation,
     source: Register,
+    emit_size: u16,
 }
 
 impl SubInstruction {
@@ -1670,7 +1888,7 @@ impl SubInstruction {
         comma: Punctuation,
         source: Register,
     ) -> Option<Self> {
-        match (destination.kind, source.kind) {
+        let emit_size = match (destination.kind, source.kind) {
             (RegisterKind::A, RegisterKind::B)
             | (RegisterKind::A, RegisterKind::C)
             | (RegisterKind::A, RegisterKind::D)
@@ -1682,14 +1900,18 @@ impl SubInstruction {
             | (RegisterKind::C, RegisterKind::D)
             | (RegisterKind::D, RegisterKind::A)
             | (RegisterKind::D, RegisterKind::B)
-            | (RegisterKind::D, RegisterKind::C) => Some(Self {
-                mnemonic,
-                destination,
-                comma,
-                source,
-            }),
-            _ => None,
-        }
+            | (RegisterKind::D, RegisterKind::C) => 1,
+            (RegisterKind::AB, RegisterKind::CD) | (RegisterKind::CD, RegisterKind::AB) => 2,
+            _ => return None,
+        };
+
+        Some(Self {
+            mnemonic,
+            destination,
+            comma,
+            source,
+            emit_size,
+        })
     }
 
     pub fn encode(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
@@ -1706,6 +1928,9 @@ impl SubInstruction {
             (RegisterKind::D, RegisterKind::A) => writer.write_all(&[0xB1]),
             (RegisterKind::D, RegisterKind::B) => writer.write_all(&[0xB2]),
             (RegisterKind::D, RegisterKind::C) => writer.write_all(&[0xB3]),
+            // The low bytes (`a`/`c`) are combined first, the carry is then propagated into the high bytes
+            (RegisterKind::AB, RegisterKind::CD) => writer.write_all(&[0xA9, 0xB9]),
+            (RegisterKind::CD, RegisterKind::AB) => writer.write_all(&[0xAE, 0xBE]),
             _ => unreachable!("invalid SUB operand"),
         }
     }
@@ -2509,7 +2734,7 @@ impl InInstruction {
             return None;
         }
 
-        if source.kind == IoRegisterKind::AudioData {
+        if !source.kind.readable() {
             return None;
         }
 
@@ -2564,11 +2789,8 @@ impl OutInstruction {
         comma: Punctuation,
         source: Register,
     ) -> Option<Self> {
-        match destination.kind {
-            IoRegisterKind::UartControl
-            | IoRegisterKind::ControllerData
-            | IoRegisterKind::VgaStatus => return None,
-            _ => {}
+        if !destination.kind.writable() {
+            return None;
         }
 
         if source.kind != RegisterKind::A {
@@ -2616,6 +2838,7 @@ pub enum Instruction {
     Lodsb(Mnemonic),
     Stosb(Mnemonic),
     Ret(Mnemonic),
+    Reti(Mnemonic),
     RetBd(Mnemonic),
     Clc(Mnemonic),
     Mov(MovInstruction),
@@ -2654,6 +2877,7 @@ impl Instruction {
             Self::Lodsb(_) => 1,
             Self::Stosb(_) => 1,
             Self::Ret(_) => 3,
+            Self::Reti(_) => 2,
             Self::RetBd(_) => 1,
             Self::Clc(_) => 1,
             Self::Mov(inst) => inst.emit_size,
@@ -2666,9 +2890,9 @@ impl Instruction {
             Self::Shr(_) => 1,
             Self::Not(_) => 1,
             Self::Test(_) => 1,
-            Self::Add(_) => 1,
+            Self::Add(inst) => inst.emit_size,
             Self::Addc(_) => 1,
-            Self::Sub(_) => 1,
+            Self::Sub(inst) => inst.emit_size,
             Self::Subb(_) => 1,
             Self::And(_) => 1,
             Self::Or(_) => 1,
@@ -2685,6 +2909,36 @@ impl Instruction {
         }
     }
 
+    /// The immediate operand of the instruction, together with the offsets
+    /// of its low and (if encoded) high byte within the emitted code
+    pub fn immediate(&self) -> Option<(&Expression, u16, Option<u16>)> {
+        match self {
+            Self::Mov(inst) => match (&inst.destination, &inst.source) {
+                (MovDestination::Register(destination), MovSource::Value(value)) => {
+                    let high = match destination.kind {
+                        RegisterKind::TX
+                        | RegisterKind::AB
+                        | RegisterKind::CD
+                        | RegisterKind::SI
+                        | RegisterKind::DI => Some(3),
+                        _ => None,
+                    };
+
+                    Some((value, 1, high))
+                }
+                _ => None,
+            },
+            Self::Call(CallInstruction { target, .. })
+            | Self::CallBd(CallBdInstruction { target, .. })
+            | Self::Jmp(JmpInstruction { target, .. })
+            | Self::Branch(BranchInstruction { target, .. }) => match target {
+                JumpTarget::Value(value) => Some((value, 1, Some(3))),
+                JumpTarget::Register(_) => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn encode(
         &self,
         mut writer: impl std::io::Write,
@@ -2698,6 +2952,7 @@ impl Instruction {
             Self::Lodsb(_) => writer.write_all(&[0x5B]),
             Self::Stosb(_) => writer.write_all(&[0x7E]),
             Self::Ret(_) => writer.write_all(&[0x5E, 0x00, 0x00]),
+            Self::Reti(_) => writer.write_all(&[0x00, 0x30]),
             Self::RetBd(_) => writer.write_all(&[0x5E]),
             Self::Clc(_) => writer.write_all(&[0x7F]),
             Self::Mov(inst) => inst.encode(writer, label_set, label_values, errors),
@@ -2738,6 +2993,7 @@ impl Display for Instruction {
             Self::Lodsb(mnemonic) => Display::fmt(mnemonic, f),
             Self::Stosb(mnemonic) => Display::fmt(mnemonic, f),
             Self::Ret(mnemonic) => Display::fmt(mnemonic, f),
+            Self::Reti(mnemonic) => Display::fmt(mnemonic, f),
             Self::RetBd(mnemonic) => Display::fmt(mnemonic, f),
             Self::Clc(mnemonic) => Display::fmt(mnemonic, f),
             Self::Mov(inst) => Display::fmt(inst, f),
@@ -2778,6 +3034,7 @@ impl Spanned for Instruction {
             Self::Lodsb(mnemonic) => mnemonic.span(),
             Self::Stosb(mnemonic) => mnemonic.span(),
             Self::Ret(mnemonic) => mnemonic.span(),
+            Self::Reti(mnemonic) => mnemonic.span(),
             Self::RetBd(mnemonic) => mnemonic.span(),
             Self::Clc(mnemonic) => mnemonic.span(),
             Self::Mov(inst) => inst.span(),
@@ -2818,6 +3075,9 @@ pub enum Statement {
     OriginDirective(Box<OriginDirective>),
     SectionDirective(Box<SectionDirective>),
     IncludeDirective(Box<IncludeDirective>),
+    EntryDirective(Box<EntryDirective>),
+    AssertDirective(Box<AssertDirective>),
+    EndDirective(Box<EndDirective>),
     Instruction(Box<Instruction>),
 }
 
@@ -2828,6 +3088,38 @@ impl Statement {
             _ => 0,
         }
     }
+
+    /// Calls `f` for every identifier the statement references, labels do not reference their own name
+    pub fn visit_identifiers(&self, f: &mut impl FnMut(&Identifier)) {
+        match self {
+            Self::Label(label) => {
+                if let LabelValue::Expression { value, .. } = label.value() {
+                    value.visit_identifiers(f);
+                }
+            }
+            Self::SectionDirective(directive) => {
+                if let Some(base) = directive.base() {
+                    base.visit_identifiers(f);
+                }
+            }
+            Self::EntryDirective(directive) => f(directive.symbol()),
+            Self::AssertDirective(directive) => directive.condition().visit_identifiers(f),
+            Self::EndDirective(directive) => {
+                if let Some(symbol) = directive.symbol() {
+                    f(symbol);
+                }
+            }
+            Self::Instruction(instruction) => {
+                if let Some((value, _, _)) = instruction.immediate() {
+                    value.visit_identifiers(f);
+                }
+            }
+            Self::OffsetDirective(_)
+            | Self::AlignDirective(_)
+            | Self::OriginDirective(_)
+            | Self::IncludeDirective(_) => {}
+        }
+    }
 }
 
 impl Display for Statement {
@@ -2839,6 +3131,9 @@ impl Display for Statement {
             Self::OriginDirective(directive) => Display::fmt(directive, f),
             Self::SectionDirective(directive) => Display::fmt(directive, f),
             Self::IncludeDirective(directive) => Display::fmt(directive, f),
+            Self::EntryDirective(directive) => Display::fmt(directive, f),
+            Self::AssertDirective(directive) => Display::fmt(directive, f),
+            Self::EndDirective(directive) => Display::fmt(directive, f),
             Self::Instruction(inst) => Display::fmt(inst, f),
         }
     }
@@ -2853,6 +3148,9 @@ impl Spanned for Statement {
             Self::OriginDirective(directive) => directive.span(),
             Self::SectionDirective(directive) => directive.span(),
             Self::IncludeDirective(directive) => directive.span(),
+            Self::EntryDirective(directive) => directive.span(),
+            Self::AssertDirective(directive) => directive.span(),
+            Self::EndDirective(directive) => directive.span(),
             Self::Instruction(inst) => inst.span(),
         }
     }
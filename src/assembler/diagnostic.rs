@@ -0,0 +1,196 @@
+use super::AssemblerError;
+use langbox::{FileServer, TextSpan};
+use serde::Serialize;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A zero-based line and column in a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DiagnosticPosition {
+    line: u32,
+    column: u32,
+}
+
+impl DiagnosticPosition {
+    #[inline]
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    #[inline]
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticLocation {
+    file: String,
+    start: DiagnosticPosition,
+    end: DiagnosticPosition,
+}
+
+impl DiagnosticLocation {
+    pub(super) fn new(
+        file_server: &FileServer,
+        span: TextSpan,
+        hint_range: Option<&Range<usize>>,
+    ) -> Self {
+        let file = file_server.get_file(span.file_id()).unwrap();
+        let (start_line, start_column) = span.start_pos().line_column(file_server);
+        let (end_line, end_column) = span.end_pos().line_column(file_server);
+
+        let (start_column, end_column) = match hint_range {
+            Some(range) if start_line == end_line => (
+                start_column + (range.start as u32),
+                start_column + (range.end as u32),
+            ),
+            _ => (start_column, end_column),
+        };
+
+        Self {
+            file: file.path().display().to_string(),
+            start: DiagnosticPosition {
+                line: start_line,
+                column: start_column,
+            },
+            end: DiagnosticPosition {
+                line: end_line,
+                column: end_column,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    #[inline]
+    pub fn start(&self) -> DiagnosticPosition {
+        self.start
+    }
+
+    #[inline]
+    pub fn end(&self) -> DiagnosticPosition {
+        self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    code: &'static str,
+    severity: Severity,
+    message: String,
+    #[serde(flatten)]
+    location: Option<DiagnosticLocation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<DiagnosticLocation>,
+}
+
+impl Diagnostic {
+    #[inline]
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    #[inline]
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The location the diagnostic refers to, if it is tied to a piece of source code
+    #[inline]
+    pub fn location(&self) -> Option<&DiagnosticLocation> {
+        self.location.as_ref()
+    }
+
+    /// The location of a previous definition, for diagnostics about duplicates
+    #[inline]
+    pub fn previous(&self) -> Option<&DiagnosticLocation> {
+        self.previous.as_ref()
+    }
+}
+
+impl AssemblerError {
+    /// A stable identifier for the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidDirective { .. } => "invalid-directive",
+            Self::InvalidIntegerLiteral { .. } => "invalid-integer-literal",
+            Self::UnclosedStringLiteral { .. } => "unclosed-string-literal",
+            Self::InvalidEscapeSequence { .. } => "invalid-escape-sequence",
+            Self::InvalidChars { .. } => "invalid-chars",
+            Self::DuplicateSectionBase { .. } => "duplicate-section-base",
+            Self::DuplicateLabel { .. } => "duplicate-label",
+            Self::SectionTooLarge { .. } => "section-too-large",
+            Self::InvalidValue { .. } => "invalid-value",
+            Self::InvalidOriginDirective { .. } => "invalid-origin-directive",
+            Self::UnresolvedSectionBase { .. } => "unresolved-section-base",
+            Self::InvalidEntryDirective { .. } => "invalid-entry-directive",
+            Self::UndefinedSection { .. } => "undefined-section",
+            Self::OverlappingSections { .. } => "overlapping-sections",
+            Self::DivideByZero { .. } => "divide-by-zero",
+            Self::ExpressionOverflow { .. } => "expression-overflow",
+            Self::NoCurrentAddress { .. } => "no-current-address",
+            Self::AssertionFailed { .. } => "assertion-failed",
+            Self::UndefinedSymbol { .. } => "undefined-symbol",
+            Self::CyclicExpression { .. } => "cyclic-expression",
+            Self::IncludeError { .. } => "include-error",
+            Self::IncludeUnsupported { .. } => "include-unsupported",
+            Self::FileError { .. } => "file-error",
+            Self::UnusedSymbol { .. } => "unused-symbol",
+            Self::CloseToMonitor { .. } => "close-to-monitor",
+            Self::ParseError(err) => match err {
+                super::parser::ParseError::UnexpectedToken { .. } => "unexpected-token",
+                super::parser::ParseError::InvalidOperands { .. } => "invalid-operands",
+                super::parser::ParseError::InvalidRegister { .. } => "invalid-register",
+                super::parser::ParseError::TokensRemaining { .. } => "tokens-remaining",
+                super::parser::ParseError::NoMatch { .. } => "no-match",
+                super::parser::ParseError::UnknownMnemonic { .. } => "unknown-mnemonic",
+            },
+        }
+    }
+
+    pub fn to_diagnostic(&self, file_server: &FileServer) -> Diagnostic {
+        let info = self.info();
+
+        let message = match info.note {
+            Some(note) => format!("{}: {note}", info.message),
+            None => info.message,
+        };
+
+        Diagnostic {
+            code: self.code(),
+            severity: self.severity(),
+            message,
+            location: info.span.map(|(span, hint_range)| {
+                DiagnosticLocation::new(file_server, span, hint_range.as_ref())
+            }),
+            previous: info
+                .previous
+                .map(|previous| DiagnosticLocation::new(file_server, previous, None)),
+        }
+    }
+}
+
+/// Serializes a list of errors as a JSON array of diagnostics
+pub fn diagnostics_to_json(errors: &[AssemblerError], file_server: &FileServer) -> String {
+    let diagnostics: Vec<_> = errors
+        .iter()
+        .map(|error| error.to_diagnostic(file_server))
+        .collect();
+
+    serde_json::to_string(&diagnostics).expect("diagnostics are always serializable")
+}
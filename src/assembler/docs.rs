@@ -0,0 +1,777 @@
+use super::lexer::*;
+use langbox::*;
+
+#[derive(Clone, Copy)]
+pub struct OperandForm {
+    syntax: &'static str,
+    encoding: &'static str,
+}
+
+impl OperandForm {
+    /// The instruction with placeholders for immediate operands, e.g. `mov a, imm8`
+    #[inline]
+    pub fn syntax(&self) -> &'static str {
+        self.syntax
+    }
+
+    /// The emitted bytes, with `??` in place of immediate bytes
+    #[inline]
+    pub fn encoding(&self) -> &'static str {
+        self.encoding
+    }
+
+    #[inline]
+    pub fn size(&self) -> u16 {
+        self.encoding.split(' ').count() as u16
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MnemonicDoc {
+    summary: &'static str,
+    forms: &'static [OperandForm],
+}
+
+impl MnemonicDoc {
+    #[inline]
+    pub fn summary(&self) -> &'static str {
+        self.summary
+    }
+
+    #[inline]
+    pub fn forms(&self) -> &'static [OperandForm] {
+        self.forms
+    }
+}
+
+impl std::fmt::Display for MnemonicDoc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.summary)?;
+
+        let width = self
+            .forms
+            .iter()
+            .map(|form| form.syntax.len())
+            .max()
+            .unwrap_or(0);
+
+        for form in self.forms {
+            let size = form.size();
+            write!(
+                f,
+                "\n{:width$}  {} ({} byte{})",
+                form.syntax,
+                form.encoding,
+                size,
+                if size == 1 { "" } else { "s" },
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+macro_rules! doc {
+    ($summary:literal, $($syntax:literal => $encoding:literal,)*) => {
+        MnemonicDoc {
+            summary: $summary,
+            forms: &[$(OperandForm { syntax: $syntax, encoding: $encoding }),*],
+        }
+    };
+}
+
+// Every operand combination the assembler accepts, the test below keeps this in sync
+#[rustfmt::skip]
+fn doc(kind: MnemonicKind) -> MnemonicDoc {
+    match kind {
+        MnemonicKind::Nop => doc!(
+            "No operation",
+            "nop" => "00",
+        ),
+        MnemonicKind::Cnop => doc!(
+            "No operation, same as `nop`",
+            "cnop" => "00",
+        ),
+        MnemonicKind::Mov => doc!(
+            "Copy a value, register or memory location",
+            "mov a, imm8"   => "01 ??",
+            "mov a, b"      => "07",
+            "mov a, c"      => "08",
+            "mov a, d"      => "09",
+            "mov a, tl"     => "1B",
+            "mov a, th"     => "1F",
+            "mov a, [si]"   => "40",
+            "mov a, [di]"   => "44",
+            "mov a, [tx]"   => "48",
+            "mov b, imm8"   => "02 ??",
+            "mov b, a"      => "0A",
+            "mov b, c"      => "0B",
+            "mov b, d"      => "0C",
+            "mov b, tl"     => "1C",
+            "mov b, th"     => "20",
+            "mov b, [si]"   => "41",
+            "mov b, [di]"   => "45",
+            "mov b, [tx]"   => "49",
+            "mov c, imm8"   => "03 ??",
+            "mov c, a"      => "0D",
+            "mov c, b"      => "0E",
+            "mov c, d"      => "0F",
+            "mov c, tl"     => "1D",
+            "mov c, th"     => "21",
+            "mov c, [si]"   => "42",
+            "mov c, [di]"   => "46",
+            "mov c, [tx]"   => "4A",
+            "mov d, imm8"   => "04 ??",
+            "mov d, a"      => "10",
+            "mov d, b"      => "11",
+            "mov d, c"      => "12",
+            "mov d, tl"     => "1E",
+            "mov d, th"     => "22",
+            "mov d, [si]"   => "43",
+            "mov d, [di]"   => "47",
+            "mov d, [tx]"   => "4B",
+            "mov tl, imm8"  => "05 ??",
+            "mov tl, a"     => "13",
+            "mov tl, b"     => "14",
+            "mov tl, c"     => "15",
+            "mov tl, d"     => "16",
+            "mov th, imm8"  => "06 ??",
+            "mov th, a"     => "17",
+            "mov th, b"     => "18",
+            "mov th, c"     => "19",
+            "mov th, d"     => "1A",
+            "mov [si], a"   => "4C",
+            "mov [si], b"   => "4D",
+            "mov [si], c"   => "4E",
+            "mov [si], d"   => "4F",
+            "mov si, imm16" => "05 ?? 06 ?? 27",
+            "mov si, di"    => "2C",
+            "mov si, tx"    => "27",
+            "mov si, sp"    => "2D",
+            "mov [di], a"   => "50",
+            "mov [di], b"   => "51",
+            "mov [di], c"   => "52",
+            "mov [di], d"   => "53",
+            "mov di, imm16" => "05 ?? 06 ?? 29",
+            "mov di, si"    => "2B",
+            "mov di, tx"    => "29",
+            "mov di, sp"    => "2E",
+            "mov [tx], a"   => "54",
+            "mov [tx], b"   => "55",
+            "mov [tx], c"   => "56",
+            "mov [tx], d"   => "57",
+            "mov tx, imm16" => "05 ?? 06 ??",
+            "mov tx, si"    => "28",
+            "mov tx, di"    => "2A",
+            "mov tx, ra"    => "24",
+            "mov tx, sp"    => "26",
+            "mov ab, imm16" => "01 ?? 02 ??",
+            "mov cd, imm16" => "03 ?? 04 ??",
+            "mov ra, tx"    => "23",
+            "mov sp, tx"    => "25",
+        ),
+        MnemonicKind::Inc => doc!(
+            "Increment a register",
+            "inc a"  => "A0",
+            "inc b"  => "A1",
+            "inc c"  => "A2",
+            "inc d"  => "A3",
+            "inc si" => "35",
+            "inc di" => "36",
+        ),
+        MnemonicKind::Incc => doc!(
+            "Increment a register if the carry flag is set",
+            "incc a"  => "A4",
+            "incc b"  => "A5",
+            "incc c"  => "A6",
+            "incc d"  => "A7",
+            "incc si" => "34",
+        ),
+        MnemonicKind::Dec => doc!(
+            "Decrement a register",
+            "dec a"  => "C0",
+            "dec b"  => "C1",
+            "dec c"  => "C2",
+            "dec d"  => "C3",
+            "dec si" => "32",
+            "dec di" => "33",
+        ),
+        MnemonicKind::In => doc!(
+            "Read from an I/O register",
+            "in a, uart_data"  => "3A",
+            "in a, uart_ctrl"  => "3B",
+            "in a, cntrl_data" => "3D",
+            "in a, vga"        => "31",
+            "in a, gpio"       => "3E",
+        ),
+        MnemonicKind::Out => doc!(
+            "Write to an I/O register",
+            "out uart_data, a"  => "39",
+            "out audio_data, a" => "3C",
+            "out gpio, a"       => "37",
+        ),
+        MnemonicKind::Break => doc!(
+            "Halt the emulator at this instruction",
+            "break" => "00 3F 00 00",
+        ),
+        MnemonicKind::Lodsb => doc!(
+            "Load the byte at `[si]` into `a` and increment `si`",
+            "lodsb" => "5B",
+        ),
+        MnemonicKind::Stosb => doc!(
+            "Store `a` at `[di]` and increment `di`",
+            "stosb" => "7E",
+        ),
+        MnemonicKind::Call => doc!(
+            "Call a subroutine",
+            "call imm16" => "05 ?? 06 ?? 5C 00 00",
+            "call di"    => "5D 00 00",
+            "call tx"    => "5C 00 00",
+        ),
+        MnemonicKind::Ret => doc!(
+            "Return from a subroutine",
+            "ret" => "5E 00 00",
+        ),
+        MnemonicKind::Reti => doc!(
+            "Return from an interrupt handler",
+            "reti" => "00 30",
+        ),
+        MnemonicKind::CallBd => doc!(
+            "Call a subroutine without filling the branch delay",
+            "callbd imm16" => "05 ?? 06 ?? 5C",
+            "callbd di"    => "5D",
+            "callbd tx"    => "5C",
+        ),
+        MnemonicKind::RetBd => doc!(
+            "Return from a subroutine without filling the branch delay",
+            "retbd" => "5E",
+        ),
+        MnemonicKind::Jmp => doc!(
+            "Jump unconditionally",
+            "jmp imm16" => "05 ?? 06 ?? 5F 60",
+            "jmp di"    => "5F 71",
+            "jmp tx"    => "5F 60",
+        ),
+        MnemonicKind::Jo => doc!(
+            "Jump if overflow",
+            "jo imm16" => "05 ?? 06 ?? 5F 61",
+            "jo tx"    => "5F 61",
+        ),
+        MnemonicKind::Jno => doc!(
+            "Jump if not overflow",
+            "jno imm16" => "05 ?? 06 ?? 5F 62",
+            "jno tx"    => "5F 62",
+        ),
+        MnemonicKind::Js => doc!(
+            "Jump if sign",
+            "js imm16" => "05 ?? 06 ?? 5F 63",
+            "js tx"    => "5F 63",
+        ),
+        MnemonicKind::Jns => doc!(
+            "Jump if not sign",
+            "jns imm16" => "05 ?? 06 ?? 5F 64",
+            "jns tx"    => "5F 64",
+        ),
+        MnemonicKind::Jz => doc!(
+            "Jump if zero",
+            "jz imm16" => "05 ?? 06 ?? 5F 65",
+            "jz tx"    => "5F 65",
+        ),
+        MnemonicKind::Jnz => doc!(
+            "Jump if not zero",
+            "jnz imm16" => "05 ?? 06 ?? 5F 66",
+            "jnz tx"    => "5F 66",
+        ),
+        MnemonicKind::Je => doc!(
+            "Jump if equal",
+            "je imm16" => "05 ?? 06 ?? 5F 65",
+            "je tx"    => "5F 65",
+        ),
+        MnemonicKind::Jne => doc!(
+            "Jump if not equal",
+            "jne imm16" => "05 ?? 06 ?? 5F 66",
+            "jne tx"    => "5F 66",
+        ),
+        MnemonicKind::Jc => doc!(
+            "Jump if carry",
+            "jc imm16" => "05 ?? 06 ?? 5F 67",
+            "jc tx"    => "5F 67",
+        ),
+        MnemonicKind::Jnc => doc!(
+            "Jump if not carry",
+            "jnc imm16" => "05 ?? 06 ?? 5F 68",
+            "jnc tx"    => "5F 68",
+        ),
+        MnemonicKind::Jnae => doc!(
+            "Jump if not above or equal (unsigned)",
+            "jnae imm16" => "05 ?? 06 ?? 5F 68",
+            "jnae tx"    => "5F 68",
+        ),
+        MnemonicKind::Jb => doc!(
+            "Jump if below (unsigned)",
+            "jb imm16" => "05 ?? 06 ?? 5F 68",
+            "jb tx"    => "5F 68",
+        ),
+        MnemonicKind::Jae => doc!(
+            "Jump if above or equal (unsigned)",
+            "jae imm16" => "05 ?? 06 ?? 5F 67",
+            "jae tx"    => "5F 67",
+        ),
+        MnemonicKind::Jnb => doc!(
+            "Jump if not below (unsigned)",
+            "jnb imm16" => "05 ?? 06 ?? 5F 67",
+            "jnb tx"    => "5F 67",
+        ),
+        MnemonicKind::Jbe => doc!(
+            "Jump if below or equal (unsigned)",
+            "jbe imm16" => "05 ?? 06 ?? 5F 69",
+            "jbe tx"    => "5F 69",
+        ),
+        MnemonicKind::Jna => doc!(
+            "Jump if not above (unsigned)",
+            "jna imm16" => "05 ?? 06 ?? 5F 69",
+            "jna tx"    => "5F 69",
+        ),
+        MnemonicKind::Ja => doc!(
+            "Jump if above (unsigned)",
+            "ja imm16" => "05 ?? 06 ?? 5F 6A",
+            "ja tx"    => "5F 6A",
+        ),
+        MnemonicKind::Jnbe => doc!(
+            "Jump if not below or equal (unsigned)",
+            "jnbe imm16" => "05 ?? 06 ?? 5F 6A",
+            "jnbe tx"    => "5F 6A",
+        ),
+        MnemonicKind::Jl => doc!(
+            "Jump if less (signed)",
+            "jl imm16" => "05 ?? 06 ?? 5F 6B",
+            "jl tx"    => "5F 6B",
+        ),
+        MnemonicKind::Jnge => doc!(
+            "Jump if not greater or equal (signed)",
+            "jnge imm16" => "05 ?? 06 ?? 5F 6B",
+            "jnge tx"    => "5F 6B",
+        ),
+        MnemonicKind::Jge => doc!(
+            "Jump if greater or equal (signed)",
+            "jge imm16" => "05 ?? 06 ?? 5F 6C",
+            "jge tx"    => "5F 6C",
+        ),
+        MnemonicKind::Jnl => doc!(
+            "Jump if not less (signed)",
+            "jnl imm16" => "05 ?? 06 ?? 5F 6C",
+            "jnl tx"    => "5F 6C",
+        ),
+        MnemonicKind::Jle => doc!(
+            "Jump if less or equal (signed)",
+            "jle imm16" => "05 ?? 06 ?? 5F 6D",
+            "jle tx"    => "5F 6D",
+        ),
+        MnemonicKind::Jng => doc!(
+            "Jump if not greater (signed)",
+            "jng imm16" => "05 ?? 06 ?? 5F 6D",
+            "jng tx"    => "5F 6D",
+        ),
+        MnemonicKind::Jg => doc!(
+            "Jump if greater (signed)",
+            "jg imm16" => "05 ?? 06 ?? 5F 6E",
+            "jg tx"    => "5F 6E",
+        ),
+        MnemonicKind::Jnle => doc!(
+            "Jump if not less or equal (signed)",
+            "jnle imm16" => "05 ?? 06 ?? 5F 6E",
+            "jnle tx"    => "5F 6E",
+        ),
+        MnemonicKind::Jlc => doc!(
+            "Jump if logical carry",
+            "jlc imm16" => "05 ?? 06 ?? 5F 6F",
+            "jlc tx"    => "5F 6F",
+        ),
+        MnemonicKind::Jnlc => doc!(
+            "Jump if not logical carry",
+            "jnlc imm16" => "05 ?? 06 ?? 5F 70",
+            "jnlc tx"    => "5F 70",
+        ),
+        MnemonicKind::Push => doc!(
+            "Push a register onto the stack",
+            "push a"  => "72",
+            "push b"  => "73",
+            "push c"  => "74",
+            "push d"  => "75",
+            "push tl" => "76",
+            "push th" => "77",
+            "push si" => "28 76 77",
+            "push di" => "2A 76 77",
+            "push tx" => "76 77",
+            "push ra" => "24 76 77",
+            "push sp" => "26 76 77",
+        ),
+        MnemonicKind::Pop => doc!(
+            "Pop a register from the stack",
+            "pop a"  => "78",
+            "pop b"  => "79",
+            "pop c"  => "7A",
+            "pop d"  => "7B",
+            "pop tl" => "7C",
+            "pop th" => "7D",
+            "pop si" => "7D 7C 00 27",
+            "pop di" => "7D 7C 00 29",
+            "pop tx" => "7D 7C",
+            "pop ra" => "7D 7C 00 23",
+            "pop sp" => "7D 7C 00 25",
+        ),
+        MnemonicKind::Clc => doc!(
+            "Clear the carry flags",
+            "clc" => "7F",
+        ),
+        MnemonicKind::Shl => doc!(
+            "Shift a register left by one bit",
+            "shl a" => "80",
+            "shl b" => "81",
+            "shl c" => "82",
+            "shl d" => "83",
+        ),
+        MnemonicKind::Shr => doc!(
+            "Shift a register right by one bit",
+            "shr a" => "84",
+            "shr b" => "85",
+            "shr c" => "86",
+            "shr d" => "87",
+        ),
+        MnemonicKind::Add => doc!(
+            "Add two registers or the register pairs `ab` and `cd`",
+            "add a, b"   => "88",
+            "add a, c"   => "89",
+            "add a, d"   => "8A",
+            "add b, a"   => "8B",
+            "add b, b"   => "59",
+            "add b, c"   => "8C",
+            "add b, d"   => "8D",
+            "add c, a"   => "8E",
+            "add c, b"   => "8F",
+            "add c, d"   => "90",
+            "add d, a"   => "91",
+            "add d, b"   => "92",
+            "add d, c"   => "93",
+            "add ab, cd" => "89 99",
+            "add cd, ab" => "8E 9E",
+        ),
+        MnemonicKind::Addc => doc!(
+            "Add two registers with carry",
+            "addc a, b" => "94",
+            "addc a, c" => "95",
+            "addc a, d" => "96",
+            "addc b, a" => "97",
+            "addc b, b" => "58",
+            "addc b, c" => "98",
+            "addc b, d" => "99",
+            "addc c, a" => "9A",
+            "addc c, b" => "9B",
+            "addc c, d" => "9C",
+            "addc d, a" => "9D",
+            "addc d, b" => "9E",
+            "addc d, c" => "9F",
+        ),
+        MnemonicKind::Addac => doc!(
+            "Add `a` to `c` with carry",
+            "addac c, a" => "5A",
+        ),
+        MnemonicKind::Sub => doc!(
+            "Subtract two registers or the register pairs `ab` and `cd`",
+            "sub a, b"   => "A8",
+            "sub a, c"   => "A9",
+            "sub a, d"   => "AA",
+            "sub b, a"   => "AB",
+            "sub b, c"   => "AC",
+            "sub b, d"   => "AD",
+            "sub c, a"   => "AE",
+            "sub c, b"   => "AF",
+            "sub c, d"   => "B0",
+            "sub d, a"   => "B1",
+            "sub d, b"   => "B2",
+            "sub d, c"   => "B3",
+            "sub ab, cd" => "A9 B9",
+            "sub cd, ab" => "AE BE",
+        ),
+        MnemonicKind::Subb => doc!(
+            "Subtract two registers with borrow",
+            "subb a, b" => "B4",
+            "subb a, c" => "B5",
+            "subb a, d" => "B6",
+            "subb b, a" => "B7",
+            "subb b, c" => "B8",
+            "subb b, d" => "B9",
+            "subb c, a" => "BA",
+            "subb c, b" => "BB",
+            "subb c, d" => "BC",
+            "subb d, a" => "BD",
+            "subb d, b" => "BE",
+            "subb d, c" => "BF",
+        ),
+        MnemonicKind::Subae => doc!(
+            "Subtract `a` from `c` with borrow",
+            "subae d, c" => "2F",
+        ),
+        MnemonicKind::And => doc!(
+            "Bitwise AND of two registers",
+            "and a, b" => "C4",
+            "and a, c" => "C5",
+            "and a, d" => "C6",
+            "and b, a" => "C7",
+            "and b, c" => "C8",
+            "and b, d" => "C9",
+            "and c, a" => "CA",
+            "and c, b" => "CB",
+            "and c, d" => "CC",
+            "and d, a" => "CD",
+            "and d, b" => "CE",
+            "and d, c" => "CF",
+        ),
+        MnemonicKind::Or => doc!(
+            "Bitwise OR of two registers",
+            "or a, b" => "D0",
+            "or a, c" => "D1",
+            "or a, d" => "D2",
+            "or b, a" => "D3",
+            "or b, c" => "D4",
+            "or b, d" => "D5",
+            "or c, a" => "D6",
+            "or c, b" => "D7",
+            "or c, d" => "D8",
+            "or d, a" => "D9",
+            "or d, b" => "DA",
+            "or d, c" => "DB",
+        ),
+        MnemonicKind::Xor => doc!(
+            "Bitwise XOR of two registers",
+            "xor a, a" => "E8",
+            "xor a, b" => "DC",
+            "xor a, c" => "DD",
+            "xor a, d" => "DE",
+            "xor b, a" => "DF",
+            "xor b, b" => "E9",
+            "xor b, c" => "E0",
+            "xor b, d" => "E1",
+            "xor c, a" => "E2",
+            "xor c, b" => "E3",
+            "xor c, c" => "EA",
+            "xor c, d" => "E4",
+            "xor d, a" => "E5",
+            "xor d, b" => "E6",
+            "xor d, c" => "E7",
+            "xor d, d" => "EB",
+        ),
+        MnemonicKind::Not => doc!(
+            "Bitwise NOT of a register",
+            "not a" => "EC",
+            "not b" => "ED",
+            "not c" => "EE",
+            "not d" => "EF",
+        ),
+        MnemonicKind::Cmp => doc!(
+            "Compare two registers by subtracting them",
+            "cmp a, b" => "F0",
+            "cmp a, c" => "F1",
+            "cmp a, d" => "F2",
+            "cmp b, a" => "F3",
+            "cmp b, c" => "F4",
+            "cmp b, d" => "F5",
+            "cmp c, a" => "F6",
+            "cmp c, b" => "F7",
+            "cmp c, d" => "F8",
+            "cmp d, a" => "F9",
+            "cmp d, b" => "FA",
+            "cmp d, c" => "FB",
+        ),
+        MnemonicKind::Test => doc!(
+            "Set the flags according to a register's value",
+            "test a" => "FC",
+            "test b" => "FD",
+            "test c" => "FE",
+            "test d" => "FF",
+        ),
+    }
+}
+
+/// Looks up the documentation of a mnemonic, ignoring case like the lexer does
+pub fn mnemonic_doc(mnemonic: &str) -> Option<MnemonicDoc> {
+    if mnemonic.is_empty() {
+        return None;
+    }
+
+    let result = Jam1TokenReader::read_token(mnemonic);
+    match result.token {
+        Jam1Token::Mnemonic(kind) if result.consumed_bytes == mnemonic.len() => Some(doc(kind)),
+        _ => None,
+    }
+}
+
+/// Finds the mnemonic at a character column in a single line of code
+pub fn mnemonic_at(line: &str, column: usize) -> Option<String> {
+    let mut offset = 0;
+    let mut char_offset = 0;
+
+    while offset < line.len() {
+        let rest = &line[offset..];
+        let trimmed = rest.trim_start();
+        if trimmed.is_empty() {
+            break;
+        }
+
+        let whitespace = &rest[..(rest.len() - trimmed.len())];
+        offset += whitespace.len();
+        char_offset += whitespace.chars().count();
+
+        let result = Jam1TokenReader::read_token(trimmed);
+        if result.consumed_bytes == 0 {
+            break;
+        }
+
+        let token_text = &trimmed[..result.consumed_bytes];
+        let token_chars = token_text.chars().count();
+
+        if column < char_offset {
+            break;
+        }
+
+        if column < (char_offset + token_chars) {
+            return match result.token {
+                Jam1Token::Mnemonic(kind) => Some(kind.to_string()),
+                _ => None,
+            };
+        }
+
+        offset += result.consumed_bytes;
+        char_offset += token_chars;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast::Statement;
+    use super::super::parser;
+    use super::*;
+    use std::collections::HashMap;
+
+    // Assembles a single line, returning `None` if it is not a valid instruction
+    fn encode_line(file_server: &mut FileServer, line: String) -> Option<Vec<u8>> {
+        let path = format!("<doc{}>", file_server.files().count());
+        let file = file_server.register_file_memory(path, line).ok()?;
+
+        let tokens: Vec<_> = Jam1Lexer::new(file, file_server)
+            .filter(|token| !matches!(token.kind, Jam1Token::NewLine | Jam1Token::Comment(_)))
+            .collect();
+
+        let Ok(Statement::Instruction(instruction)) = parser::parse(TokenStream::new(&tokens))
+        else {
+            return None;
+        };
+
+        let mut data = Vec::new();
+        let mut errors = Vec::new();
+        instruction
+            .encode(&mut data, &HashMap::new(), &HashMap::new(), &mut errors)
+            .expect("writing to an in-memory buffer");
+
+        (errors.is_empty() && (data.len() == (instruction.emit_size() as usize))).then_some(data)
+    }
+
+    // Tries every combination of operands against the assembler
+    fn accepted_forms(name: &str) -> Vec<(String, String)> {
+        const IMMEDIATE: &str = "imm";
+
+        let mut operands = vec![IMMEDIATE.to_owned()];
+        for (register, kind) in keywords() {
+            match kind {
+                KeywordKind::Register => {
+                    operands.push(format!("[{register}]"));
+                    operands.push(register);
+                }
+                KeywordKind::IoRegister => operands.push(register),
+                _ => {}
+            }
+        }
+
+        let mut candidates = vec![Vec::new()];
+        for op1 in &operands {
+            candidates.push(vec![op1.as_str()]);
+            for op2 in &operands {
+                candidates.push(vec![op1.as_str(), op2.as_str()]);
+            }
+        }
+
+        let mut file_server = FileServer::new();
+        let mut forms = Vec::new();
+        for candidate in candidates {
+            let line = |immediate: &str| {
+                let operands: Vec<_> = candidate
+                    .iter()
+                    .map(|&op| if op == IMMEDIATE { immediate } else { op })
+                    .collect();
+                format!("{name} {}", operands.join(", "))
+            };
+
+            // Immediate bytes are the ones that differ between two encodings with different values
+            let Some(low) = encode_line(&mut file_server, line("0")) else {
+                continue;
+            };
+            let Some(high) = encode_line(&mut file_server, line("0xFFFF")) else {
+                continue;
+            };
+
+            let immediate_bytes = low.iter().zip(&high).filter(|(l, h)| l != h).count();
+            let immediate = match immediate_bytes {
+                0 => IMMEDIATE,
+                1 => "imm8",
+                _ => "imm16",
+            };
+
+            let encoding: Vec<_> = low
+                .iter()
+                .zip(&high)
+                .map(|(l, h)| {
+                    if l == h {
+                        format!("{l:0>2X}")
+                    } else {
+                        "??".to_owned()
+                    }
+                })
+                .collect();
+
+            forms.push((line(immediate).trim_end().to_owned(), encoding.join(" ")));
+        }
+
+        forms
+    }
+
+    #[test]
+    fn forms_match_the_assembler() {
+        for (name, kind) in keywords() {
+            if kind != KeywordKind::Mnemonic {
+                continue;
+            }
+
+            let doc = mnemonic_doc(&name).unwrap();
+            let forms: Vec<_> = doc
+                .forms()
+                .iter()
+                .map(|form| (form.syntax().to_owned(), form.encoding().to_owned()))
+                .collect();
+            assert_eq!(forms, accepted_forms(&name), "forms of `{name}`");
+        }
+    }
+
+    #[test]
+    fn lookup_ignores_case_and_other_tokens() {
+        assert_eq!(
+            mnemonic_doc("MOV").unwrap().summary(),
+            doc(MnemonicKind::Mov).summary()
+        );
+        assert!(mnemonic_doc("").is_none());
+        assert!(mnemonic_doc("tx").is_none());
+        assert!(mnemonic_doc("movx").is_none());
+        assert!(mnemonic_doc("mov a").is_none());
+    }
+}
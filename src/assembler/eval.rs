@@ -8,9 +8,20 @@ use std::collections::HashMap;
 pub enum EvalError {
     InvalidLiteralValue(IntegerLiteral),
     DivideByZero(BinaryExpression),
+    Overflow(TextSpan),
     ErrorInReferenceEval,
     MissingReferenceValue,
     UndefinedSymbol(Identifier),
+    NoCurrentAddress(TextSpan),
+}
+
+/// The value map entry `$` evaluates to, which is only present while evaluating
+/// a statement that has an address
+pub const CURRENT_ADDRESS: &str = "$";
+
+// Shifting by a negative amount or by the full width or more is reported as an overflow
+fn shift_amount(rhs: i64) -> Option<u32> {
+    u32::try_from(rhs).ok()
 }
 
 impl Expression {
@@ -32,12 +43,21 @@ impl Expression {
                     Err(EvalError::UndefinedSymbol(expr.as_ref().clone()))
                 }
             }
+            Expression::CurrentAddress(expr) => match value_map.get(CURRENT_ADDRESS) {
+                Some(&Some(value)) => Ok(value),
+                _ => Err(EvalError::NoCurrentAddress(expr.span())),
+            },
             Expression::Group(expr) => expr.inner().try_eval(label_set, value_map),
             Expression::Identity(expr) => expr.inner().try_eval(label_set, value_map),
-            Expression::Negation(expr) => expr
-                .inner()
-                .try_eval(label_set, value_map)
-                .map(|value| value.wrapping_neg()),
+            Expression::Negation(expr) => {
+                expr.inner()
+                    .try_eval(label_set, value_map)
+                    .and_then(|value| {
+                        value
+                            .checked_neg()
+                            .ok_or_else(|| EvalError::Overflow(expr.span()))
+                    })
+            }
             Expression::BitwiseNot(expr) => expr
                 .inner()
                 .try_eval(label_set, value_map)
@@ -45,17 +65,20 @@ impl Expression {
             Expression::Addition(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
                 let rhs = expr.rhs().try_eval(label_set, value_map)?;
-                Ok(lhs.wrapping_add(rhs))
+                lhs.checked_add(rhs)
+                    .ok_or_else(|| EvalError::Overflow(expr.span()))
             }
             Expression::Subtraction(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
                 let rhs = expr.rhs().try_eval(label_set, value_map)?;
-                Ok(lhs.wrapping_sub(rhs))
+                lhs.checked_sub(rhs)
+                    .ok_or_else(|| EvalError::Overflow(expr.span()))
             }
             Expression::Multiplication(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
                 let rhs = expr.rhs().try_eval(label_set, value_map)?;
-                Ok(lhs.wrapping_mul(rhs))
+                lhs.checked_mul(rhs)
+                    .ok_or_else(|| EvalError::Overflow(expr.span()))
             }
             Expression::Division(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
@@ -63,7 +86,8 @@ impl Expression {
                 if rhs == 0 {
                     Err(EvalError::DivideByZero(expr.as_ref().clone()))
                 } else {
-                    Ok(lhs.wrapping_div(rhs))
+                    lhs.checked_div(rhs)
+                        .ok_or_else(|| EvalError::Overflow(expr.span()))
                 }
             }
             Expression::Remainder(expr) => {
@@ -72,23 +96,38 @@ impl Expression {
                 if rhs == 0 {
                     Err(EvalError::DivideByZero(expr.as_ref().clone()))
                 } else {
-                    Ok(lhs.wrapping_rem(rhs))
+                    lhs.checked_rem(rhs)
+                        .ok_or_else(|| EvalError::Overflow(expr.span()))
                 }
             }
             Expression::LeftShift(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
                 let rhs = expr.rhs().try_eval(label_set, value_map)?;
-                Ok(lhs << rhs)
+                shift_amount(rhs)
+                    .and_then(|rhs| {
+                        // `checked_shl` only checks the amount, bits that are shifted out are lost too
+                        let shifted = lhs.checked_shl(rhs)?;
+                        ((shifted >> rhs) == lhs).then_some(shifted)
+                    })
+                    .ok_or_else(|| EvalError::Overflow(expr.span()))
             }
             Expression::ArithmeticRightShift(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
                 let rhs = expr.rhs().try_eval(label_set, value_map)?;
-                Ok(lhs >> rhs)
+                shift_amount(rhs)
+                    .and_then(|rhs| lhs.checked_shr(rhs))
+                    .ok_or_else(|| EvalError::Overflow(expr.span()))
             }
             Expression::LogicalRightShift(expr) => {
+                // Values are 64 bits wide, so a logical shift fills in zeroes from bit 63 and not
+                // from the top of the 8 or 16 bit operand the result ends up in. `(-8) >> 1`
+                // truncated to a byte is therefore 0xFC and not 0x7C, mask the value for that.
                 let lhs = expr.lhs().try_eval(label_set, value_map)? as u64;
-                let rhs = expr.rhs().try_eval(label_set, value_map)? as u64;
-                Ok((lhs >> rhs) as i64)
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                shift_amount(rhs)
+                    .and_then(|rhs| lhs.checked_shr(rhs))
+                    .map(|value| value as i64)
+                    .ok_or_else(|| EvalError::Overflow(expr.span()))
             }
             Expression::BitwiseAnd(expr) => {
                 let lhs = expr.lhs().try_eval(label_set, value_map)?;
@@ -105,6 +144,36 @@ impl Expression {
                 let rhs = expr.rhs().try_eval(label_set, value_map)?;
                 Ok(lhs ^ rhs)
             }
+            Expression::Equal(expr) => {
+                let lhs = expr.lhs().try_eval(label_set, value_map)?;
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                Ok((lhs == rhs) as i64)
+            }
+            Expression::NotEqual(expr) => {
+                let lhs = expr.lhs().try_eval(label_set, value_map)?;
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                Ok((lhs != rhs) as i64)
+            }
+            Expression::LessThan(expr) => {
+                let lhs = expr.lhs().try_eval(label_set, value_map)?;
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                Ok((lhs < rhs) as i64)
+            }
+            Expression::LessThanOrEqual(expr) => {
+                let lhs = expr.lhs().try_eval(label_set, value_map)?;
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                Ok((lhs <= rhs) as i64)
+            }
+            Expression::GreaterThan(expr) => {
+                let lhs = expr.lhs().try_eval(label_set, value_map)?;
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                Ok((lhs > rhs) as i64)
+            }
+            Expression::GreaterThanOrEqual(expr) => {
+                let lhs = expr.lhs().try_eval(label_set, value_map)?;
+                let rhs = expr.rhs().try_eval(label_set, value_map)?;
+                Ok((lhs >= rhs) as i64)
+            }
         }
     }
 
@@ -125,12 +194,165 @@ impl Expression {
                 errors.push(AssemblerError::DivideByZero { expr: expr.span() });
                 0
             }
+            Err(EvalError::Overflow(expr)) => {
+                errors.push(AssemblerError::ExpressionOverflow { expr });
+                0
+            }
             Err(EvalError::UndefinedSymbol(ident)) => {
-                errors.push(AssemblerError::UndefinedSymbol {
-                    ident: ident.span(),
-                });
+                errors.push(AssemblerError::undefined_symbol(&ident));
                 0
             }
+            Err(EvalError::NoCurrentAddress(expr)) => {
+                errors.push(AssemblerError::NoCurrentAddress { expr });
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assemble_impl, AssemblerError, SharedStr};
+    use langbox::FileServer;
+    use std::collections::HashMap;
+
+    // Assembles constant definitions and returns the value of every constant
+    fn eval_constants(code: &str) -> (HashMap<SharedStr, Option<i64>>, Vec<AssemblerError>) {
+        let mut file_server = FileServer::new();
+        let code = format!(".section \"code\" 0\n{code}\n");
+        let file = file_server.register_file_memory("<test>", code).unwrap();
+
+        let mut errors = Vec::new();
+        let (_, symbols) = assemble_impl(&mut file_server, &[file], None, &mut errors);
+        (symbols.label_values, errors)
+    }
+
+    fn eval(expr: &str) -> i64 {
+        let (values, errors) = eval_constants(&format!("VALUE = {expr}"));
+        assert!(errors.is_empty(), "`{expr}` failed: {errors:?}");
+        values["VALUE"].unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("2 + 3 * 4"), 14);
+        assert_eq!(eval("(2 + 3) * 4"), 20);
+        assert_eq!(eval("10 - 4 - 3"), 3);
+        assert_eq!(eval("1 | 2 & 4"), 1);
+        assert_eq!(eval("-2 * 3"), -6);
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(eval("1 << 4"), 16);
+        assert_eq!(eval("1 + 1 << 4"), 32);
+        assert_eq!(eval("1 << 62"), 1 << 62);
+        assert_eq!(eval("(-1) << 63"), i64::MIN);
+        assert_eq!(eval("0x80 >> 4"), 0x08);
+        assert_eq!(eval("0x80 >>> 4"), 0x08);
+
+        // Only negative values tell the two right shifts apart
+        assert_eq!(eval("(-8) >>> 1"), -4);
+        assert_eq!(eval("(-8) >> 1"), ((-8i64 as u64) >> 1) as i64);
+        assert_ne!(eval("(-8) >> 1"), eval("(-8) >>> 1"));
+        assert_eq!(eval("(-8) >> 1") as u8, 0xFC);
+        assert_eq!(eval("(-8 & 0xFF) >> 1"), 0x7C);
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        for expr in ["1 / 0", "1 % 0", "1 / (2 - 2)"] {
+            let (values, errors) = eval_constants(&format!("VALUE = {expr}"));
+            assert!(
+                matches!(errors[..], [AssemblerError::DivideByZero { .. }]),
+                "`{expr}` reported {errors:?}"
+            );
+            assert_eq!(values["VALUE"], None);
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(eval("0x10000 * 0x10000"), 0x1_0000_0000);
+
+        for expr in [
+            "0x100000000 * 0x100000000",
+            "0x7FFFFFFFFFFFFFFF + 1",
+            "-0x7FFFFFFFFFFFFFFF - 2",
+            "1 << 64",
+            "1 << 63",
+            "0x100 << 56",
+            "(-2) << 63",
+            "1 >> -1",
+        ] {
+            let (values, errors) = eval_constants(&format!("VALUE = {expr}"));
+            assert!(
+                matches!(errors[..], [AssemblerError::ExpressionOverflow { .. }]),
+                "`{expr}` reported {errors:?}"
+            );
+            assert_eq!(values["VALUE"], None);
         }
     }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(eval("1 < 2"), 1);
+        assert_eq!(eval("2 <= 1"), 0);
+        assert_eq!(eval("2 >= 2"), 1);
+        assert_eq!(eval("-1 > 0"), 0);
+        assert_eq!(eval("3 == 3"), 1);
+        assert_eq!(eval("3 != 3"), 0);
+
+        // Comparisons bind weaker than every other operator
+        assert_eq!(eval("1 | 2 == 3"), 1);
+        assert_eq!(eval("1 + 1 < 2"), 0);
+        assert_eq!(eval("2 > 1 == 1"), 1);
+    }
+
+    #[test]
+    fn assertions() {
+        let (_, errors) =
+            eval_constants("    mov a, 1\n    .assert $ == 2\n    .assert END <= 4\nEND:");
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let (_, errors) = eval_constants("    mov a, 1\nEND:\n    .assert END < 2, \"too large\"");
+        assert!(
+            matches!(
+                &errors[..],
+                [AssemblerError::AssertionFailed { message: Some(message), .. }]
+                    if message.as_ref() == "too large"
+            ),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn current_address_outside_statement() {
+        let (values, errors) = eval_constants("VALUE = $");
+        assert!(
+            matches!(errors[..], [AssemblerError::NoCurrentAddress { .. }]),
+            "{errors:?}"
+        );
+        assert_eq!(values["VALUE"], None);
+    }
+
+    #[test]
+    fn forward_references() {
+        let (values, errors) = eval_constants("X = Y + 1\nY = Z * 2\nZ = 3");
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(values["X"], Some(7));
+        assert_eq!(values["Y"], Some(6));
+    }
+
+    #[test]
+    fn cyclic_references() {
+        let (_, errors) = eval_constants("X = Y + 1\nY = X");
+        assert!(
+            errors
+                .iter()
+                .all(|error| matches!(error, AssemblerError::CyclicExpression { .. })),
+            "{errors:?}"
+        );
+        assert_eq!(errors.len(), 2);
+    }
 }
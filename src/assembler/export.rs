@@ -0,0 +1,144 @@
+use super::Program;
+use std::fmt::Write;
+use std::path::Path;
+
+const BYTES_PER_LINE: usize = 12;
+
+/// Turns the file name of `path` into a valid C identifier, e.g. `my-prog.h` into `my_prog`
+pub fn c_identifier(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+
+    let mut ident: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || (c == '_')) {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+fn write_bytes(output: &mut String, data: &[u8], indent: &str) {
+    for line in data.chunks(BYTES_PER_LINE) {
+        output.push_str(indent);
+        for (i, byte) in line.iter().enumerate() {
+            if i > 0 {
+                output.push(' ');
+            }
+            write!(output, "0x{byte:0>2X},").unwrap();
+        }
+        output.push('\n');
+    }
+}
+
+/// Formats the program as C definitions of `name` holding the bytes and `name_base` holding
+/// the address they have to be loaded at
+pub fn to_c_array(program: &Program, name: &str) -> String {
+    let mut output = String::new();
+    writeln!(output, "#include <stdint.h>").unwrap();
+    writeln!(output).unwrap();
+    writeln!(
+        output,
+        "const uint16_t {name}_base = 0x{:0>4X};",
+        program.base()
+    )
+    .unwrap();
+    writeln!(output, "const uint8_t {name}[] = {{").unwrap();
+    write_bytes(&mut output, program.data(), "    ");
+    writeln!(output, "}};").unwrap();
+    output
+}
+
+/// Formats the program as a Rust module defining `BASE` and `DATA`, to be embedded with `include!`
+pub fn to_rust_module(program: &Program) -> String {
+    let mut output = String::new();
+    writeln!(output, "pub const BASE: u16 = 0x{:0>4X};", program.base()).unwrap();
+    writeln!(output, "pub const DATA: &[u8] = &[").unwrap();
+    write_bytes(&mut output, program.data(), "    ");
+    writeln!(output, "];").unwrap();
+    output
+}
+
+/// The most bytes a single `writebin` command can load
+const MAX_WRITEBIN_COUNT: usize = 0xFFFF;
+
+/// Encodes `data` as the UART input that makes the monitor load it at `base`, so a program can be
+/// loaded purely through the serial port.
+///
+/// The stream consists of `writebin <addr> <count>\r` commands, with address and count in hex
+/// without prefix. After each command the monitor reads `count` bytes of two characters each,
+/// the low nibble first, with every nibble encoded as `'A' + nibble`, e.g. `0x2A` as `KC`. Longer
+/// programs are split into several commands, empty ones produce an empty stream since a count of
+/// zero would make the monitor wait for 65536 bytes.
+///
+/// The monitor echoes the characters of the commands, so the host must not send them faster than
+/// the UART transmits. The data characters are read without echo.
+pub fn to_monitor_loader_stream(base: u16, data: &[u8]) -> Vec<u8> {
+    let mut stream = Vec::new();
+    let mut addr = base;
+    for chunk in data.chunks(MAX_WRITEBIN_COUNT) {
+        let command = format!("writebin {addr:X} {:X}\r", chunk.len());
+        stream.extend_from_slice(command.as_bytes());
+        for &byte in chunk {
+            stream.push(b'A' + (byte & 0x0F));
+            stream.push(b'A' + (byte >> 4));
+        }
+        addr = addr.wrapping_add(chunk.len() as u16);
+    }
+    stream
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumKind {
+    /// One byte chosen so all bytes of the image add up to zero
+    SumToZero,
+    /// CRC-16/CCITT-FALSE of all bytes except the checksum itself, stored low byte first
+    Crc16,
+}
+
+impl ChecksumKind {
+    /// The number of bytes the checksum occupies
+    #[inline]
+    pub fn size(self) -> usize {
+        match self {
+            Self::SumToZero => 1,
+            Self::Crc16 => 2,
+        }
+    }
+}
+
+fn crc16(crc: u16, bytes: &[u8]) -> u16 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        let mut crc = crc ^ ((byte as u16) << 8);
+        for _ in 0..8 {
+            crc = if (crc & 0x8000) != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Writes a checksum of `data` to offset `at`, the bytes there are not part of the checksum.
+/// Panics if the checksum does not fit into `data` at that offset.
+pub fn apply_checksum(data: &mut [u8], kind: ChecksumKind, at: usize) {
+    let end = at + kind.size();
+    assert!(end <= data.len(), "checksum does not fit into the image");
+
+    match kind {
+        ChecksumKind::SumToZero => {
+            data[at] = 0;
+            let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            data[at] = sum.wrapping_neg();
+        }
+        ChecksumKind::Crc16 => {
+            let crc = crc16(crc16(0xFFFF, &data[..at]), &data[end..]);
+            data[at..end].copy_from_slice(&crc.to_le_bytes());
+        }
+    }
+}
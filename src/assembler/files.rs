@@ -0,0 +1,84 @@
+use langbox::{FileId, FileServer};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+/// Resolves the files referenced by `.include` directives
+pub trait FileProvider {
+    /// Registers the file at `path` with the file server, `path` is already
+    /// relative to the directory of the including file
+    fn register_file(&self, file_server: &mut FileServer, path: &Path) -> std::io::Result<FileId>;
+}
+
+/// Reads included files from disk
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsFileProvider;
+
+impl FileProvider for FsFileProvider {
+    #[inline]
+    fn register_file(&self, file_server: &mut FileServer, path: &Path) -> std::io::Result<FileId> {
+        file_server.register_file(path)
+    }
+}
+
+/// Resolves includes against a map of file names to contents, for tests and the browser
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFileProvider {
+    files: HashMap<PathBuf, String>,
+}
+
+// Resolves `.` and `..` without touching the file system, since the files may not exist on disk
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl MemoryFileProvider {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file or replaces its contents if it was added before
+    pub fn add_file(&mut self, path: impl AsRef<Path>, contents: impl Into<String>) {
+        self.files.insert(normalize(path.as_ref()), contents.into());
+    }
+
+    pub fn remove_file(&mut self, path: impl AsRef<Path>) -> bool {
+        self.files.remove(&normalize(path.as_ref())).is_some()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
+
+impl FileProvider for MemoryFileProvider {
+    fn register_file(&self, file_server: &mut FileServer, path: &Path) -> std::io::Result<FileId> {
+        let path = normalize(path);
+        let Some(contents) = self.files.get(&path) else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no file named `{}`", path.display()),
+            ));
+        };
+
+        // Files included twice are already registered
+        match file_server.register_file_memory(&path, contents.clone()) {
+            Ok(file) => Ok(file),
+            Err(()) => file_server.register_file(&path),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use super::ast::Statement;
+use super::lexer::*;
+use super::{emit_lexer_errors, parser, AssemblerError, SharedStr};
+use langbox::*;
+
+const INDENT: &str = "    ";
+const MNEMONIC_WIDTH: usize = 8;
+
+fn byte_offset(file_server: &FileServer, file: FileId, span: TextSpan) -> usize {
+    let text = file_server.get_file(file).unwrap().text();
+    (span.text(file_server).as_ptr() as usize) - (text.as_ptr() as usize)
+}
+
+// Literals keep their original spelling, so the radix and escape sequences are preserved
+fn token_text(token: &Token<Jam1Token>, file_server: &FileServer) -> String {
+    match &token.kind {
+        Jam1Token::Punctuation(kind) => kind.to_string(),
+        Jam1Token::Directive(kind) => kind.to_string(),
+        Jam1Token::Register(kind) => kind.to_string(),
+        Jam1Token::IoRegister(kind) => kind.to_string(),
+        Jam1Token::Mnemonic(kind) => kind.to_string(),
+        _ => token.span.text(file_server).to_owned(),
+    }
+}
+
+fn join_tokens(tokens: &[Token<Jam1Token>], file_server: &FileServer) -> String {
+    let mut output = String::new();
+    let mut expect_operand = true;
+    let mut prev_unary = false;
+
+    for (i, token) in tokens.iter().enumerate() {
+        let (space_before, unary) = match &token.kind {
+            Jam1Token::Punctuation(
+                PunctuationKind::Comma
+                | PunctuationKind::Colon
+                | PunctuationKind::ClosingParenthesis
+                | PunctuationKind::ClosingBracket,
+            ) => (false, false),
+            Jam1Token::Punctuation(
+                PunctuationKind::PlusSign
+                | PunctuationKind::MinusSign
+                | PunctuationKind::ExclamationMark,
+            ) if expect_operand => (true, true),
+            _ => (true, false),
+        };
+
+        let after_open = matches!(
+            i.checked_sub(1).map(|i| &tokens[i].kind),
+            Some(Jam1Token::Punctuation(
+                PunctuationKind::OpeningParenthesis | PunctuationKind::OpeningBracket
+            ))
+        );
+
+        if (i > 0) && space_before && !after_open && !prev_unary {
+            output.push(' ');
+        }
+        output.push_str(&token_text(token, file_server));
+
+        expect_operand = match &token.kind {
+            Jam1Token::Punctuation(
+                PunctuationKind::ClosingParenthesis
+                | PunctuationKind::ClosingBracket
+                | PunctuationKind::DollarSign,
+            ) => false,
+            Jam1Token::Punctuation(_) | Jam1Token::Mnemonic(_) | Jam1Token::Directive(_) => true,
+            _ => false,
+        };
+        prev_unary = unary;
+    }
+
+    output
+}
+
+fn format_statement(
+    tokens: &[Token<Jam1Token>],
+    file_server: &FileServer,
+    errors: &mut Vec<AssemblerError>,
+) -> String {
+    if !emit_lexer_errors(tokens, errors) {
+        return String::new();
+    }
+
+    match parser::parse(TokenStream::new(tokens)) {
+        Ok(Statement::Instruction(_)) => {
+            let mnemonic = token_text(&tokens[0], file_server);
+            let operands = join_tokens(&tokens[1..], file_server);
+
+            if operands.is_empty() {
+                format!("{INDENT}{mnemonic}")
+            } else {
+                format!("{INDENT}{mnemonic:<MNEMONIC_WIDTH$}{operands}")
+            }
+        }
+        Ok(_) => join_tokens(tokens, file_server),
+        Err(err) => {
+            errors.push(AssemblerError::ParseError(err));
+            String::new()
+        }
+    }
+}
+
+/// Re-emits assembly code with normalized spacing, lowercase keywords and aligned operands.
+/// Comments and blank lines are preserved, lines joined with `\` are merged into one.
+pub fn format_source(code: &str) -> Result<String, String> {
+    // Tabs between tokens disappear with the rest of the whitespace, the ones in literals and
+    // comments are kept
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("<code>", code.to_owned())
+        .unwrap();
+    let text = file_server.get_file(file).unwrap().text();
+
+    let mut errors = Vec::new();
+    let mut output = String::new();
+    let mut tokens = Vec::new();
+    let mut comment = None;
+
+    let emit_line = |tokens: &mut Vec<Token<Jam1Token>>,
+                     comment: &mut Option<(TextSpan, SharedStr)>,
+                     errors: &mut Vec<AssemblerError>| {
+        let mut line = if tokens.is_empty() {
+            String::new()
+        } else {
+            format_statement(tokens, &file_server, errors)
+        };
+
+        if let Some((span, comment)) = comment.take() {
+            if line.is_empty() {
+                // Comments on their own line stay indented if they were before
+                let start = byte_offset(&file_server, file, span);
+                if !text[..start].ends_with('\n') && (start > 0) {
+                    line.push_str(INDENT);
+                }
+            } else {
+                line.push(' ');
+            }
+
+            line.push_str(comment.trim_end());
+        }
+
+        tokens.clear();
+        line
+    };
+
+    let mut rest = None;
+    for token in Jam1Lexer::new(file, &file_server) {
+        match token.kind {
+            Jam1Token::NewLine => {
+                let end = matches!(
+                    tokens.first().map(|token: &Token<Jam1Token>| &token.kind),
+                    Some(Jam1Token::Directive(DirectiveKind::End))
+                );
+
+                output.push_str(&emit_line(&mut tokens, &mut comment, &mut errors));
+                output.push('\n');
+
+                // Anything after `.end` is not assembled, so it is kept as it is
+                if end {
+                    let newline_len = token.span.text(&file_server).len();
+                    rest = Some(byte_offset(&file_server, file, token.span) + newline_len);
+                    break;
+                }
+            }
+            Jam1Token::Comment(text) => comment = Some((token.span, text)),
+            Jam1Token::LineContinuation => {}
+            _ => tokens.push(token),
+        }
+    }
+
+    match rest {
+        Some(start) => output.push_str(&text[start..]),
+        None => output.push_str(&emit_line(&mut tokens, &mut comment, &mut errors)),
+    }
+
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        let mut message = String::new();
+
+        for (i, error) in errors.into_iter().enumerate() {
+            if i > 0 {
+                message.push('\n');
+            }
+
+            message.push_str(&error.format(&file_server));
+        }
+
+        Err(message)
+    }
+}
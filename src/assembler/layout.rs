@@ -0,0 +1,115 @@
+use super::{AssemblerError, SharedStr};
+use std::fmt::Write;
+
+/// Where the monitor ROM starts, programs have to end before it
+pub const MONITOR_START: u16 = 0xE000;
+/// Programs ending closer than this to the monitor get a warning
+const MONITOR_MARGIN: u32 = 0x400;
+
+/// Where a section ended up in the assembled program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionLayout {
+    name: SharedStr,
+    base: u16,
+    size: u16,
+    headroom: u32,
+}
+
+impl SectionLayout {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[inline]
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// The address right after the section
+    #[inline]
+    pub fn end(&self) -> u32 {
+        (self.base as u32) + (self.size as u32)
+    }
+
+    /// Free bytes between the end of the section and the next section or the monitor
+    #[inline]
+    pub fn headroom(&self) -> u32 {
+        self.headroom
+    }
+}
+
+/// Turns `(name, base, size)` of sections sorted by base address into their layouts
+pub(super) fn section_layouts(
+    sections: impl IntoIterator<Item = (SharedStr, u16, u16)>,
+) -> Vec<SectionLayout> {
+    let mut layouts: Vec<_> = sections
+        .into_iter()
+        .map(|(name, base, size)| SectionLayout {
+            name,
+            base,
+            size,
+            headroom: 0,
+        })
+        .collect();
+
+    for i in 0..layouts.len() {
+        let end = layouts[i].end();
+        let limit = match layouts.get(i + 1) {
+            Some(next) => next.base as u32,
+            None if end <= (MONITOR_START as u32) => MONITOR_START as u32,
+            None => 0x10000,
+        };
+        layouts[i].headroom = limit.saturating_sub(end);
+    }
+
+    layouts
+}
+
+/// Warns if the sections below the monitor end inside of it or close to it
+pub fn monitor_warning(layouts: &[SectionLayout]) -> Option<AssemblerError> {
+    let end = layouts
+        .iter()
+        .filter(|layout| layout.base < MONITOR_START)
+        .map(SectionLayout::end)
+        .max()?;
+
+    ((end + MONITOR_MARGIN) > (MONITOR_START as u32))
+        .then_some(AssemblerError::CloseToMonitor { end })
+}
+
+/// Formats a table of the base address, size and free space after every section
+pub fn format_section_report(layouts: &[SectionLayout]) -> String {
+    let name_width = layouts
+        .iter()
+        .map(|layout| layout.name.chars().count())
+        .chain(std::iter::once("section".len()))
+        .max()
+        .unwrap_or_default();
+
+    let mut output = String::new();
+    writeln!(
+        output,
+        "{:name_width$}  base    end     size   free",
+        "section"
+    )
+    .unwrap();
+    for layout in layouts {
+        writeln!(
+            output,
+            "{:name_width$}  0x{:0>4X}  0x{:0>4X}  {:>5}  {:>5}",
+            layout.name,
+            layout.base,
+            layout.end(),
+            layout.size,
+            layout.headroom,
+        )
+        .unwrap();
+    }
+    output
+}
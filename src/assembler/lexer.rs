@@ -1,9 +1,11 @@
 use super::SharedStr;
 use cow_utils::CowUtils;
 use langbox::*;
+use std::collections::HashMap;
 use std::fmt;
 use std::num::ParseIntError;
 use std::str::CharIndices;
+use std::sync::LazyLock;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PunctuationKind {
@@ -37,6 +39,20 @@ pub enum PunctuationKind {
     TrippleGreaterThanSign,
     /// `>>`
     DoubleGreaterThanSign,
+    /// `==`
+    DoubleEqualSign,
+    /// `!=`
+    ExclamationMarkEqualSign,
+    /// `<=`
+    LessThanEqualSign,
+    /// `<`
+    LessThanSign,
+    /// `>=`
+    GreaterThanEqualSign,
+    /// `>`
+    GreaterThanSign,
+    /// `$`
+    DollarSign,
     /// `(`
     OpeningParenthesis,
     /// `)`
@@ -65,6 +81,13 @@ impl fmt::Display for PunctuationKind {
             Self::DoubleLessThanSign => write!(f, "<<"),
             Self::TrippleGreaterThanSign => write!(f, ">>>"),
             Self::DoubleGreaterThanSign => write!(f, ">>"),
+            Self::DoubleEqualSign => write!(f, "=="),
+            Self::ExclamationMarkEqualSign => write!(f, "!="),
+            Self::LessThanEqualSign => write!(f, "<="),
+            Self::LessThanSign => write!(f, "<"),
+            Self::GreaterThanEqualSign => write!(f, ">="),
+            Self::GreaterThanSign => write!(f, ">"),
+            Self::DollarSign => write!(f, "$"),
             Self::OpeningParenthesis => write!(f, "("),
             Self::ClosingParenthesis => write!(f, ")"),
             Self::OpeningBracket => write!(f, "["),
@@ -73,27 +96,35 @@ impl fmt::Display for PunctuationKind {
     }
 }
 
+// Longer patterns have to come before their prefixes
 #[rustfmt::skip]
 const PUNCTUATION_MAP: &[(&str, PunctuationKind)] = &[
-    (","  , PunctuationKind::Comma                 ),
-    (":"  , PunctuationKind::Colon                 ),
-    ("="  , PunctuationKind::EqualSign             ),
-    ("+"  , PunctuationKind::PlusSign              ),
-    ("-"  , PunctuationKind::MinusSign             ),
-    ("*"  , PunctuationKind::Asterisk              ),
-    ("/"  , PunctuationKind::Slash                 ),
-    ("%"  , PunctuationKind::PercentSign           ),
-    ("!"  , PunctuationKind::ExclamationMark       ),
-    ("&"  , PunctuationKind::Ampersand             ),
-    ("|"  , PunctuationKind::VerticalBar           ),
-    ("^"  , PunctuationKind::Accent                ),
-    ("<<" , PunctuationKind::DoubleLessThanSign    ),
-    (">>>", PunctuationKind::TrippleGreaterThanSign),
-    (">>" , PunctuationKind::DoubleGreaterThanSign ),
-    ("("  , PunctuationKind::OpeningParenthesis    ),
-    (")"  , PunctuationKind::ClosingParenthesis    ),
-    ("["  , PunctuationKind::OpeningBracket        ),
-    ("]"  , PunctuationKind::ClosingBracket        ),
+    (","  , PunctuationKind::Comma                   ),
+    (":"  , PunctuationKind::Colon                   ),
+    ("==" , PunctuationKind::DoubleEqualSign         ),
+    ("="  , PunctuationKind::EqualSign               ),
+    ("+"  , PunctuationKind::PlusSign                ),
+    ("-"  , PunctuationKind::MinusSign               ),
+    ("*"  , PunctuationKind::Asterisk                ),
+    ("/"  , PunctuationKind::Slash                   ),
+    ("%"  , PunctuationKind::PercentSign             ),
+    ("!=" , PunctuationKind::ExclamationMarkEqualSign),
+    ("!"  , PunctuationKind::ExclamationMark         ),
+    ("&"  , PunctuationKind::Ampersand               ),
+    ("|"  , PunctuationKind::VerticalBar             ),
+    ("^"  , PunctuationKind::Accent                  ),
+    ("<<" , PunctuationKind::DoubleLessThanSign      ),
+    ("<=" , PunctuationKind::LessThanEqualSign       ),
+    ("<"  , PunctuationKind::LessThanSign            ),
+    (">>>", PunctuationKind::TrippleGreaterThanSign  ),
+    (">>" , PunctuationKind::DoubleGreaterThanSign   ),
+    (">=" , PunctuationKind::GreaterThanEqualSign    ),
+    (">"  , PunctuationKind::GreaterThanSign         ),
+    ("$"  , PunctuationKind::DollarSign              ),
+    ("("  , PunctuationKind::OpeningParenthesis      ),
+    (")"  , PunctuationKind::ClosingParenthesis      ),
+    ("["  , PunctuationKind::OpeningBracket          ),
+    ("]"  , PunctuationKind::ClosingBracket          ),
 ];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -103,6 +134,9 @@ pub enum DirectiveKind {
     Origin,
     Section,
     Include,
+    Entry,
+    Assert,
+    End,
 }
 
 impl fmt::Display for DirectiveKind {
@@ -113,6 +147,9 @@ impl fmt::Display for DirectiveKind {
             Self::Origin => write!(f, ".origin"),
             Self::Section => write!(f, ".section"),
             Self::Include => write!(f, ".include"),
+            Self::Entry => write!(f, ".entry"),
+            Self::Assert => write!(f, ".assert"),
+            Self::End => write!(f, ".end"),
         }
     }
 }
@@ -124,6 +161,9 @@ const DIRECTIVE_MAP: &[(&str, DirectiveKind)] = &[
     ("origin" , DirectiveKind::Origin ),
     ("section", DirectiveKind::Section),
     ("include", DirectiveKind::Include),
+    ("entry"  , DirectiveKind::Entry  ),
+    ("assert" , DirectiveKind::Assert ),
+    ("end"    , DirectiveKind::End    ),
 ];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -213,6 +253,34 @@ const IO_REGISTER_MAP: &[(&str, IoRegisterKind)] = &[
     ("gpio"      , IoRegisterKind::Gpio          ),
 ];
 
+impl IoRegisterKind {
+    // Which IO registers `in` can read and `out` can write, following the IO map of the hardware
+    #[rustfmt::skip]
+    fn access(self) -> (bool, bool) {
+        match self {
+            //                       readable  writable
+            Self::UartData       => (true    , true    ),
+            Self::UartControl    => (true    , false   ),
+            Self::AudioData      => (false   , true    ),
+            Self::ControllerData => (true    , false   ),
+            Self::VgaStatus      => (true    , false   ),
+            Self::Gpio           => (true    , true    ),
+        }
+    }
+
+    /// Whether the register can be the source of `in`
+    #[inline]
+    pub fn readable(self) -> bool {
+        self.access().0
+    }
+
+    /// Whether the register can be the destination of `out`
+    #[inline]
+    pub fn writable(self) -> bool {
+        self.access().1
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MnemonicKind {
     Nop,
@@ -228,6 +296,7 @@ pub enum MnemonicKind {
     Stosb,
     Call,
     Ret,
+    Reti,
     CallBd,
     RetBd,
     Jmp,
@@ -294,6 +363,7 @@ impl fmt::Display for MnemonicKind {
             Self::Stosb => write!(f, "stosb"),
             Self::Call => write!(f, "call"),
             Self::Ret => write!(f, "ret"),
+            Self::Reti => write!(f, "reti"),
             Self::CallBd => write!(f, "callbd"),
             Self::RetBd => write!(f, "retbd"),
             Self::Jmp => write!(f, "jmp"),
@@ -361,6 +431,7 @@ const MNEMONIC_MAP: &[(&str, MnemonicKind)] = &[
     ("stosb" , MnemonicKind::Stosb ),
     ("call"  , MnemonicKind::Call  ),
     ("ret"   , MnemonicKind::Ret   ),
+    ("reti"  , MnemonicKind::Reti  ),
     ("callbd", MnemonicKind::CallBd),
     ("retbd" , MnemonicKind::RetBd ),
     ("jmp"   , MnemonicKind::Jmp   ),
@@ -420,7 +491,8 @@ pub enum ParseStringError {
 #[derive(Debug, Clone)]
 pub enum Jam1Token {
     NewLine,
-    Comment,
+    LineContinuation,
+    Comment(SharedStr),
     Punctuation(PunctuationKind),
     Directive(DirectiveKind),
     Register(RegisterKind),
@@ -436,17 +508,35 @@ pub enum Jam1Token {
 }
 
 fn read_comment_token(text: &str) -> Option<ReadTokenResult<Jam1Token>> {
-    if let Some(text) = text.strip_prefix("//") {
-        let end = text.find('\n').unwrap_or(text.len());
-        Some(ReadTokenResult {
-            token: Jam1Token::Comment,
-            consumed_bytes: end + "//".len(),
-        })
-    } else if let Some(text) = text.strip_prefix(";") {
-        let end = text.find('\n').unwrap_or(text.len());
+    let prefix_len = if text.starts_with("//") {
+        "//".len()
+    } else if text.starts_with(';') {
+        ";".len()
+    } else {
+        return None;
+    };
+
+    // The comment text includes the `//` or `;` it was started with
+    let end = text[prefix_len..]
+        .find('\n')
+        .map_or(text.len(), |end| end + prefix_len);
+    Some(ReadTokenResult {
+        token: Jam1Token::Comment(text[..end].into()),
+        consumed_bytes: end,
+    })
+}
+
+// A backslash as the last character on a line joins it with the next line
+fn read_line_continuation_token(text: &str) -> Option<ReadTokenResult<Jam1Token>> {
+    let text = text.strip_prefix('\\')?;
+    let end = text
+        .find('\n')
+        .map_or(text.len(), |end| end + '\n'.len_utf8());
+
+    if text[..end].trim().is_empty() {
         Some(ReadTokenResult {
-            token: Jam1Token::Comment,
-            consumed_bytes: end + ";".len(),
+            token: Jam1Token::LineContinuation,
+            consumed_bytes: '\\'.len_utf8() + end,
         })
     } else {
         None
@@ -498,6 +588,29 @@ fn read_directive_token(text: &str) -> Option<ReadTokenResult<Jam1Token>> {
     }
 }
 
+#[derive(Clone, Copy)]
+enum Keyword {
+    Register(RegisterKind),
+    IoRegister(IoRegisterKind),
+    Mnemonic(MnemonicKind),
+}
+
+// Every identifier is looked up here, scanning the maps for each one is too slow for large files.
+// Later maps take precedence, so registers win over mnemonics like the scans used to.
+static KEYWORDS: LazyLock<HashMap<&'static str, Keyword>> = LazyLock::new(|| {
+    let mnemonics = MNEMONIC_MAP
+        .iter()
+        .map(|&(pattern, mnemonic)| (pattern, Keyword::Mnemonic(mnemonic)));
+    let io_registers = IO_REGISTER_MAP
+        .iter()
+        .map(|&(pattern, io_register)| (pattern, Keyword::IoRegister(io_register)));
+    let registers = REGISTER_MAP
+        .iter()
+        .map(|&(pattern, register)| (pattern, Keyword::Register(register)));
+
+    mnemonics.chain(io_registers).chain(registers).collect()
+});
+
 fn read_identifier_token(text: &str) -> Option<ReadTokenResult<Jam1Token>> {
     let mut chars = text.chars();
     let first_char = chars.next().expect("text was empty");
@@ -514,35 +627,15 @@ fn read_identifier_token(text: &str) -> Option<ReadTokenResult<Jam1Token>> {
         let identifier = &text[..consumed];
         let lowercase = identifier.cow_to_ascii_lowercase();
 
-        for &(pattern, register) in REGISTER_MAP {
-            if pattern.eq(lowercase.as_ref()) {
-                return Some(ReadTokenResult {
-                    token: Jam1Token::Register(register),
-                    consumed_bytes: consumed,
-                });
-            }
-        }
-
-        for &(pattern, io_register) in IO_REGISTER_MAP {
-            if pattern.eq(lowercase.as_ref()) {
-                return Some(ReadTokenResult {
-                    token: Jam1Token::IoRegister(io_register),
-                    consumed_bytes: consumed,
-                });
-            }
-        }
-
-        for &(pattern, mnemonic) in MNEMONIC_MAP {
-            if pattern.eq(lowercase.as_ref()) {
-                return Some(ReadTokenResult {
-                    token: Jam1Token::Mnemonic(mnemonic),
-                    consumed_bytes: consumed,
-                });
-            }
-        }
+        let token = match KEYWORDS.get(lowercase.as_ref()) {
+            Some(&Keyword::Register(register)) => Jam1Token::Register(register),
+            Some(&Keyword::IoRegister(io_register)) => Jam1Token::IoRegister(io_register),
+            Some(&Keyword::Mnemonic(mnemonic)) => Jam1Token::Mnemonic(mnemonic),
+            None => Jam1Token::Identifier(identifier.into()),
+        };
 
         Some(ReadTokenResult {
-            token: Jam1Token::Identifier(identifier.into()),
+            token,
             consumed_bytes: consumed,
         })
     } else {
@@ -624,12 +717,15 @@ fn process_escape_sequence(
             let raw_val = &buffer[..buffer_len];
             let raw_val = unsafe { std::str::from_utf8_unchecked(raw_val) };
 
-            match u8::from_str_radix(raw_val, 16) {
-                Ok(val) => {
-                    let char = char::from_u32(val as u32).expect("invalid char code");
-                    literal.push(char);
-                }
-                Err(_) => {
+            // `from_str_radix` also accepts a leading sign
+            let char = u8::from_str_radix(raw_val, 16)
+                .ok()
+                .filter(|_| raw_val.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|val| char::from_u32(val as u32));
+
+            match char {
+                Some(char) => literal.push(char),
+                None => {
                     let range = (index - '\\'.len_utf8())..(index + 'x'.len_utf8() + buffer_len);
                     return Err(ParseStringError::InvalidEscapeSequence(range));
                 }
@@ -650,12 +746,15 @@ fn process_escape_sequence(
             let raw_val = &buffer[..buffer_len];
             let raw_val = unsafe { std::str::from_utf8_unchecked(raw_val) };
 
-            match u16::from_str_radix(raw_val, 16) {
-                Ok(val) => {
-                    let char = char::from_u32(val as u32).expect("invalid char code");
-                    literal.push(char);
-                }
-                Err(_) => {
+            // `from_str_radix` also accepts a leading sign, and surrogates are not valid chars
+            let char = u16::from_str_radix(raw_val, 16)
+                .ok()
+                .filter(|_| raw_val.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|val| char::from_u32(val as u32));
+
+            match char {
+                Some(char) => literal.push(char),
+                None => {
                     let range = (index - '\\'.len_utf8())..(index + 'u'.len_utf8() + buffer_len);
                     return Err(ParseStringError::InvalidEscapeSequence(range));
                 }
@@ -733,6 +832,106 @@ fn read_string_literal_token(text: &str) -> Option<ReadTokenResult<Jam1Token>> {
     }
 }
 
+pub fn parse_string_literal(text: &str) -> Option<SharedStr> {
+    match read_string_literal_token(text)? {
+        ReadTokenResult {
+            token: Jam1Token::StringLiteral(literal),
+            consumed_bytes,
+        } if consumed_bytes == text.len() => Some(literal),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordKind {
+    Directive,
+    Register,
+    IoRegister,
+    Mnemonic,
+}
+
+pub fn keywords() -> impl Iterator<Item = (String, KeywordKind)> {
+    let directives = DIRECTIVE_MAP
+        .iter()
+        .map(|&(pattern, _)| (format!(".{pattern}"), KeywordKind::Directive));
+    let registers = REGISTER_MAP
+        .iter()
+        .map(|&(pattern, _)| (pattern.to_owned(), KeywordKind::Register));
+    let io_registers = IO_REGISTER_MAP
+        .iter()
+        .map(|&(pattern, _)| (pattern.to_owned(), KeywordKind::IoRegister));
+    let mnemonics = MNEMONIC_MAP
+        .iter()
+        .map(|&(pattern, _)| (pattern.to_owned(), KeywordKind::Mnemonic));
+
+    directives
+        .chain(registers)
+        .chain(io_registers)
+        .chain(mnemonics)
+}
+
+// Optimal string alignment distance, so swapping two adjacent characters counts as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // `rows[i][j]` is the distance between the first `i` chars of `a` and the first `j` of `b`
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in rows[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+
+            if (i > 1) && (j > 1) && (a[i - 1] == b[j - 2]) && (a[i - 2] == b[j - 1]) {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+// The pattern closest to `word`, if it is close enough to likely be a typo of it
+fn closest_pattern<T>(word: &str, map: &[(&'static str, T)]) -> Option<&'static str> {
+    // Very short words are within one edit of too many patterns for a suggestion to be useful
+    let len = word.chars().count();
+    if len < 3 {
+        return None;
+    }
+
+    let word = word.cow_to_ascii_lowercase();
+    let max_distance = (len / 3).max(1);
+    map.iter()
+        .map(|&(pattern, _)| (edit_distance(&word, pattern), pattern))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, pattern)| pattern)
+}
+
+/// The directive a misspelled one most likely meant, without the leading `.`
+pub(super) fn suggest_directive(name: &str) -> Option<&'static str> {
+    closest_pattern(name, DIRECTIVE_MAP)
+}
+
+pub(super) fn suggest_mnemonic(name: &str) -> Option<&'static str> {
+    closest_pattern(name, MNEMONIC_MAP)
+}
+
+pub(super) fn suggest_register(name: &str) -> Option<&'static str> {
+    closest_pattern(name, REGISTER_MAP)
+}
+
 pub struct Jam1TokenReader;
 impl TokenReader for Jam1TokenReader {
     type Token = Jam1Token;
@@ -745,6 +944,10 @@ impl TokenReader for Jam1TokenReader {
             };
         }
 
+        if let Some(result) = read_line_continuation_token(text) {
+            return result;
+        }
+
         if let Some(result) = read_comment_token(text) {
             return result;
         }
@@ -1,5 +1,11 @@
 mod ast;
+mod diagnostic;
+mod docs;
 mod eval;
+mod export;
+mod files;
+mod formatter;
+mod layout;
 mod lexer;
 mod parser;
 
@@ -11,14 +17,27 @@ use lexer::*;
 use std::collections::HashMap;
 use std::num::ParseIntError;
 use std::ops::Range;
+use std::path::PathBuf;
 use std::rc::Rc;
 
+pub use diagnostic::*;
+pub use docs::{mnemonic_at, mnemonic_doc, MnemonicDoc, OperandForm};
+pub use export::{
+    apply_checksum, c_identifier, to_c_array, to_monitor_loader_stream, to_rust_module,
+    ChecksumKind,
+};
+pub use files::{FileProvider, FsFileProvider, MemoryFileProvider};
+pub use formatter::format_source;
+pub use layout::{format_section_report, monitor_warning, SectionLayout, MONITOR_START};
+pub use lexer::{keywords, parse_string_literal, KeywordKind};
+
 type SharedStr = Rc<str>;
 
 #[derive(Debug)]
 pub enum AssemblerError {
     InvalidDirective {
         directive: TextSpan,
+        suggestion: Option<&'static str>,
     },
     InvalidIntegerLiteral {
         literal: TextSpan,
@@ -52,6 +71,12 @@ pub enum AssemblerError {
     InvalidOriginDirective {
         directive: TextSpan,
     },
+    UnresolvedSectionBase {
+        ident: TextSpan,
+    },
+    InvalidEntryDirective {
+        directive: TextSpan,
+    },
     UndefinedSection {
         statement: TextSpan,
     },
@@ -62,8 +87,19 @@ pub enum AssemblerError {
     DivideByZero {
         expr: TextSpan,
     },
+    ExpressionOverflow {
+        expr: TextSpan,
+    },
+    NoCurrentAddress {
+        expr: TextSpan,
+    },
+    AssertionFailed {
+        directive: TextSpan,
+        message: Option<SharedStr>,
+    },
     UndefinedSymbol {
         ident: TextSpan,
+        suggestion: Option<&'static str>,
     },
     CyclicExpression {
         expr: TextSpan,
@@ -75,265 +111,518 @@ pub enum AssemblerError {
     IncludeUnsupported {
         directive: TextSpan,
     },
+    FileError {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    UnusedSymbol {
+        symbol: TextSpan,
+    },
+    CloseToMonitor {
+        end: u32,
+    },
     ParseError(parser::ParseError),
 }
 
+// Escape codes for the formatted errors, which are all empty if colors are disabled
+struct Palette {
+    bold: &'static str,
+    regular: &'static str,
+    red: &'static str,
+    yellow: &'static str,
+    blue: &'static str,
+    cyan: &'static str,
+    white: &'static str,
+}
+
+impl Palette {
+    const COLORED: Self = Self {
+        bold: "\x1B\x5B1m",
+        regular: "\x1B\x5B22m",
+        red: "\x1B\x5B31m",
+        yellow: "\x1B\x5B33m",
+        blue: "\x1B\x5B34m",
+        cyan: "\x1B\x5B36m",
+        white: "\x1B\x5B39m",
+    };
+
+    const UNCOLORED: Self = Self {
+        bold: "",
+        regular: "",
+        red: "",
+        yellow: "",
+        blue: "",
+        cyan: "",
+        white: "",
+    };
+}
+
+/// How many columns a tab advances to when errors are formatted, unless specified otherwise
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+// Replaces tabs with spaces up to the next multiple of `tab_width`
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let width = tab_width - (column % tab_width);
+            expanded.extend(std::iter::repeat_n(' ', width));
+            column += width;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+// The column a character is displayed at once tabs are expanded
+fn display_column(line: &str, column: usize, tab_width: usize) -> usize {
+    let prefix: String = line.chars().take(column).collect();
+    // Spans can end after the last character of the line
+    expand_tabs(&prefix, tab_width).chars().count() + column.saturating_sub(line.chars().count())
+}
+
 fn format_code_hint<W: std::fmt::Write>(
     mut writer: W,
     file_server: &FileServer,
     span: TextSpan,
+    palette: &Palette,
     hint_color: &str,
     hint_range: Option<Range<usize>>,
+    tab_width: usize,
 ) {
-    const BOLD: &str = "\x1B\x5B1m";
-    const REGULAR: &str = "\x1B\x5B22m";
-    const CYAN: &str = "\x1B\x5B36m";
-    const WHITE: &str = "\x1B\x5B39m";
+    let &Palette {
+        bold,
+        regular,
+        cyan,
+        white,
+        ..
+    } = palette;
 
     let (start_line, start_column) = span.start_pos().line_column(file_server);
     let (end_line, end_column) = span.end_pos().line_column(file_server);
 
     let file = file_server.get_file(span.file_id()).unwrap();
-    let line = file.text().lines().nth(start_line as usize).unwrap();
-    let line_number = format!("{}", start_line + 1);
+    // Spans at the very end of the file start on a line `lines` does not yield
+    let line_text = |line: u32| file.text().lines().nth(line as usize).unwrap_or("");
 
-    let (start_column, end_column) = if end_line == start_line {
-        assert!(end_column >= start_column);
-        (start_column as usize, end_column as usize)
+    // The character range to underline in every line of the span
+    let underlines: Vec<(u32, Range<usize>)> = if (end_line == start_line) || hint_range.is_some() {
+        let line = line_text(start_line);
+        let (start_column, end_column) = if end_line == start_line {
+            assert!(end_column >= start_column);
+            (start_column as usize, end_column as usize)
+        } else {
+            (start_column as usize, line.chars().count())
+        };
+
+        let hint_range = hint_range.unwrap_or_else(|| 0..(end_column - start_column));
+        vec![(
+            start_line,
+            (start_column + hint_range.start)..(start_column + hint_range.end),
+        )]
     } else {
-        (start_column as usize, line.chars().count())
+        // A span ending right after a line break doesn't cover anything of the line it ends on
+        let last_line = if end_column == 0 {
+            end_line - 1
+        } else {
+            end_line
+        };
+
+        (start_line..=last_line)
+            .map(|line| {
+                // Lines after the first are underlined from their indentation on
+                let start = if line == start_line {
+                    start_column as usize
+                } else {
+                    line_text(line)
+                        .chars()
+                        .take_while(|c| c.is_whitespace())
+                        .count()
+                };
+                let end = if line == end_line {
+                    end_column as usize
+                } else {
+                    line_text(line).chars().count()
+                };
+                (line, start.min(end)..end)
+            })
+            .collect()
     };
 
-    let hint_range = hint_range.unwrap_or_else(|| 0..(end_column - start_column));
+    let last_line = underlines.last().map_or(start_line, |&(line, _)| line);
+    let number_width = format!("{}", last_line + 1).len();
 
     write!(
         writer,
-        "{BOLD}{CYAN}{:width$} |{WHITE}\r\n",
-        "",
-        width = line_number.len()
-    )
-    .unwrap();
-    write!(writer, "{CYAN}{line_number} |{WHITE}{REGULAR}  {line}\r\n").unwrap();
-    write!(
-        writer,
-        "{BOLD}{CYAN}{:width$} |{WHITE}  ",
-        "",
-        width = line_number.len()
-    )
-    .unwrap();
-    write!(
-        writer,
-        "{:width$}",
+        "{bold}{cyan}{:width$} |{white}\r\n",
         "",
-        width = start_column + hint_range.start
-    )
-    .unwrap();
-    write!(
-        writer,
-        "{hint_color}{:^>width$}{WHITE}\r\n",
-        "",
-        width = (hint_range.end - hint_range.start).max(1)
+        width = number_width
     )
     .unwrap();
+
+    for (line, range) in &underlines {
+        let text = line_text(*line);
+
+        // The line is printed with tabs expanded, so the hint is placed by display columns
+        let hint_start = display_column(text, range.start, tab_width);
+        let hint_end = display_column(text, range.end, tab_width);
+        let text = expand_tabs(text, tab_width);
+
+        write!(
+            writer,
+            "{cyan}{:>width$} |{white}{regular}  {text}\r\n",
+            line + 1,
+            width = number_width
+        )
+        .unwrap();
+
+        // Lines of a longer span that have nothing to underline, e.g. empty ones, are left bare
+        if (underlines.len() > 1) && (hint_end == hint_start) {
+            continue;
+        }
+
+        write!(
+            writer,
+            "{bold}{cyan}{:width$} |{white}  ",
+            "",
+            width = number_width
+        )
+        .unwrap();
+        write!(writer, "{:width$}", "", width = hint_start).unwrap();
+        write!(
+            writer,
+            "{hint_color}{:^>width$}{white}\r\n",
+            "",
+            width = (hint_end - hint_start).max(1)
+        )
+        .unwrap();
+    }
+
     write!(
         writer,
-        "{CYAN}{:width$} |{WHITE}{REGULAR}\r\n",
+        "{cyan}{:width$} |{white}{regular}\r\n",
         "",
-        width = line_number.len()
+        width = number_width
     )
     .unwrap();
 }
 
-impl AssemblerError {
-    pub fn format(&self, file_server: &FileServer) -> String {
-        use std::fmt::Write;
+struct ErrorInfo {
+    message: String,
+    note: Option<String>,
+    span: Option<(TextSpan, Option<Range<usize>>)>,
+    previous: Option<TextSpan>,
+}
 
-        const BOLD: &str = "\x1B\x5B1m";
-        const REGULAR: &str = "\x1B\x5B22m";
-        const RED: &str = "\x1B\x5B31m";
-        const BLUE: &str = "\x1B\x5B34m";
-        const WHITE: &str = "\x1B\x5B39m";
+impl ErrorInfo {
+    #[inline]
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            note: None,
+            span: None,
+            previous: None,
+        }
+    }
 
-        let mut output = String::new();
+    #[inline]
+    fn with_span(mut self, span: TextSpan) -> Self {
+        self.span = Some((span, None));
+        self
+    }
+}
+
+fn format_location(file_server: &FileServer, span: TextSpan, column_offset: usize) -> String {
+    let file = file_server.get_file(span.file_id()).unwrap();
+    let (line, column) = span.start_pos().line_column(file_server);
 
+    format!(
+        "{}:{}:{}",
+        file.path().display(),
+        line + 1,
+        (column as usize) + column_offset + 1
+    )
+}
+
+impl AssemblerError {
+    // Suggests a register if the symbol looks like a misspelled one
+    fn undefined_symbol(ident: &Identifier) -> Self {
+        Self::UndefinedSymbol {
+            ident: ident.span(),
+            suggestion: suggest_register(&ident.name()),
+        }
+    }
+
+    fn info(&self) -> ErrorInfo {
         match self {
-            &Self::InvalidDirective { directive } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: unknown directive{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, directive, RED, None);
-            }
+            &Self::InvalidDirective {
+                directive,
+                suggestion,
+            } => ErrorInfo {
+                note: suggestion.map(|suggestion| format!("did you mean `.{suggestion}`?")),
+                ..ErrorInfo::new("unknown directive").with_span(directive)
+            },
             &Self::InvalidIntegerLiteral { literal, .. } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: literal contains invalid characters{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, literal, RED, None);
+                ErrorInfo::new("literal contains invalid characters").with_span(literal)
             }
             &Self::UnclosedStringLiteral { literal } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: literal is missing closing quotes{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, literal, RED, None);
-            }
-            Self::InvalidEscapeSequence { literal, range } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: unknown escape sequence{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, *literal, RED, Some(range.clone()));
+                ErrorInfo::new("literal is missing closing quotes").with_span(literal)
             }
+            Self::InvalidEscapeSequence { literal, range } => ErrorInfo {
+                span: Some((*literal, Some(range.clone()))),
+                ..ErrorInfo::new("unknown escape sequence")
+            },
             &Self::InvalidChars { span } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: invalid characters in input{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, span, RED, None);
-            }
-            &Self::DuplicateSectionBase { value, previous } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: section base address is defined twice{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, value, RED, None);
-                write!(output, "Previous definition:\r\n").unwrap();
-                format_code_hint(&mut output, file_server, previous, BLUE, None);
+                ErrorInfo::new("invalid characters in input").with_span(span)
             }
+            &Self::DuplicateSectionBase { value, previous } => ErrorInfo {
+                previous: Some(previous),
+                ..ErrorInfo::new("section base address is defined twice").with_span(value)
+            },
             &Self::DuplicateLabel {
                 previous,
                 duplicate,
-            } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: symbol is defined twice{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, duplicate, RED, None);
-                write!(output, "Previous definition:\r\n").unwrap();
-                format_code_hint(&mut output, file_server, previous, BLUE, None);
-            }
+            } => ErrorInfo {
+                previous: Some(previous),
+                ..ErrorInfo::new("symbol is defined twice").with_span(duplicate)
+            },
             Self::SectionTooLarge { section } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: section `{section}` is too large{REGULAR}\r\n"
-                )
-                .unwrap();
+                ErrorInfo::new(format!("section `{section}` is too large"))
             }
             &Self::InvalidValue { value, .. } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: value is not valid for this directive{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, value, RED, None);
+                ErrorInfo::new("value is not valid for this directive").with_span(value)
             }
             &Self::InvalidOriginDirective { directive } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: origin has already been defined{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, directive, RED, None);
+                ErrorInfo::new("origin has already been defined").with_span(directive)
+            }
+            &Self::UnresolvedSectionBase { ident } => ErrorInfo::new(
+                "section base can only reference constants defined before the section",
+            )
+            .with_span(ident),
+            &Self::InvalidEntryDirective { directive } => {
+                ErrorInfo::new("entry point has already been defined").with_span(directive)
             }
             &Self::UndefinedSection { statement } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: statement is only valid inside a section{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, statement, RED, None);
+                ErrorInfo::new("statement is only valid inside a section").with_span(statement)
             }
             Self::OverlappingSections { first, second } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: sections `{first}` and `{second}` are overlapping{REGULAR}\r\n"
-                )
-                .unwrap();
+                ErrorInfo::new(format!("sections `{first}` and `{second}` are overlapping"))
             }
             &Self::DivideByZero { expr } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: divide by zero error while evaluating expression{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, expr, RED, None);
-            }
-            &Self::UndefinedSymbol { ident } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: symbol is not defined{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, ident, RED, None);
+                ErrorInfo::new("divide by zero error while evaluating expression").with_span(expr)
+            }
+            &Self::ExpressionOverflow { expr } => {
+                ErrorInfo::new("expression overflows a 64 bit integer").with_span(expr)
+            }
+            &Self::NoCurrentAddress { expr } => {
+                ErrorInfo::new("`$` can only be used in instructions and `.assert`").with_span(expr)
             }
+            Self::AssertionFailed { directive, message } => {
+                let message = match message {
+                    Some(message) => format!("assertion failed: {message}"),
+                    None => "assertion failed".to_owned(),
+                };
+                ErrorInfo::new(message).with_span(*directive)
+            }
+            &Self::UndefinedSymbol { ident, suggestion } => ErrorInfo {
+                note: suggestion
+                    .map(|suggestion| format!("did you mean the register `{suggestion}`?")),
+                ..ErrorInfo::new("symbol is not defined").with_span(ident)
+            },
             &Self::CyclicExpression { expr } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: expression cannot be evaluated due to cyclic dependencies{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, expr, RED, None);
-            }
-            Self::IncludeError { directive, error } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: failed to include file{REGULAR}\r\n"
-                )
-                .unwrap();
-                write!(output, "{error}\r\n").unwrap();
-                format_code_hint(&mut output, file_server, *directive, RED, None);
+                ErrorInfo::new("expression cannot be evaluated due to cyclic dependencies")
+                    .with_span(expr)
             }
+            Self::IncludeError { directive, error } => ErrorInfo {
+                note: Some(error.to_string()),
+                ..ErrorInfo::new("failed to include file").with_span(*directive)
+            },
             &Self::IncludeUnsupported { directive } => {
-                write!(
-                    output,
-                    "{BOLD}{RED}Error{WHITE}: including files is not supported in this environment{REGULAR}\r\n"
-                )
-                .unwrap();
-                format_code_hint(&mut output, file_server, directive, RED, None);
+                ErrorInfo::new("including files is not supported in this environment")
+                    .with_span(directive)
+            }
+            Self::FileError { path, error } => ErrorInfo {
+                note: Some(error.to_string()),
+                ..ErrorInfo::new(format!("failed to read file `{}`", path.display()))
+            },
+            &Self::UnusedSymbol { symbol } => {
+                ErrorInfo::new("symbol is never used").with_span(symbol)
+            }
+            &Self::CloseToMonitor { end } => {
+                let monitor_start = MONITOR_START as u32;
+                if end > monitor_start {
+                    ErrorInfo::new(format!(
+                        "program overlaps the monitor at 0x{monitor_start:0>4X} by {} bytes",
+                        end - monitor_start
+                    ))
+                } else {
+                    ErrorInfo::new(format!(
+                        "program ends only {} bytes before the monitor at 0x{monitor_start:0>4X}",
+                        monitor_start - end
+                    ))
+                }
             }
             Self::ParseError(err) => match err {
                 &parser::ParseError::UnexpectedToken { token, expected } => {
-                    write!(output, "{BOLD}{RED}Error{WHITE}: expected {expected}\r\n").unwrap();
-                    format_code_hint(&mut output, file_server, token, RED, None);
+                    ErrorInfo::new(format!("expected {expected}")).with_span(token)
                 }
                 parser::ParseError::InvalidOperands { op1, op2 } => {
-                    write!(
-                        output,
-                        "{BOLD}{RED}Error{WHITE}: instruction does not support this combination of operands{REGULAR}\r\n"
-                    )
-                    .unwrap();
-                    format_code_hint(&mut output, file_server, op1.join(op2), RED, None);
+                    ErrorInfo::new("instruction does not support this combination of operands")
+                        .with_span(op1.join(op2))
                 }
                 &parser::ParseError::InvalidRegister { register } => {
-                    write!(
-                        output,
-                        "{BOLD}{RED}Error{WHITE}: register is not supported by this instruction{REGULAR}\r\n"
-                    )
-                    .unwrap();
-                    format_code_hint(&mut output, file_server, register, RED, None);
+                    ErrorInfo::new("register is not supported by this instruction")
+                        .with_span(register)
                 }
                 &parser::ParseError::TokensRemaining { span } => {
-                    write!(output, "{BOLD}{RED}Error{WHITE}: unexpected tokens after complete statement{REGULAR}\r\n").unwrap();
-                    format_code_hint(&mut output, file_server, span, RED, None);
+                    ErrorInfo::new("unexpected tokens after complete statement").with_span(span)
                 }
                 &parser::ParseError::NoMatch { span } => {
-                    write!(
-                        output,
-                        "{BOLD}{RED}Error{WHITE}: unknown statement{REGULAR}\r\n"
-                    )
-                    .unwrap();
-                    format_code_hint(&mut output, file_server, span, RED, None);
+                    ErrorInfo::new("unknown statement").with_span(span)
                 }
+                &parser::ParseError::UnknownMnemonic {
+                    mnemonic,
+                    suggestion,
+                } => ErrorInfo {
+                    note: Some(format!("did you mean `{suggestion}`?")),
+                    ..ErrorInfo::new("unknown mnemonic").with_span(mnemonic)
+                },
             },
         }
+    }
+
+    /// Warnings are only reported, they don't prevent a program from being assembled
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnusedSymbol { .. } | Self::CloseToMonitor { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    #[inline]
+    pub fn format(&self, file_server: &FileServer) -> String {
+        self.format_with_tab_width(file_server, DEFAULT_TAB_WIDTH)
+    }
+
+    /// Like `format`, but tabs in the code hints advance to the next multiple of `tab_width`
+    #[inline]
+    pub fn format_with_tab_width(&self, file_server: &FileServer, tab_width: usize) -> String {
+        self.format_styled(file_server, tab_width, true)
+    }
+
+    /// Like `format_with_tab_width`, but without any escape codes if `use_color` is not set,
+    /// e.g. for output that is redirected to a file
+    pub fn format_styled(
+        &self,
+        file_server: &FileServer,
+        tab_width: usize,
+        use_color: bool,
+    ) -> String {
+        use std::fmt::Write;
+
+        let palette = if use_color {
+            &Palette::COLORED
+        } else {
+            &Palette::UNCOLORED
+        };
+        let &Palette {
+            bold,
+            regular,
+            red,
+            yellow,
+            blue,
+            white,
+            ..
+        } = palette;
+
+        let (kind, color) = match self.severity() {
+            Severity::Error => ("Error", red),
+            Severity::Warning => ("Warning", yellow),
+        };
+
+        let info = self.info();
+        let mut output = String::new();
+
+        write!(
+            output,
+            "{bold}{color}{kind}{white}: {}{regular}\r\n",
+            info.message
+        )
+        .unwrap();
+
+        if let Some(note) = &info.note {
+            write!(output, "{note}\r\n").unwrap();
+        }
+
+        if let Some((span, hint_range)) = info.span {
+            format_code_hint(
+                &mut output,
+                file_server,
+                span,
+                palette,
+                color,
+                hint_range,
+                tab_width,
+            );
+        }
+
+        if let Some(previous) = info.previous {
+            write!(output, "Previous definition:\r\n").unwrap();
+            format_code_hint(
+                &mut output,
+                file_server,
+                previous,
+                palette,
+                blue,
+                None,
+                tab_width,
+            );
+        }
+
+        output
+    }
+
+    /// Formats the error as `file:line:col: error: message` lines without any color codes,
+    /// warnings use `warning` instead of `error`
+    pub fn format_plain(&self, file_server: &FileServer) -> String {
+        use std::fmt::Write;
+
+        let info = self.info();
+        let mut output = String::new();
+
+        if let Some((span, hint_range)) = &info.span {
+            let column_offset = hint_range.as_ref().map_or(0, |range| range.start);
+            write!(
+                output,
+                "{}: ",
+                format_location(file_server, *span, column_offset)
+            )
+            .unwrap();
+        }
+
+        let kind = match self.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        write!(output, "{kind}: {}", info.message).unwrap();
+        if let Some(note) = &info.note {
+            write!(output, ": {note}").unwrap();
+        }
+        output.push('\n');
+
+        if let Some(previous) = info.previous {
+            writeln!(
+                output,
+                "{}: note: previous definition",
+                format_location(file_server, previous, 0)
+            )
+            .unwrap();
+        }
 
         output
     }
@@ -345,9 +634,10 @@ fn emit_lexer_errors(tokens: &[Token<Jam1Token>], errors: &mut Vec<AssemblerErro
 
     while let Some(token) = tokens.next() {
         match &token.kind {
-            Jam1Token::InvalidDirective(_) => {
+            Jam1Token::InvalidDirective(name) => {
                 errors.push(AssemblerError::InvalidDirective {
                     directive: token.span,
+                    suggestion: suggest_directive(name),
                 });
                 can_parse = false;
             }
@@ -414,28 +704,85 @@ impl Default for RawSection {
     }
 }
 
+fn evaluate_section_base(
+    directive: &SectionDirective,
+    base: &Expression,
+    label_set: &HashMap<SharedStr, TextSpan>,
+    constants: &HashMap<SharedStr, Option<i64>>,
+    errors: &mut Vec<AssemblerError>,
+) -> Option<(u16, TextSpan)> {
+    match base.try_eval(label_set, constants) {
+        Ok(value) => match u16::try_from(value) {
+            Ok(value) => Some((value, base.span())),
+            Err(_) => {
+                errors.push(AssemblerError::InvalidValue {
+                    value: base.span(),
+                    directive: directive.span(),
+                });
+                None
+            }
+        },
+        Err(EvalError::InvalidLiteralValue(_)) | Err(EvalError::ErrorInReferenceEval) => None,
+        Err(EvalError::DivideByZero(expr)) => {
+            errors.push(AssemblerError::DivideByZero { expr: expr.span() });
+            None
+        }
+        Err(EvalError::Overflow(expr)) => {
+            errors.push(AssemblerError::ExpressionOverflow { expr });
+            None
+        }
+        Err(EvalError::NoCurrentAddress(expr)) => {
+            errors.push(AssemblerError::NoCurrentAddress { expr });
+            None
+        }
+        Err(EvalError::MissingReferenceValue) => {
+            errors.push(AssemblerError::UnresolvedSectionBase { ident: base.span() });
+            None
+        }
+        Err(EvalError::UndefinedSymbol(ident)) => {
+            errors.push(AssemblerError::UnresolvedSectionBase {
+                ident: ident.span(),
+            });
+            None
+        }
+    }
+}
+
+// How a program is laid out:
+// - `.origin` may only appear once and only before the first `.section`. It is where the
+//   first section without an explicit base is placed, 0 if there is no origin.
+// - Sections without a base follow each other in the order they first appear, starting at the
+//   origin. Sections with a base are placed there and don't move the ones without.
+// - `.end` ends the input as a whole, nothing after it is assembled, including the rest of the
+//   files that included the one it is in. `.end label` also sets the entry point like `.entry`.
 fn process_file(
     file_server: &mut FileServer,
     file: FileId,
     errors: &mut Vec<AssemblerError>,
     sections: &mut IndexMap<SharedStr, RawSection>,
     label_set: &mut HashMap<SharedStr, TextSpan>,
+    constants: &mut HashMap<SharedStr, Option<i64>>,
     current_section: &mut Option<SharedStr>,
     default_base: &mut Option<u16>,
-    allow_include: bool,
+    entry: &mut Option<EntryDirective>,
+    ended: &mut bool,
+    references: &mut HashMap<SharedStr, Vec<TextSpan>>,
+    provider: Option<&dyn FileProvider>,
 ) {
     let mut statements = Vec::new();
 
-    // Tokenize and parse
+    // Tokenize and parse, anything after `.end` is not even tokenized
     let mut lexer = Jam1Lexer::new(file, &file_server);
     let mut tokens = Vec::new();
     while let Some(token) = lexer.next() {
         match &token.kind {
             Jam1Token::NewLine => {
                 if !tokens.is_empty() {
+                    let mut end = false;
                     if emit_lexer_errors(&tokens, errors) {
                         match parser::parse(TokenStream::new(&tokens)) {
                             Ok(statement) => {
+                                end = matches!(statement, Statement::EndDirective(_));
                                 statements.push(statement);
                             }
                             Err(err) => {
@@ -445,9 +792,12 @@ fn process_file(
                     }
 
                     tokens.clear();
+                    if end {
+                        break;
+                    }
                 }
             }
-            Jam1Token::Comment => {}
+            Jam1Token::Comment(_) | Jam1Token::LineContinuation => {}
             _ => {
                 tokens.push(token);
             }
@@ -467,30 +817,46 @@ fn process_file(
         }
     }
 
+    for statement in &statements {
+        statement.visit_identifiers(&mut |ident| {
+            references
+                .entry(ident.name())
+                .or_default()
+                .push(ident.span());
+        });
+    }
+
     // Place statements into sections
     for statement in statements {
         match &statement {
             Statement::SectionDirective(directive) => {
                 let current_section = current_section.insert(directive.name().value());
-                *default_base = Some(0);
+                // Keeps the origin if there is one, but rejects any that comes after this
+                default_base.get_or_insert(0);
 
-                if let Some(base) = directive.base().and_then(|base| base.value()) {
+                // Section bases are resolved right away, so they can only
+                // reference constants that were defined before the section
+                let base = directive.base().and_then(|base| {
+                    evaluate_section_base(directive, base, label_set, constants, errors)
+                });
+
+                if let Some((base, span)) = base {
                     let section = sections
                         .entry(SharedStr::clone(current_section))
                         .or_default();
 
                     if let Some((_, previous)) = section.base {
                         errors.push(AssemblerError::DuplicateSectionBase {
-                            value: directive.base().unwrap().span(),
+                            value: span,
                             previous,
                         });
                     } else {
-                        section.base = Some((base as u16, directive.base().unwrap().span()));
+                        section.base = Some((base, span));
                     }
                 }
             }
             Statement::IncludeDirective(directive) => {
-                if allow_include {
+                if let Some(provider) = provider {
                     let rel_path = directive.path().value();
                     let file_path = file_server.get_file(file).unwrap().path();
                     let include_path = file_path
@@ -498,7 +864,7 @@ fn process_file(
                         .map(|parent| parent.join(rel_path.as_ref()))
                         .unwrap_or(rel_path.as_ref().into());
 
-                    match file_server.register_file(&include_path) {
+                    match provider.register_file(file_server, &include_path) {
                         Ok(include_file) => {
                             process_file(
                                 file_server,
@@ -506,10 +872,18 @@ fn process_file(
                                 errors,
                                 sections,
                                 label_set,
+                                constants,
                                 current_section,
                                 default_base,
-                                allow_include,
+                                entry,
+                                ended,
+                                references,
+                                Some(provider),
                             );
+
+                            if *ended {
+                                break;
+                            }
                         }
                         Err(error) => {
                             errors.push(AssemblerError::IncludeError {
@@ -543,6 +917,29 @@ fn process_file(
                     });
                 }
             }
+            Statement::EntryDirective(directive) => {
+                if entry.is_none() {
+                    *entry = Some(EntryDirective::clone(directive));
+                } else {
+                    errors.push(AssemblerError::InvalidEntryDirective {
+                        directive: directive.span(),
+                    });
+                }
+            }
+            Statement::EndDirective(directive) => {
+                if let Some(end_entry) = directive.entry() {
+                    if entry.is_none() {
+                        *entry = Some(end_entry);
+                    } else {
+                        errors.push(AssemblerError::InvalidEntryDirective {
+                            directive: directive.span(),
+                        });
+                    }
+                }
+
+                *ended = true;
+                break;
+            }
             _ => {
                 if let Statement::Label(label) = &statement {
                     if let Some(previous) =
@@ -553,6 +950,19 @@ fn process_file(
                             duplicate: label.name().span(),
                         });
                     }
+
+                    if let LabelValue::Expression { value, .. } = label.value() {
+                        match value.try_eval(label_set, constants) {
+                            Ok(value) => {
+                                constants.insert(label.name().name(), Some(value));
+                            }
+                            Err(EvalError::MissingReferenceValue)
+                            | Err(EvalError::UndefinedSymbol(_)) => {}
+                            Err(_) => {
+                                constants.insert(label.name().name(), None);
+                            }
+                        }
+                    }
                 }
 
                 if let Some(current_section) = current_section {
@@ -617,9 +1027,15 @@ fn process_sections(
                                     directive: directive.span(),
                                 });
                             }
-                            Ok(align) => {
-                                current_address = current_address.div_ceil(align) * align;
-                            }
+                            Ok(align) => match current_address.div_ceil(align).checked_mul(align) {
+                                Some(aligned) => current_address = aligned,
+                                None => {
+                                    errors.push(AssemblerError::SectionTooLarge {
+                                        section: SharedStr::clone(&name),
+                                    });
+                                    break;
+                                }
+                            },
                             Err(_) => {
                                 errors.push(AssemblerError::InvalidValue {
                                     value: directive.value().span(),
@@ -631,7 +1047,10 @@ fn process_sections(
                     Statement::OriginDirective(_) => unreachable!(),
                     Statement::SectionDirective(_) => unreachable!(),
                     Statement::IncludeDirective(_) => unreachable!(),
+                    Statement::EntryDirective(_) => unreachable!(),
+                    Statement::EndDirective(_) => unreachable!(),
                     Statement::Label(_) => {}
+                    Statement::AssertDirective(_) => {}
                     Statement::Instruction(_) => {}
                 }
 
@@ -671,12 +1090,21 @@ fn process_sections(
         .collect();
 
     // Check for overlapping sections
+    if sections.len() <= 1 {
+        return sections;
+    }
+
+    // Sections that are too large can end past the address space, so the ends are computed wider
     for (i, first) in sections.iter().enumerate() {
+        let first_start = first.base as u32;
+        let first_end = first_start + (first.size as u32);
+
         for second in sections.iter().skip(i + 1) {
-            if (second.base >= first.base) && (second.base <= (first.base + first.size))
-                || (((second.base + second.size) >= first.base)
-                    && ((second.base + second.size) <= (first.base + first.size)))
-            {
+            let second_start = second.base as u32;
+            let second_end = second_start + (second.size as u32);
+
+            // Sections ending right where the next one starts, like unplaced ones, don't overlap
+            if (second_start < first_end) && (first_start < second_end) {
                 errors.push(AssemblerError::OverlappingSections {
                     first: SharedStr::clone(&first.name),
                     second: SharedStr::clone(&second.name),
@@ -688,14 +1116,17 @@ fn process_sections(
     sections
 }
 
+// `address_offset` is added to every positional label, which is used to find out what values depend on addresses
 fn evaluate_labels(
     sections: &[Section],
     label_set: &HashMap<SharedStr, TextSpan>,
+    address_offset: i64,
     errors: &mut Vec<AssemblerError>,
 ) -> HashMap<SharedStr, Option<i64>> {
     // Evaluate positional labels
     let mut label_values = HashMap::new();
     let mut label_expressions = Vec::new();
+    let mut assertions = Vec::new();
     for section in sections {
         let mut current_address = section.base;
 
@@ -703,7 +1134,10 @@ fn evaluate_labels(
             match statement {
                 Statement::Label(label) => match label.value() {
                     LabelValue::Address { .. } => {
-                        label_values.insert(label.name().name(), Some(current_address as i64));
+                        label_values.insert(
+                            label.name().name(),
+                            Some((current_address as i64) + address_offset),
+                        );
                     }
                     LabelValue::Expression { value, .. } => {
                         label_expressions.push((label.name().name(), value));
@@ -722,6 +1156,11 @@ fn evaluate_labels(
                 Statement::OriginDirective(_) => unreachable!(),
                 Statement::SectionDirective(_) => unreachable!(),
                 Statement::IncludeDirective(_) => unreachable!(),
+                Statement::EntryDirective(_) => unreachable!(),
+                Statement::EndDirective(_) => unreachable!(),
+                Statement::AssertDirective(directive) => {
+                    assertions.push((directive, (current_address as i64) + address_offset));
+                }
                 Statement::Instruction(_) => {}
             }
 
@@ -746,10 +1185,16 @@ fn evaluate_labels(
                         errors.push(AssemblerError::DivideByZero { expr: expr.span() });
                         label_values.insert(SharedStr::clone(label_name), None);
                     }
+                    Err(EvalError::Overflow(expr)) => {
+                        errors.push(AssemblerError::ExpressionOverflow { expr });
+                        label_values.insert(SharedStr::clone(label_name), None);
+                    }
+                    Err(EvalError::NoCurrentAddress(expr)) => {
+                        errors.push(AssemblerError::NoCurrentAddress { expr });
+                        label_values.insert(SharedStr::clone(label_name), None);
+                    }
                     Err(EvalError::UndefinedSymbol(ident)) => {
-                        errors.push(AssemblerError::UndefinedSymbol {
-                            ident: ident.span(),
-                        });
+                        errors.push(AssemblerError::undefined_symbol(&ident));
                         label_values.insert(SharedStr::clone(label_name), None);
                     }
                     Err(EvalError::MissingReferenceValue) => {}
@@ -773,105 +1218,840 @@ fn evaluate_labels(
         }
     }
 
+    // Assertions can reference every label, so they are checked last
+    for (directive, address) in assertions {
+        label_values.insert(CURRENT_ADDRESS.into(), Some(address));
+        evaluate_assert(directive, label_set, &label_values, errors);
+    }
+    label_values.remove(CURRENT_ADDRESS);
+
     label_values
 }
 
-pub fn assemble(
+fn evaluate_assert(
+    directive: &AssertDirective,
+    label_set: &HashMap<SharedStr, TextSpan>,
+    label_values: &HashMap<SharedStr, Option<i64>>,
+    errors: &mut Vec<AssemblerError>,
+) {
+    match directive.condition().try_eval(label_set, label_values) {
+        Ok(0) => errors.push(AssemblerError::AssertionFailed {
+            directive: directive.span(),
+            message: directive.message().map(StringLiteral::value),
+        }),
+        Ok(_) => {}
+        // These have already been reported when evaluating the referenced labels
+        Err(EvalError::InvalidLiteralValue(_))
+        | Err(EvalError::ErrorInReferenceEval)
+        | Err(EvalError::MissingReferenceValue) => {}
+        Err(EvalError::DivideByZero(expr)) => {
+            errors.push(AssemblerError::DivideByZero { expr: expr.span() });
+        }
+        Err(EvalError::Overflow(expr)) => {
+            errors.push(AssemblerError::ExpressionOverflow { expr });
+        }
+        Err(EvalError::NoCurrentAddress(expr)) => {
+            errors.push(AssemblerError::NoCurrentAddress { expr });
+        }
+        Err(EvalError::UndefinedSymbol(ident)) => {
+            errors.push(AssemblerError::undefined_symbol(&ident));
+        }
+    }
+}
+
+fn evaluate_entry(
+    entry: &EntryDirective,
+    label_values: &HashMap<SharedStr, Option<i64>>,
+    errors: &mut Vec<AssemblerError>,
+) -> Option<u16> {
+    match label_values.get(entry.symbol().name().as_ref()) {
+        Some(&Some(value)) => match u16::try_from(value) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                errors.push(AssemblerError::InvalidValue {
+                    value: entry.symbol().span(),
+                    directive: entry.span(),
+                });
+                None
+            }
+        },
+        Some(None) => None,
+        None => {
+            errors.push(AssemblerError::undefined_symbol(entry.symbol()));
+            None
+        }
+    }
+}
+
+/// An immediate in the program that holds an absolute address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    low: u16,
+    high: Option<u16>,
+}
+
+impl Relocation {
+    /// Offset of the low byte of the address in the program data
+    #[inline]
+    pub fn low(&self) -> u16 {
+        self.low
+    }
+
+    /// Offset of the high byte of the address in the program data, if it was encoded
+    #[inline]
+    pub fn high(&self) -> Option<u16> {
+        self.high
+    }
+}
+
+/// The locations of all absolute addresses in a program, which allows loading it at a different base.
+/// Only plain address references (a label plus or minus a constant) can be relocated,
+/// values that are computed from addresses in any other way are left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct Relocations {
+    base: u16,
+    entries: Vec<Relocation>,
+}
+
+impl Relocations {
+    /// The base address the program was assembled for
+    #[inline]
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[Relocation] {
+        &self.entries
+    }
+
+    /// Adjusts all addresses in `data` for the program being loaded at `base` instead
+    pub fn apply(&self, data: &mut [u8], base: u16) {
+        let delta = base.wrapping_sub(self.base);
+
+        for entry in &self.entries {
+            let low = data[entry.low as usize];
+            let high = entry.high.map_or(0, |high| data[high as usize]);
+            let address = u16::from_le_bytes([low, high]).wrapping_add(delta);
+
+            let [low, high] = address.to_le_bytes();
+            data[entry.low as usize] = low;
+            if let Some(offset) = entry.high {
+                data[offset as usize] = high;
+            }
+        }
+    }
+}
+
+pub struct Program {
+    base: u16,
+    entry: u16,
+    data: Vec<u8>,
+    relocations: Relocations,
+    symbols: HashMap<SharedStr, i64>,
+    sections: Vec<SectionLayout>,
+}
+
+impl Program {
+    #[inline]
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[inline]
+    pub fn entry(&self) -> u16 {
+        self.entry
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[inline]
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    #[inline]
+    pub fn relocations(&self) -> &Relocations {
+        &self.relocations
+    }
+
+    /// Fills the program up to `size` bytes with `fill`, e.g. to match the size of an EEPROM.
+    /// Fails with the size of the program if it is larger than that.
+    pub fn pad(&mut self, size: usize, fill: u8) -> Result<(), usize> {
+        if self.data.len() > size {
+            return Err(self.data.len());
+        }
+
+        self.data.resize(size, fill);
+        Ok(())
+    }
+
+    /// Writes a checksum into the program at `at`, see `apply_checksum`
+    #[inline]
+    pub fn apply_checksum(&mut self, kind: ChecksumKind, at: usize) {
+        apply_checksum(&mut self.data, kind, at);
+    }
+
+    /// The value of a symbol, or `None` if it is not defined or could not be evaluated
+    #[inline]
+    pub fn symbol(&self, name: &str) -> Option<i64> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Where every section ended up, sorted by base address
+    #[inline]
+    pub fn sections(&self) -> &[SectionLayout] {
+        &self.sections
+    }
+}
+
+struct Symbols {
+    label_set: HashMap<SharedStr, TextSpan>,
+    label_values: HashMap<SharedStr, Option<i64>>,
+    references: HashMap<SharedStr, Vec<TextSpan>>,
+}
+
+// Includes are read from disk if they are allowed
+#[inline]
+fn include_provider(allow_include: bool) -> Option<&'static dyn FileProvider> {
+    allow_include.then_some(&FsFileProvider as &dyn FileProvider)
+}
+
+fn assemble_impl(
     file_server: &mut FileServer,
-    file: FileId,
-    allow_include: bool,
-) -> Result<(u16, Vec<u8>), Vec<AssemblerError>> {
-    let mut errors = Vec::new();
+    files: &[FileId],
+    provider: Option<&dyn FileProvider>,
+    errors: &mut Vec<AssemblerError>,
+) -> (Option<Program>, Symbols) {
     let mut sections = IndexMap::<SharedStr, RawSection>::new();
 
     let mut label_set = HashMap::new();
+    let mut constants = HashMap::new();
     let mut current_section = None;
     let mut default_base = None;
-    process_file(
-        file_server,
-        file,
-        &mut errors,
-        &mut sections,
-        &mut label_set,
-        &mut current_section,
-        &mut default_base,
-        allow_include,
-    );
+    let mut entry = None;
+    let mut ended = false;
+    let mut references = HashMap::new();
+    for &file in files {
+        process_file(
+            file_server,
+            file,
+            errors,
+            &mut sections,
+            &mut label_set,
+            &mut constants,
+            &mut current_section,
+            &mut default_base,
+            &mut entry,
+            &mut ended,
+            &mut references,
+            provider,
+        );
 
-    let mut sections = process_sections(sections, default_base.unwrap_or(0), &mut errors);
-    let label_values = evaluate_labels(&sections, &label_set, &mut errors);
+        // `.end` ends the input as a whole, not just the file it is in
+        if ended {
+            break;
+        }
+    }
 
-    if errors.is_empty() {
-        if sections.is_empty() {
-            Ok((0, Vec::new()))
-        } else {
-            sections.sort_by_key(|section| section.base);
+    let mut sections = process_sections(sections, default_base.unwrap_or(0), errors);
+    let mut label_values = evaluate_labels(&sections, &label_set, 0, errors);
+    let entry = entry.and_then(|entry| evaluate_entry(&entry, &label_values, errors));
+    let symbols = label_values
+        .iter()
+        .filter_map(|(name, value)| Some((SharedStr::clone(name), (*value)?)))
+        .collect();
 
-            let first_section = sections.first().unwrap();
-            let last_section = sections.last().unwrap();
+    let program = if !errors.is_empty() {
+        None
+    } else if sections.is_empty() {
+        Some(Program {
+            base: 0,
+            entry: entry.unwrap_or(0),
+            data: Vec::new(),
+            relocations: Relocations::default(),
+            symbols,
+            sections: Vec::new(),
+        })
+    } else {
+        sections.sort_by_key(|section| section.base);
+
+        let first_section = sections.first().unwrap();
+        let last_section = sections.last().unwrap();
+
+        let start_address = first_section.base;
+        let end_address = last_section.base + last_section.size;
 
-            let start_address = first_section.base;
-            let end_address = last_section.base + last_section.size;
+        // Evaluating all labels with their addresses shifted reveals which values depend on addresses
+        const RELOCATION_PROBE: i64 = 0x1000;
+        let mut shifted_values =
+            evaluate_labels(&sections, &label_set, RELOCATION_PROBE, &mut Vec::new());
+        let mut relocations = Vec::new();
 
-            let mut data = vec![0u8; (end_address - start_address) as usize];
-            let mut writer = std::io::Cursor::new(&mut data);
+        let layouts = layout::section_layouts(
+            sections
+                .iter()
+                .map(|section| (SharedStr::clone(&section.name), section.base, section.size)),
+        );
 
-            for section in sections {
-                writer.set_position((section.base - start_address) as u64);
+        let mut data = vec![0u8; (end_address - start_address) as usize];
+        let mut writer = std::io::Cursor::new(&mut data);
 
-                for statement in section.statements {
-                    match statement {
-                        Statement::Label(_) => {}
-                        Statement::OffsetDirective(directive) => {
-                            let offset = directive.value().value().unwrap() as u16;
-                            writer.set_position((section.base - start_address + offset) as u64);
+        for section in sections {
+            writer.set_position((section.base - start_address) as u64);
+
+            for statement in section.statements {
+                match statement {
+                    Statement::Label(_) => {}
+                    Statement::OffsetDirective(directive) => {
+                        let offset = directive.value().value().unwrap() as u16;
+                        writer.set_position((section.base - start_address + offset) as u64);
+                    }
+                    Statement::AlignDirective(directive) => {
+                        let align = directive.value().value().unwrap() as u64;
+                        if align > 0 {
+                            writer.set_position(writer.position().div_ceil(align) * align);
                         }
-                        Statement::AlignDirective(directive) => {
-                            let align = directive.value().value().unwrap() as u64;
-                            if align > 0 {
-                                writer.set_position(writer.position().div_ceil(align) * align);
+                    }
+                    Statement::OriginDirective(_) => unreachable!(),
+                    Statement::SectionDirective(_) => unreachable!(),
+                    Statement::IncludeDirective(_) => unreachable!(),
+                    Statement::EntryDirective(_) => unreachable!(),
+                    Statement::EndDirective(_) => unreachable!(),
+                    Statement::AssertDirective(_) => {}
+                    Statement::Instruction(instruction) => {
+                        let address = (start_address as i64) + (writer.position() as i64);
+                        label_values.insert(CURRENT_ADDRESS.into(), Some(address));
+                        shifted_values
+                            .insert(CURRENT_ADDRESS.into(), Some(address + RELOCATION_PROBE));
+
+                        if let Some((value, low, high)) = instruction.immediate() {
+                            let original = value.try_eval(&label_set, &label_values);
+                            let shifted = value.try_eval(&label_set, &shifted_values);
+
+                            if let (Ok(original), Ok(shifted)) = (original, shifted) {
+                                if shifted.wrapping_sub(original) == RELOCATION_PROBE {
+                                    let position = writer.position() as u16;
+                                    relocations.push(Relocation {
+                                        low: position + low,
+                                        high: high.map(|high| position + high),
+                                    });
+                                }
                             }
                         }
-                        Statement::OriginDirective(_) => unreachable!(),
-                        Statement::SectionDirective(_) => unreachable!(),
-                        Statement::IncludeDirective(_) => unreachable!(),
-                        Statement::Instruction(instruction) => {
-                            instruction
-                                .encode(&mut writer, &label_set, &label_values, &mut errors)
-                                .expect("writing to an in-memory buffer");
-                        }
+
+                        instruction
+                            .encode(&mut writer, &label_set, &label_values, errors)
+                            .expect("writing to an in-memory buffer");
                     }
                 }
             }
+        }
 
-            if errors.is_empty() {
-                Ok((start_address, data))
-            } else {
-                Err(errors)
-            }
+        Some(Program {
+            base: start_address,
+            entry: entry.unwrap_or(start_address),
+            data,
+            relocations: Relocations {
+                base: start_address,
+                entries: relocations,
+            },
+            symbols,
+            sections: layouts,
+        })
+    };
+    label_values.remove(CURRENT_ADDRESS);
+
+    let symbols = Symbols {
+        label_set,
+        label_values,
+        references,
+    };
+
+    (program, symbols)
+}
+
+pub fn assemble(
+    file_server: &mut FileServer,
+    file: FileId,
+    allow_include: bool,
+) -> Result<Program, Vec<AssemblerError>> {
+    let mut errors = Vec::new();
+    let (program, _) = assemble_impl(
+        file_server,
+        &[file],
+        include_provider(allow_include),
+        &mut errors,
+    );
+
+    match program {
+        Some(program) if errors.is_empty() => Ok(program),
+        _ => Err(errors),
+    }
+}
+
+/// Like `assemble`, but resolves `.include` directives through `provider` instead of the file system
+pub fn assemble_with_provider(
+    file_server: &mut FileServer,
+    file: FileId,
+    provider: &dyn FileProvider,
+) -> Result<Program, Vec<AssemblerError>> {
+    let mut errors = Vec::new();
+    let (program, _) = assemble_impl(file_server, &[file], Some(provider), &mut errors);
+
+    match program {
+        Some(program) if errors.is_empty() => Ok(program),
+        _ => Err(errors),
+    }
+}
+
+/// Assembles multiple files into one program, as if they were included one after another.
+/// Labels defined in one file can be referenced from all the others.
+pub fn assemble_files(
+    file_server: &mut FileServer,
+    paths: &[PathBuf],
+) -> Result<Program, Vec<AssemblerError>> {
+    let mut errors = Vec::new();
+    let mut files = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        match file_server.register_file(path) {
+            Ok(file) => files.push(file),
+            Err(error) => errors.push(AssemblerError::FileError {
+                path: path.clone(),
+                error,
+            }),
         }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let (program, _) = assemble_impl(file_server, &files, Some(&FsFileProvider), &mut errors);
+
+    match program {
+        Some(program) if errors.is_empty() => Ok(program),
+        _ => Err(errors),
+    }
+}
+
+// Symbols referenced by `.entry` count as used, so the entry point never triggers a warning
+fn unused_symbol_warnings(file_server: &FileServer, symbols: &Symbols) -> Vec<AssemblerError> {
+    let mut unused: Vec<_> = symbols
+        .label_set
+        .iter()
+        .filter(|(name, _)| !symbols.references.contains_key(*name))
+        .map(|(_, &span)| span)
+        .collect();
+
+    unused.sort_by_key(|span| {
+        let path = file_server
+            .get_file(span.file_id())
+            .unwrap()
+            .path()
+            .to_owned();
+        (path, span.start_pos().line_column(file_server))
+    });
+
+    unused
+        .into_iter()
+        .map(|symbol| AssemblerError::UnusedSymbol { symbol })
+        .collect()
+}
+
+/// Like `assemble`, but also returns warnings. If `warn_unused` is set, every symbol that is
+/// never referenced is reported, which is opt-in since some symbols are intentionally unused.
+/// Warnings are returned together with the errors if assembling fails.
+pub fn assemble_with_warnings(
+    file_server: &mut FileServer,
+    file: FileId,
+    allow_include: bool,
+    warn_unused: bool,
+) -> Result<(Program, Vec<AssemblerError>), Vec<AssemblerError>> {
+    let mut errors = Vec::new();
+    let (program, symbols) = assemble_impl(
+        file_server,
+        &[file],
+        include_provider(allow_include),
+        &mut errors,
+    );
+
+    let mut warnings = if warn_unused {
+        unused_symbol_warnings(file_server, &symbols)
     } else {
-        Err(errors)
+        Vec::new()
+    };
+    if let Some(program) = &program {
+        warnings.extend(monitor_warning(program.sections()));
+    }
+
+    match program {
+        Some(program) if errors.is_empty() => Ok((program, warnings)),
+        _ => {
+            errors.extend(warnings);
+            Err(errors)
+        }
+    }
+}
+
+/// For every symbol, where it is defined and all places it is referenced
+pub type CrossReference = HashMap<SharedStr, (TextSpan, Vec<TextSpan>)>;
+
+/// Assembles a file and collects where each symbol is defined and used
+pub fn assemble_xref(
+    file_server: &mut FileServer,
+    file: FileId,
+    allow_include: bool,
+) -> Result<CrossReference, Vec<AssemblerError>> {
+    let mut errors = Vec::new();
+    let (program, mut symbols) = assemble_impl(
+        file_server,
+        &[file],
+        include_provider(allow_include),
+        &mut errors,
+    );
+
+    match program {
+        Some(_) if errors.is_empty() => Ok(symbols
+            .label_set
+            .into_iter()
+            .map(|(name, definition)| {
+                let uses = symbols.references.remove(&name).unwrap_or_default();
+                (name, (definition, uses))
+            })
+            .collect()),
+        _ => Err(errors),
+    }
+}
+
+/// Formats a cross-reference as one line per symbol with its definition,
+/// followed by an indented line for every use, sorted by name
+pub fn format_xref(xref: &CrossReference, file_server: &FileServer) -> String {
+    use std::fmt::Write;
+
+    let mut names: Vec<_> = xref.keys().collect();
+    names.sort();
+
+    let mut output = String::new();
+    for name in names {
+        let (definition, uses) = &xref[name];
+        writeln!(
+            output,
+            "{name} ({}, {} use{})",
+            format_location(file_server, *definition, 0),
+            uses.len(),
+            if uses.len() == 1 { "" } else { "s" },
+        )
+        .unwrap();
+
+        for &span in uses {
+            writeln!(output, "    {}", format_location(file_server, span, 0)).unwrap();
+        }
     }
+
+    output
 }
 
-pub fn assemble_code(code: &str, allow_include: bool) -> Result<(u16, Vec<u8>), String> {
-    let code = code.replace('\t', "    ");
+pub struct Symbol {
+    location: DiagnosticLocation,
+    value: Option<i64>,
+}
+
+impl Symbol {
+    /// Where the symbol is defined
+    #[inline]
+    pub fn location(&self) -> &DiagnosticLocation {
+        &self.location
+    }
+
+    /// The value of the symbol, or `None` if it could not be evaluated
+    #[inline]
+    pub fn value(&self) -> Option<i64> {
+        self.value
+    }
+}
+
+/// Everything editor tooling needs to know about a file, even if it fails to assemble
+pub struct Analysis {
+    diagnostics: Vec<Diagnostic>,
+    symbols: HashMap<SharedStr, Symbol>,
+}
+
+impl Analysis {
+    #[inline]
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    #[inline]
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
 
+    #[inline]
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_ref(), symbol))
+    }
+}
+
+pub fn analyze(file_server: &mut FileServer, file: FileId, allow_include: bool) -> Analysis {
+    let mut errors = Vec::new();
+    let (_, symbols) = assemble_impl(
+        file_server,
+        &[file],
+        include_provider(allow_include),
+        &mut errors,
+    );
+
+    let diagnostics = errors
+        .iter()
+        .map(|error| error.to_diagnostic(file_server))
+        .collect();
+
+    let symbols = symbols
+        .label_set
+        .into_iter()
+        .map(|(name, span)| {
+            let symbol = Symbol {
+                location: DiagnosticLocation::new(file_server, span, None),
+                value: symbols.label_values.get(&name).copied().flatten(),
+            };
+
+            (name, symbol)
+        })
+        .collect();
+
+    Analysis {
+        diagnostics,
+        symbols,
+    }
+}
+
+/// Checks a single line of code for operands that the instruction does not support,
+/// returning their byte range within the line
+pub fn find_invalid_operands(line: &str) -> Option<Range<usize>> {
     let mut file_server = FileServer::new();
-    let file = file_server.register_file_memory("<code>", code).unwrap();
+    let file = file_server
+        .register_file_memory("<line>", line.to_owned())
+        .ok()?;
 
-    assemble(&mut file_server, file, allow_include).map_err(|errors| {
-        let mut output = String::new();
+    let tokens: Vec<_> = Jam1Lexer::new(file, &file_server)
+        .filter(|token| {
+            !matches!(
+                token.kind,
+                Jam1Token::NewLine | Jam1Token::Comment(_) | Jam1Token::LineContinuation
+            )
+        })
+        .collect();
 
-        for (i, error) in errors.into_iter().enumerate() {
-            if i > 0 {
-                output.push('\n');
+    if tokens.is_empty() {
+        return None;
+    }
+
+    let span = match parser::parse(TokenStream::new(&tokens)) {
+        Err(parser::ParseError::InvalidOperands { op1, op2 }) => op1.join(&op2),
+        Err(parser::ParseError::InvalidRegister { register }) => register,
+        _ => return None,
+    };
+
+    let text = file_server.get_file(file).unwrap().text();
+    let span_text = span.text(&file_server);
+    let start = (span_text.as_ptr() as usize) - (text.as_ptr() as usize);
+    Some(start..(start + span_text.len()))
+}
+
+/// Finds the bracket or parenthesis matching the one at or right before a byte offset,
+/// returning the byte offsets of both
+pub fn matching_bracket(code: &str, offset: usize) -> Option<(usize, usize)> {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("<code>", code.to_owned())
+        .ok()?;
+    let text = file_server.get_file(file).unwrap().text();
+
+    let mut stack = Vec::new();
+    let mut pairs = Vec::new();
+    for token in Jam1Lexer::new(file, &file_server) {
+        let position = (token.span.text(&file_server).as_ptr() as usize) - (text.as_ptr() as usize);
+
+        match token.kind {
+            Jam1Token::Punctuation(
+                kind @ (PunctuationKind::OpeningParenthesis | PunctuationKind::OpeningBracket),
+            ) => stack.push((kind, position)),
+            Jam1Token::Punctuation(PunctuationKind::ClosingParenthesis) => {
+                if let Some((PunctuationKind::OpeningParenthesis, open)) = stack.last().copied() {
+                    stack.pop();
+                    pairs.push((open, position));
+                } else {
+                    stack.clear();
+                }
             }
+            Jam1Token::Punctuation(PunctuationKind::ClosingBracket) => {
+                if let Some((PunctuationKind::OpeningBracket, open)) = stack.last().copied() {
+                    stack.pop();
+                    pairs.push((open, position));
+                } else {
+                    stack.clear();
+                }
+            }
+            // Statements end at a new line, so brackets cannot be matched across one
+            Jam1Token::NewLine => stack.clear(),
+            _ => {}
+        }
+    }
+
+    let find = |offset: usize| {
+        pairs
+            .iter()
+            .copied()
+            .find(|&(open, close)| (open == offset) || (close == offset))
+    };
+
+    find(offset).or_else(|| offset.checked_sub(1).and_then(find))
+}
+
+/// Finds the identifier at a zero-based line and column
+pub fn identifier_at(
+    file_server: &FileServer,
+    file: FileId,
+    line: u32,
+    column: u32,
+) -> Option<SharedStr> {
+    let lexer = Jam1Lexer::new(file, file_server);
+    for token in lexer {
+        let (start_line, start_column) = token.span.start_pos().line_column(file_server);
+        let (end_line, end_column) = token.span.end_pos().line_column(file_server);
 
-            output.push_str(&error.format(&file_server));
+        if (start_line > line) || ((start_line == line) && (start_column > column)) {
+            break;
         }
 
-        output
-    })
+        if let Jam1Token::Identifier(name) = token.kind {
+            if (start_line == line) && (end_line == line) && (column <= end_column) {
+                return Some(name);
+            }
+        }
+    }
+
+    None
+}
+
+/// Finds the definition of the symbol at a byte offset in the code,
+/// returning the byte range of the symbol's name where it is defined
+pub fn definition_at(code: &str, offset: usize) -> Option<Range<usize>> {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("<code>", code.to_owned())
+        .ok()?;
+
+    let text_start = file_server.get_file(file).unwrap().text().as_ptr() as usize;
+    let byte_range = |file_server: &FileServer, span: TextSpan| {
+        let span_text = span.text(file_server);
+        let start = (span_text.as_ptr() as usize) - text_start;
+        start..(start + span_text.len())
+    };
+
+    let name = Jam1Lexer::new(file, &file_server).find_map(|token| match token.kind {
+        Jam1Token::Identifier(name) => {
+            let range = byte_range(&file_server, token.span);
+            ((range.start <= offset) && (offset <= range.end)).then_some(name)
+        }
+        _ => None,
+    })?;
+
+    let (_, symbols) = assemble_impl(&mut file_server, &[file], None, &mut Vec::new());
+    let definition = symbols.label_set.get(&name)?;
+    Some(byte_range(&file_server, *definition))
+}
+
+// Assembles code from the editor, which is not backed by a file
+fn assemble_code_impl(
+    code: &str,
+    provider: Option<&dyn FileProvider>,
+) -> (FileServer, Result<Program, Vec<AssemblerError>>) {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("<code>", code.to_owned())
+        .unwrap();
+
+    let mut errors = Vec::new();
+    let (program, _) = assemble_impl(&mut file_server, &[file], provider, &mut errors);
+
+    let result = match program {
+        Some(program) if errors.is_empty() => Ok(program),
+        _ => Err(errors),
+    };
+
+    (file_server, result)
+}
+
+fn to_diagnostics(errors: &[AssemblerError], file_server: &FileServer) -> Vec<Diagnostic> {
+    errors
+        .iter()
+        .map(|error| error.to_diagnostic(file_server))
+        .collect()
+}
+
+fn format_errors(errors: &[AssemblerError], file_server: &FileServer, tab_width: usize) -> String {
+    let mut output = String::new();
+
+    for (i, error) in errors.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+
+        output.push_str(&error.format_with_tab_width(file_server, tab_width));
+    }
+
+    output
+}
+
+/// Like `assemble_code`, but reports errors as structured diagnostics instead of formatted text
+pub fn assemble_code_diagnostics(
+    code: &str,
+    allow_include: bool,
+) -> Result<Program, Vec<Diagnostic>> {
+    let (file_server, result) = assemble_code_impl(code, include_provider(allow_include));
+    result.map_err(|errors| to_diagnostics(&errors, &file_server))
+}
+
+/// Like `assemble_code_diagnostics`, but resolves `.include` directives through `provider`.
+/// Paths are relative to the root of the provider.
+pub fn assemble_code_diagnostics_with_provider(
+    code: &str,
+    provider: &dyn FileProvider,
+) -> Result<Program, Vec<Diagnostic>> {
+    let (file_server, result) = assemble_code_impl(code, Some(provider));
+    result.map_err(|errors| to_diagnostics(&errors, &file_server))
+}
+
+#[inline]
+pub fn assemble_code(code: &str, allow_include: bool) -> Result<Program, String> {
+    assemble_code_with_tab_width(code, allow_include, DEFAULT_TAB_WIDTH)
+}
+
+/// Like `assemble_code`, but tabs in the formatted errors advance to the next multiple of `tab_width`
+pub fn assemble_code_with_tab_width(
+    code: &str,
+    allow_include: bool,
+    tab_width: usize,
+) -> Result<Program, String> {
+    let (file_server, result) = assemble_code_impl(code, include_provider(allow_include));
+    result.map_err(|errors| format_errors(&errors, &file_server, tab_width))
+}
+
+/// Like `assemble_code`, but resolves `.include` directives through `provider`.
+/// Paths are relative to the root of the provider.
+pub fn assemble_code_with_provider(
+    code: &str,
+    provider: &dyn FileProvider,
+) -> Result<Program, String> {
+    let (file_server, result) = assemble_code_impl(code, Some(provider));
+    result.map_err(|errors| format_errors(&errors, &file_server, DEFAULT_TAB_WIDTH))
 }
@@ -22,6 +22,10 @@ pub enum ParseError {
     NoMatch {
         span: TextSpan,
     },
+    UnknownMnemonic {
+        mnemonic: TextSpan,
+        suggestion: &'static str,
+    },
 }
 
 macro_rules! expect {
@@ -203,6 +207,7 @@ fn leaf_expression() -> impl Jam1Parser<Expression> {
     choice!(
         parser!(({integer_literal()}->[Box::new])->[Expression::Literal]),
         parser!(({identifier()}->[Box::new])->[Expression::Identifier]),
+        parser!(({punctuation([PunctuationKind::DollarSign])}->[Box::new])->[Expression::CurrentAddress]),
         parser!(({group_expression()}->[Box::new])->[Expression::Group]),
     )
 }
@@ -258,6 +263,12 @@ fn build_binary_expression_tree(
                 Expression::ArithmeticRightShift(binary_expr)
             }
             PunctuationKind::DoubleGreaterThanSign => Expression::LogicalRightShift(binary_expr),
+            PunctuationKind::DoubleEqualSign => Expression::Equal(binary_expr),
+            PunctuationKind::ExclamationMarkEqualSign => Expression::NotEqual(binary_expr),
+            PunctuationKind::LessThanSign => Expression::LessThan(binary_expr),
+            PunctuationKind::LessThanEqualSign => Expression::LessThanOrEqual(binary_expr),
+            PunctuationKind::GreaterThanSign => Expression::GreaterThan(binary_expr),
+            PunctuationKind::GreaterThanEqualSign => Expression::GreaterThanOrEqual(binary_expr),
             _ => unreachable!(),
         };
     }
@@ -288,7 +299,20 @@ fn expression() -> impl Jam1Parser<Expression> {
     let xor_expr = binary_expression!(and_expr, [Accent]);
     let or_expr = binary_expression!(xor_expr, [VerticalBar]);
 
-    or_expr
+    // Comparisons bind weaker than the bitwise operators, so `A & 1 == 0` tests the masked value
+    let relational_expr = binary_expression!(
+        or_expr,
+        [
+            LessThanSign,
+            LessThanEqualSign,
+            GreaterThanSign,
+            GreaterThanEqualSign,
+        ],
+    );
+    let equality_expr =
+        binary_expression!(relational_expr, [DoubleEqualSign, ExclamationMarkEqualSign]);
+
+    equality_expr
 }
 
 fn label() -> impl Jam1Parser<Label> {
@@ -332,7 +356,7 @@ fn section_directive() -> impl Jam1Parser<SectionDirective> {
         (
             {directive(DirectiveKind::Section)}
             <.> {string_literal()}!![expect!("string literal")]
-            <.> ?{integer_literal()}
+            <.> ?{expression()}
         )->[|((directive, name), base)| SectionDirective::new(directive, name, base)]
     )
 }
@@ -344,6 +368,35 @@ fn include_directive() -> impl Jam1Parser<IncludeDirective> {
     )
 }
 
+fn entry_directive() -> impl Jam1Parser<EntryDirective> {
+    parser!(
+        ({directive(DirectiveKind::Entry)} <.> {identifier()}!![expect!("identifier")])
+        ->[|(directive, symbol)| EntryDirective::new(directive, symbol)]
+    )
+}
+
+fn end_directive() -> impl Jam1Parser<EndDirective> {
+    parser!(
+        ({directive(DirectiveKind::End)} <.> ?{identifier()})
+        ->[|(directive, symbol)| EndDirective::new(directive, symbol)]
+    )
+}
+
+fn assert_directive() -> impl Jam1Parser<AssertDirective> {
+    let message = parser!(
+        {punctuation([PunctuationKind::Comma])}
+        <.> {string_literal()}!![expect!("string literal")]
+    );
+
+    parser!(
+        (
+            {directive(DirectiveKind::Assert)}
+            <.> {expression()}!![expect!("expression")]
+            <.> ?message
+        )->[|((directive, condition), message)| AssertDirective::new(directive, condition, message)]
+    )
+}
+
 fn mov_instruction() -> impl Jam1Parser<MovInstruction> {
     let dst = parser!(
         {register()}->[MovDestination::Register]
@@ -586,7 +639,7 @@ fn in_instruction() -> impl Jam1Parser<InInstruction> {
 
 fn out_instruction() -> impl Jam1Parser<OutInstruction> {
     let raw = sequence!(
-        mnemonic([MnemonicKind::In]),
+        mnemonic([MnemonicKind::Out]),
         parser!({io_register()}!![expect!("IO register")]),
         parser!({punctuation([PunctuationKind::Comma])}!![expect!("`,`")]),
         parser!({register()}!![expect!("register")]),
@@ -689,6 +742,7 @@ fn instruction() -> impl Jam1Parser<Instruction> {
     let lodsb_instruction = mnemonic([MnemonicKind::Lodsb]);
     let stosb_instruction = mnemonic([MnemonicKind::Stosb]);
     let ret_instruction = mnemonic([MnemonicKind::Ret]);
+    let reti_instruction = mnemonic([MnemonicKind::Reti]);
     let retbd_instruction = mnemonic([MnemonicKind::RetBd]);
     let clc_instruction = mnemonic([MnemonicKind::Clc]);
 
@@ -698,6 +752,7 @@ fn instruction() -> impl Jam1Parser<Instruction> {
         parser!(lodsb_instruction->[Instruction::Lodsb]),
         parser!(stosb_instruction->[Instruction::Stosb]),
         parser!(ret_instruction->[Instruction::Ret]),
+        parser!(reti_instruction->[Instruction::Reti]),
         parser!(retbd_instruction->[Instruction::RetBd]),
         parser!(clc_instruction->[Instruction::Clc]),
         parser!({mov_instruction()}->[Instruction::Mov]),
@@ -737,13 +792,43 @@ fn statement() -> impl Jam1Parser<Statement> {
         parser!(({origin_directive()}->[Box::new])->[Statement::OriginDirective]),
         parser!(({section_directive()}->[Box::new])->[Statement::SectionDirective]),
         parser!(({include_directive()}->[Box::new])->[Statement::IncludeDirective]),
+        parser!(({entry_directive()}->[Box::new])->[Statement::EntryDirective]),
+        parser!(({assert_directive()}->[Box::new])->[Statement::AssertDirective]),
+        parser!(({end_directive()}->[Box::new])->[Statement::EndDirective]),
         parser!(({instruction()}->[Box::new])->[Statement::Instruction]),
     )
 }
 
+/// A statement starting with an identifier that is not a label is most likely a misspelled mnemonic
+fn unknown_mnemonic(input: TokenStream<Jam1Token>) -> Option<ParseError> {
+    let (first, rest) = input.remaining().split_first()?;
+    let Jam1Token::Identifier(name) = &first.kind else {
+        return None;
+    };
+
+    let is_label = rest.first().is_some_and(|token| {
+        matches!(
+            token.kind,
+            Jam1Token::Punctuation(PunctuationKind::Colon | PunctuationKind::EqualSign)
+        )
+    });
+    if is_label {
+        return None;
+    }
+
+    suggest_mnemonic(name).map(|suggestion| ParseError::UnknownMnemonic {
+        mnemonic: first.span,
+        suggestion,
+    })
+}
+
 pub fn parse(input: TokenStream<Jam1Token>) -> Result<Statement, ParseError> {
     assert!(!input.remaining().is_empty());
 
+    if let Some(err) = unknown_mnemonic(input) {
+        return Err(err);
+    }
+
     match statement().run(input) {
         ParseResult::Match {
             value, remaining, ..
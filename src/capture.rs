@@ -0,0 +1,86 @@
+use crate::Terminal;
+
+/// A control sequence the program sent to the terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlSequence {
+    /// A C0 control character like `\r` or `\n`
+    Execute(u8),
+    /// `ESC [ params action`, with sub-parameters flattened into `params`
+    Csi { params: Vec<u16>, action: char },
+    /// `ESC byte`
+    Esc(u8),
+}
+
+/// Terminal without side effects that keeps everything the system prints in memory,
+/// so the output of programs can be checked in tests
+#[derive(Debug, Default)]
+pub struct CaptureTerminal {
+    displayed: String,
+    control_sequences: Vec<ControlSequence>,
+}
+
+impl CaptureTerminal {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All printed characters, line feeds are included as `\n`
+    #[inline]
+    pub fn displayed(&self) -> &str {
+        &self.displayed
+    }
+
+    #[inline]
+    pub fn control_sequences(&self) -> &[ControlSequence] {
+        &self.control_sequences
+    }
+
+    pub fn clear(&mut self) {
+        self.displayed.clear();
+        self.control_sequences.clear();
+    }
+}
+
+impl vte::Perform for CaptureTerminal {
+    fn print(&mut self, c: char) {
+        self.displayed.push(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.displayed.push('\n');
+        }
+
+        self.control_sequences.push(ControlSequence::Execute(byte));
+    }
+
+    fn csi_dispatch(
+        &mut self,
+        params: &vte::Params,
+        _intermediates: &[u8],
+        ignore: bool,
+        action: char,
+    ) {
+        if !ignore {
+            let params = params.iter().flatten().copied().collect();
+            self.control_sequences
+                .push(ControlSequence::Csi { params, action });
+        }
+    }
+
+    fn esc_dispatch(&mut self, _intermediates: &[u8], ignore: bool, byte: u8) {
+        if !ignore {
+            self.control_sequences.push(ControlSequence::Esc(byte));
+        }
+    }
+}
+
+impl Terminal for CaptureTerminal {
+    fn reset(&mut self) {
+        self.clear();
+    }
+
+    #[inline]
+    fn flush(&mut self) {}
+}
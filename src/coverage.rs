@@ -0,0 +1,78 @@
+use crate::disassembler;
+use std::fmt::Display;
+
+/// Which opcodes were executed at least once during a run
+#[derive(Debug, Clone)]
+pub struct OpcodeCoverage {
+    executed: [bool; 0x100],
+}
+
+impl OpcodeCoverage {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            executed: [false; 0x100],
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record(&mut self, opcode: u8) {
+        self.executed[opcode as usize] = true;
+    }
+
+    #[inline]
+    pub fn executed(&self, opcode: u8) -> bool {
+        self.executed[opcode as usize]
+    }
+
+    /// The number of defined opcodes that were executed
+    pub fn executed_count(&self) -> usize {
+        defined_opcodes()
+            .filter(|&opcode| self.executed(opcode))
+            .count()
+    }
+
+    /// The number of opcodes the CPU defines
+    pub fn defined_count(&self) -> usize {
+        defined_opcodes().count()
+    }
+
+    /// The defined opcodes that were never executed
+    pub fn missing(&self) -> Vec<u8> {
+        defined_opcodes()
+            .filter(|&opcode| !self.executed(opcode))
+            .collect()
+    }
+}
+
+impl Default for OpcodeCoverage {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn defined_opcodes() -> impl Iterator<Item = u8> {
+    (0..=u8::MAX).filter(|&opcode| disassembler::opcode_name(opcode).is_some())
+}
+
+impl Display for OpcodeCoverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "executed {} of {} defined opcodes",
+            self.executed_count(),
+            self.defined_count()
+        )?;
+
+        let missing = self.missing();
+        if !missing.is_empty() {
+            writeln!(f, ", never executed:")?;
+            for opcode in missing {
+                let name = disassembler::opcode_name(opcode).unwrap();
+                writeln!(f, "    {opcode:0>2X}  {name}")?;
+            }
+        }
+        Ok(())
+    }
+}
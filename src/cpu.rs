@@ -2,7 +2,9 @@ use bitflags::bitflags;
 use modular_bitfield::error::InvalidBitPattern;
 use modular_bitfield::*;
 use std::fmt::Display;
+use std::ops::Range;
 
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::{Audio, Controler, Memory, Spi, Uart, Vga};
 
 const PIPE_ROM_SIZE: usize = 0x8000;
@@ -152,6 +154,161 @@ mod pipeline_data {
 
 use pipeline_data::*;
 
+struct Stage1Control {
+    lhs_bus_assert: AluBusRegister,
+    rhs_bus_assert: AluBusRegister,
+    alu_op: AluOp,
+    transfer_bus_load: TransferBusRegister,
+    load_constant: bool,
+    transfer_bus_assert: TransferBusRegister,
+    no_fetch: bool,
+}
+
+impl Stage1Control {
+    fn decode(a: Pipe1AData, b: Pipe1BData) -> Result<Self, InvalidBitPattern<u8>> {
+        Ok(Self {
+            lhs_bus_assert: a.lhs_bus_assert_or_err()?,
+            rhs_bus_assert: a.rhs_bus_assert_or_err()?,
+            alu_op: a.alu_op_or_err()?,
+            transfer_bus_load: b.transfer_bus_load_or_err()?,
+            load_constant: b.load_constant(),
+            transfer_bus_assert: b.transfer_bus_assert_or_err()?,
+            no_fetch: b.no_fetch_or_err()?,
+        })
+    }
+}
+
+struct Stage2Control {
+    main_bus_assert: MainBusAssertDevice,
+    main_bus_load: MainBusLoadDevice,
+    increment_register: IncrementRegister,
+    address_bus_assert: AddressBusRegister,
+    bus_request: bool,
+    flip_pc_ra: bool,
+    break_clock: bool,
+}
+
+impl Stage2Control {
+    fn decode(a: Pipe2AData, b: Pipe2BData) -> Result<Self, InvalidBitPattern<u8>> {
+        Ok(Self {
+            main_bus_assert: a.main_bus_assert_or_err()?,
+            main_bus_load: a.main_bus_load_or_err()?,
+            increment_register: b.increment_register_or_err()?,
+            address_bus_assert: b.address_bus_assert_or_err()?,
+            bus_request: b.bus_request_or_err()?,
+            flip_pc_ra: b.flip_pc_ra_or_err()?,
+            break_clock: b.break_clock_or_err()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidInstruction {
+    opcode: u8,
+    address: u16,
+}
+
+impl InvalidInstruction {
+    #[inline]
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    #[inline]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+}
+
+impl Display for InvalidInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid instruction 0x{:0>2X} at 0x{:0>4X}",
+            self.opcode, self.address
+        )
+    }
+}
+
+/// The part of an instruction executed by the last pipeline stage in one clock cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicroState {
+    opcode: u8,
+    address: u16,
+    step: u8,
+    bus_address: Option<u16>,
+}
+
+impl MicroState {
+    #[inline]
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    /// Where the opcode was fetched from
+    #[inline]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// How many cycles of the instruction came before this one. Instructions that need more
+    /// than one cycle are fed back into the pipeline until they are done, starting at 0.
+    #[inline]
+    pub fn step(&self) -> u8 {
+        self.step
+    }
+
+    /// The memory address read or written in this cycle, e.g. to load an operand byte
+    #[inline]
+    pub fn bus_address(&self) -> Option<u16> {
+        self.bus_address
+    }
+}
+
+/// A push or pop that moved SP outside of the stack bounds, holding the new value of SP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFault {
+    Overflow(u16),
+    Underflow(u16),
+}
+
+impl Display for StackFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overflow(sp) => write!(f, "stack overflow, SP is 0x{sp:0>4X}"),
+            Self::Underflow(sp) => write!(f, "stack underflow, SP is 0x{sp:0>4X}"),
+        }
+    }
+}
+
+/// The instruction held by one of the pipeline stages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineStage {
+    opcode: u8,
+    address: u16,
+    step: u8,
+}
+
+impl PipelineStage {
+    /// The opcode, stalls are represented by NOPs
+    #[inline]
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    /// Where the opcode was fetched from
+    #[inline]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// How often the instruction was fed back into the pipeline, see `MicroState::step`
+    #[inline]
+    pub fn step(&self) -> u8 {
+        self.step
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, PartialEq, Eq)]
     pub struct Flags : u8 {
@@ -161,6 +318,7 @@ bitflags! {
         const CARRY_A = 1<<3;
         const CARRY_L = 1<<4;
         const PC_RA_FLIP = 1<<5;
+        const INTERRUPT_ENABLE = 1<<6;
     }
 }
 
@@ -268,7 +426,85 @@ fn get_ca_override(op: AluOp) -> Option<bool> {
 }
 
 const NOP: u8 = 0;
+// Decodes as a `nop` in the pipeline ROMs, only an interrupt handler returns with it
+const RETI: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    TL,
+    TH,
+    TX,
+    SI,
+    DI,
+    SP,
+    RA,
+    PC,
+    Flags,
+}
+
+impl Register {
+    #[inline]
+    pub fn is_8_bit(self) -> bool {
+        matches!(
+            self,
+            Self::A | Self::B | Self::C | Self::D | Self::TL | Self::TH | Self::Flags
+        )
+    }
+}
+
+// Registers saved when entering an interrupt and restored by `reti`. The ALU updates the flags
+// from its inputs every cycle, so those are saved together with the flags.
+#[derive(Clone, Copy)]
+struct InterruptContext {
+    tx: u16,
+    alu_lhs: u8,
+    alu_rhs: u8,
+    ca_override: Option<bool>,
+    flags: Flags,
+}
+
+impl InterruptContext {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u16(self.tx);
+        writer.u8(self.alu_lhs);
+        writer.u8(self.alu_rhs);
+        writer.u8(save_ca_override(self.ca_override));
+        writer.u8(self.flags.bits());
+    }
+
+    fn load_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        Ok(Self {
+            tx: reader.u16()?,
+            alu_lhs: reader.u8()?,
+            alu_rhs: reader.u8()?,
+            ca_override: load_ca_override(reader.u8()?)?,
+            flags: Flags::from_bits(reader.u8()?).ok_or(StateError::InvalidData)?,
+        })
+    }
+}
+
+fn save_ca_override(ca_override: Option<bool>) -> u8 {
+    match ca_override {
+        None => 0,
+        Some(false) => 1,
+        Some(true) => 2,
+    }
+}
 
+fn load_ca_override(value: u8) -> Result<Option<bool>, StateError> {
+    match value {
+        0 => Ok(None),
+        1 => Ok(Some(false)),
+        2 => Ok(Some(true)),
+        _ => Err(StateError::InvalidData),
+    }
+}
+
+#[derive(Clone)]
 pub struct Cpu {
     // special purpose registers
     pc_ra_0: u16,
@@ -295,6 +531,24 @@ pub struct Cpu {
     stage0_instruction: u8,
     stage1_instruction: u8,
     stage2_instruction: u8,
+    stage0_address: u16,
+    stage1_address: u16,
+    stage2_address: u16,
+    stage0_step: u8,
+    stage1_step: u8,
+    stage2_step: u8,
+    stage0_fetched: bool,
+    // Memory accessed by stage 2 in the last cycle
+    bus_address: Option<u16>,
+
+    pending_interrupt: Option<u16>,
+    // Registers of the interrupted code while its handler runs
+    interrupt_context: Option<InterruptContext>,
+
+    // Not part of the saved state, the system carries the bounds over when loading a state
+    // like watchpoints. The fault only lives until the end of the cycle.
+    stack_bounds: Option<Range<u16>>,
+    stack_fault: Option<StackFault>,
 }
 
 impl Cpu {
@@ -322,6 +576,19 @@ impl Cpu {
             stage0_instruction: NOP,
             stage1_instruction: NOP,
             stage2_instruction: NOP,
+            stage0_address: 0,
+            stage1_address: 0,
+            stage2_address: 0,
+            stage0_step: 0,
+            stage1_step: 0,
+            stage2_step: 0,
+            stage0_fetched: false,
+            bus_address: None,
+            stack_bounds: None,
+            stack_fault: None,
+
+            pending_interrupt: None,
+            interrupt_context: None,
         }
     }
 
@@ -337,6 +604,102 @@ impl Cpu {
         self.stage0_instruction = NOP;
         self.stage1_instruction = NOP;
         self.stage2_instruction = NOP;
+        self.stage0_address = 0;
+        self.stage1_address = 0;
+        self.stage2_address = 0;
+        self.stage0_step = 0;
+        self.stage1_step = 0;
+        self.stage2_step = 0;
+        self.stage0_fetched = false;
+        self.bus_address = None;
+        self.pending_interrupt = None;
+        self.interrupt_context = None;
+        self.stack_fault = None;
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.u16(self.pc_ra_0);
+        writer.u16(self.pc_ra_1);
+        writer.u16(self.sp);
+        writer.u16(self.si);
+        writer.u16(self.di);
+        writer.u16(self.tx);
+
+        writer.u8(self.a);
+        writer.u8(self.b);
+        writer.u8(self.c);
+        writer.u8(self.d);
+
+        writer.u8(self.constant);
+        writer.u8(self.alu_lhs);
+        writer.u8(self.alu_rhs);
+        writer.u8(save_ca_override(self.ca_override));
+        writer.u8(self.flags.bits());
+
+        writer.u8(self.stage0_instruction);
+        writer.u8(self.stage1_instruction);
+        writer.u8(self.stage2_instruction);
+        writer.u16(self.stage0_address);
+        writer.u16(self.stage1_address);
+        writer.u16(self.stage2_address);
+        writer.u8(self.stage0_step);
+        writer.u8(self.stage1_step);
+        writer.u8(self.stage2_step);
+        writer.bool(self.stage0_fetched);
+        writer.bool(self.bus_address.is_some());
+        writer.u16(self.bus_address.unwrap_or(0));
+
+        writer.bool(self.pending_interrupt.is_some());
+        writer.u16(self.pending_interrupt.unwrap_or(0));
+        writer.bool(self.interrupt_context.is_some());
+        if let Some(context) = &self.interrupt_context {
+            context.save_state(writer);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.pc_ra_0 = reader.u16()?;
+        self.pc_ra_1 = reader.u16()?;
+        self.sp = reader.u16()?;
+        self.si = reader.u16()?;
+        self.di = reader.u16()?;
+        self.tx = reader.u16()?;
+
+        self.a = reader.u8()?;
+        self.b = reader.u8()?;
+        self.c = reader.u8()?;
+        self.d = reader.u8()?;
+
+        self.constant = reader.u8()?;
+        self.alu_lhs = reader.u8()?;
+        self.alu_rhs = reader.u8()?;
+        self.ca_override = load_ca_override(reader.u8()?)?;
+        self.flags = Flags::from_bits(reader.u8()?).ok_or(StateError::InvalidData)?;
+
+        self.stage0_instruction = reader.u8()?;
+        self.stage1_instruction = reader.u8()?;
+        self.stage2_instruction = reader.u8()?;
+        self.stage0_address = reader.u16()?;
+        self.stage1_address = reader.u16()?;
+        self.stage2_address = reader.u16()?;
+        self.stage0_step = reader.u8()?;
+        self.stage1_step = reader.u8()?;
+        self.stage2_step = reader.u8()?;
+        self.stage0_fetched = reader.bool()?;
+        let has_bus_address = reader.bool()?;
+        let bus_address = reader.u16()?;
+        self.bus_address = has_bus_address.then_some(bus_address);
+
+        let has_interrupt = reader.bool()?;
+        let interrupt = reader.u16()?;
+        self.pending_interrupt = has_interrupt.then_some(interrupt);
+        self.interrupt_context = if reader.bool()? {
+            Some(InterruptContext::load_state(reader)?)
+        } else {
+            None
+        };
+
+        Ok(())
     }
 
     #[inline]
@@ -412,6 +775,128 @@ impl Cpu {
         self.flags
     }
 
+    /// The address of the instruction fetched in the last clock cycle, if any
+    #[inline]
+    pub fn fetched_instruction(&self) -> Option<u16> {
+        self.stage0_fetched.then_some(self.stage0_address)
+    }
+
+    /// Replaces the instruction that could not be decoded with a NOP, so the next clock skips it
+    pub(crate) fn replace_with_nop(&mut self, invalid: InvalidInstruction) {
+        if (self.stage0_instruction == invalid.opcode) && (self.stage0_address == invalid.address) {
+            self.stage0_instruction = NOP;
+        } else {
+            self.stage1_instruction = NOP;
+        }
+    }
+
+    /// The instructions in the fetch, decode and execute stages, in that order
+    #[inline]
+    pub fn pipeline(&self) -> [PipelineStage; 3] {
+        [
+            PipelineStage {
+                opcode: self.stage0_instruction,
+                address: self.stage0_address,
+                step: self.stage0_step,
+            },
+            PipelineStage {
+                opcode: self.stage1_instruction,
+                address: self.stage1_address,
+                step: self.stage1_step,
+            },
+            PipelineStage {
+                opcode: self.stage2_instruction,
+                address: self.stage2_address,
+                step: self.stage2_step,
+            },
+        ]
+    }
+
+    /// What the last pipeline stage did in the last clock cycle
+    #[inline]
+    pub fn microstep(&self) -> MicroState {
+        MicroState {
+            opcode: self.stage2_instruction,
+            address: self.stage2_address,
+            step: self.stage2_step,
+            bus_address: self.bus_address,
+        }
+    }
+
+    #[inline]
+    pub fn interrupts_enabled(&self) -> bool {
+        self.flags.contains(Flags::INTERRUPT_ENABLE)
+    }
+
+    /// Disabling interrupts drops a pending request, a handler that is already running can still
+    /// return with `reti`
+    #[inline]
+    pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+        self.flags.set(Flags::INTERRUPT_ENABLE, enabled);
+        if !enabled {
+            self.pending_interrupt = None;
+        }
+    }
+
+    /// Whether an interrupt handler is running, further interrupts are held back until it returns
+    #[inline]
+    pub fn in_interrupt(&self) -> bool {
+        self.interrupt_context.is_some()
+    }
+
+    #[inline]
+    pub fn stack_bounds(&self) -> Option<Range<u16>> {
+        self.stack_bounds.clone()
+    }
+
+    /// Pushes may not move SP below the start of `bounds` and pops may not move it past the end,
+    /// which is where SP points when the stack is empty. Moving SP directly is not checked.
+    #[inline]
+    pub fn set_stack_bounds(&mut self, bounds: Option<Range<u16>>) {
+        self.stack_bounds = bounds;
+    }
+
+    /// The stack fault caused in the last clock cycle, if any
+    #[inline]
+    pub fn take_stack_fault(&mut self) -> Option<StackFault> {
+        self.stack_fault.take()
+    }
+
+    fn push_sp(&mut self) {
+        self.sp = self.sp.wrapping_sub(1);
+        if let Some(bounds) = &self.stack_bounds {
+            // SP wrapping around from 0 ends up above the bounds
+            if (self.sp < bounds.start) || (self.sp >= bounds.end) {
+                self.stack_fault = Some(StackFault::Overflow(self.sp));
+            }
+        }
+    }
+
+    fn pop_sp(&mut self) {
+        self.sp = self.sp.wrapping_add(1);
+        if let Some(bounds) = &self.stack_bounds {
+            if (self.sp > bounds.end) || (self.sp <= bounds.start) {
+                self.stack_fault = Some(StackFault::Underflow(self.sp));
+            }
+        }
+    }
+
+    // Requests are ignored while interrupts are disabled or another interrupt is still pending
+    pub fn request_interrupt(&mut self, vector: u16) {
+        if self.interrupts_enabled() && self.pending_interrupt.is_none() {
+            self.pending_interrupt = Some(vector);
+        }
+    }
+
+    #[inline]
+    fn write_pc(&mut self, value: u16) {
+        if self.flags.contains(Flags::PC_RA_FLIP) {
+            self.pc_ra_1 = value;
+        } else {
+            self.pc_ra_0 = value;
+        }
+    }
+
     #[inline]
     fn inc_pc(&mut self) {
         if self.flags.contains(Flags::PC_RA_FLIP) {
@@ -421,20 +906,140 @@ impl Cpu {
         }
     }
 
+    /// Continues execution at `value`. Instructions already in the pipeline are discarded,
+    /// so the next instruction is fetched from the new address.
+    pub fn set_pc(&mut self, value: u16) {
+        self.write_pc(value);
+        self.stage0_instruction = NOP;
+        self.stage1_instruction = NOP;
+        self.stage2_instruction = NOP;
+        self.stage0_step = 0;
+        self.stage1_step = 0;
+        self.stage2_step = 0;
+        self.stage0_fetched = false;
+    }
+
+    /// PC and RA are swapped by the pipeline, this always sets the register currently acting as RA
+    #[inline]
+    pub fn set_ra(&mut self, value: u16) {
+        if self.flags.contains(Flags::PC_RA_FLIP) {
+            self.pc_ra_0 = value;
+        } else {
+            self.pc_ra_1 = value;
+        }
+    }
+
+    #[inline]
+    pub fn set_sp(&mut self, value: u16) {
+        self.sp = value;
+    }
+
     #[inline]
-    fn set_tl(&mut self, value: u8) {
+    pub fn set_si(&mut self, value: u16) {
+        self.si = value;
+    }
+
+    #[inline]
+    pub fn set_di(&mut self, value: u16) {
+        self.di = value;
+    }
+
+    /// TL and TH are the low and high byte of TX, so this changes both of them
+    #[inline]
+    pub fn set_tx(&mut self, value: u16) {
+        self.tx = value;
+    }
+
+    #[inline]
+    pub fn set_a(&mut self, value: u8) {
+        self.a = value;
+    }
+
+    #[inline]
+    pub fn set_b(&mut self, value: u8) {
+        self.b = value;
+    }
+
+    #[inline]
+    pub fn set_c(&mut self, value: u8) {
+        self.c = value;
+    }
+
+    #[inline]
+    pub fn set_d(&mut self, value: u8) {
+        self.d = value;
+    }
+
+    /// Sets the low byte of TX
+    #[inline]
+    pub fn set_tl(&mut self, value: u8) {
         let mut bytes = self.tx.to_le_bytes();
         bytes[0] = value;
         self.tx = u16::from_le_bytes(bytes);
     }
 
+    /// Sets the high byte of TX
     #[inline]
-    fn set_th(&mut self, value: u8) {
+    pub fn set_th(&mut self, value: u8) {
         let mut bytes = self.tx.to_le_bytes();
         bytes[1] = value;
         self.tx = u16::from_le_bytes(bytes);
     }
 
+    /// Sets the status flags. The PC-RA flip is controlled by the pipeline and cannot be changed,
+    /// the interrupt enable flag behaves like [`Cpu::set_interrupts_enabled`].
+    pub fn set_flags(&mut self, flags: Flags) {
+        let flip = self.flags.contains(Flags::PC_RA_FLIP);
+        let interrupts_enabled = flags.contains(Flags::INTERRUPT_ENABLE);
+
+        self.flags = flags.difference(Flags::PC_RA_FLIP | Flags::INTERRUPT_ENABLE);
+        self.flags.set(Flags::PC_RA_FLIP, flip);
+        self.set_interrupts_enabled(interrupts_enabled);
+    }
+
+    pub fn register(&self, register: Register) -> u16 {
+        match register {
+            Register::A => self.a as u16,
+            Register::B => self.b as u16,
+            Register::C => self.c as u16,
+            Register::D => self.d as u16,
+            Register::TL => self.tl() as u16,
+            Register::TH => self.th() as u16,
+            Register::TX => self.tx,
+            Register::SI => self.si,
+            Register::DI => self.di,
+            Register::SP => self.sp,
+            Register::RA => self.ra(),
+            Register::PC => self.pc(),
+            Register::Flags => self.flags.bits() as u16,
+        }
+    }
+
+    /// Fails if the value does not fit into the register
+    pub fn set_register(&mut self, register: Register, value: u16) -> Result<(), ()> {
+        if register.is_8_bit() && (value > 0xFF) {
+            return Err(());
+        }
+
+        match register {
+            Register::A => self.set_a(value as u8),
+            Register::B => self.set_b(value as u8),
+            Register::C => self.set_c(value as u8),
+            Register::D => self.set_d(value as u8),
+            Register::TL => self.set_tl(value as u8),
+            Register::TH => self.set_th(value as u8),
+            Register::TX => self.set_tx(value),
+            Register::SI => self.set_si(value),
+            Register::DI => self.set_di(value),
+            Register::SP => self.set_sp(value),
+            Register::RA => self.set_ra(value),
+            Register::PC => self.set_pc(value),
+            Register::Flags => self.set_flags(Flags::from_bits_truncate(value as u8)),
+        }
+
+        Ok(())
+    }
+
     fn execute_alu(&mut self) -> u8 {
         let lhs_sign = (self.alu_lhs & 0x80) != 0;
         let rhs_sign = (self.alu_rhs & 0x80) != 0;
@@ -479,7 +1084,62 @@ impl Cpu {
         }
     }
 
-    // Returns true if a break instruction was reached
+    // The pipeline ROMs have no interrupt microcode, so entering an interrupt is done
+    // directly: the instruction in stage 0 is discarded, its address is pushed onto the
+    // stack like `push tx` would and the PC is set to the vector. TX and the flags are kept
+    // in the interrupt context, so the handler is free to use them and to branch.
+    //
+    // The handler returns with `reti`, which restores TX and the flags and pops the return
+    // address. Every other register it uses has to be preserved by the handler itself.
+    // Interrupts don't nest, requests arriving while the handler runs are taken after `reti`.
+    fn enter_interrupt(&mut self, memory: &mut Memory, vga: &mut Vga, vector: u16) {
+        self.interrupt_context = Some(InterruptContext {
+            tx: self.tx,
+            alu_lhs: self.alu_lhs,
+            alu_rhs: self.alu_rhs,
+            ca_override: self.ca_override,
+            flags: self.flags,
+        });
+
+        let [low, high] = self.pc().wrapping_sub(1).to_le_bytes();
+        self.push_sp();
+        memory.write(vga, self.sp, low);
+        self.push_sp();
+        memory.write(vga, self.sp, high);
+
+        self.write_pc(vector);
+        self.discard_fetch();
+    }
+
+    fn return_from_interrupt(&mut self, memory: &Memory, vga: &Vga, context: InterruptContext) {
+        let high = memory.read(vga, self.sp);
+        self.pop_sp();
+        let low = memory.read(vga, self.sp);
+        self.pop_sp();
+
+        // Interrupts may have been disabled by the host in the meantime
+        let interrupts_enabled = self.interrupts_enabled();
+        self.flags = context.flags;
+        self.flags.set(Flags::INTERRUPT_ENABLE, interrupts_enabled);
+        self.alu_lhs = context.alu_lhs;
+        self.alu_rhs = context.alu_rhs;
+        self.ca_override = context.ca_override;
+        self.tx = context.tx;
+
+        self.write_pc(u16::from_le_bytes([low, high]));
+        self.discard_fetch();
+    }
+
+    #[inline]
+    fn discard_fetch(&mut self) {
+        self.stage0_instruction = NOP;
+        self.stage0_step = 0;
+        self.stage0_fetched = false;
+    }
+
+    // Returns true if a break instruction was reached.
+    // If an instruction cannot be decoded the pipeline does not advance, but entering or returning
+    // from an interrupt at the start of the cycle has already updated the stack and the PC.
     pub fn clock(
         &mut self,
         memory: &mut Memory,
@@ -488,36 +1148,66 @@ impl Cpu {
         vga: &mut Vga,
         controler: &mut Controler,
         spi: &mut Spi,
-    ) -> Result<bool, InvalidBitPattern<u8>> {
-        // Move instruction stream forward
-        self.stage2_instruction = self.stage1_instruction;
-        self.stage1_instruction = self.stage0_instruction;
+    ) -> Result<bool, InvalidInstruction> {
+        // Interrupts are only taken on an instruction boundary, meaning stage 0 holds an opcode
+        // fetched from memory and no other instruction is in flight anymore.
+        // `reti` is padded with a `nop` by the assembler, so it always starts on a boundary.
+        if self.stage0_fetched && (self.stage1_instruction == NOP) {
+            if self.stage0_instruction == RETI {
+                if let Some(context) = self.interrupt_context.take() {
+                    self.return_from_interrupt(memory, vga, context);
+                }
+            } else if self.interrupt_context.is_none() {
+                if let Some(vector) = self.pending_interrupt.take() {
+                    self.enter_interrupt(memory, vga, vector);
+                }
+            }
+        }
 
         // Decode instructions in the pipeline using ROMs
-        let flag_value = ((self.flags.bits() as usize) | 0x40) << 8;
+        // The interrupt enable flag is not connected to the ROMs
+        let rom_flags = self.flags.difference(Flags::INTERRUPT_ENABLE);
+        let flag_value = ((rom_flags.bits() as usize) | 0x40) << 8;
 
-        let pipe1_address = (self.stage1_instruction as usize) | flag_value;
+        let pipe1_address = (self.stage0_instruction as usize) | flag_value;
         let pipe1a_data = Pipe1AData::from_bytes([PIPE_1A[pipe1_address]]);
         let pipe1b_data = Pipe1BData::from_bytes([PIPE_1B[pipe1_address]]);
+        let stage1 =
+            Stage1Control::decode(pipe1a_data, pipe1b_data).map_err(|_| InvalidInstruction {
+                opcode: self.stage0_instruction,
+                address: self.stage0_address,
+            })?;
 
-        let pipe2_address = (self.stage2_instruction as usize) | flag_value;
+        let pipe2_address = (self.stage1_instruction as usize) | flag_value;
         let pipe2a_data = Pipe2AData::from_bytes([PIPE_2A[pipe2_address]]);
         let pipe2b_data = Pipe2BData::from_bytes([PIPE_2B[pipe2_address]]);
+        let stage2 =
+            Stage2Control::decode(pipe2a_data, pipe2b_data).map_err(|_| InvalidInstruction {
+                opcode: self.stage1_instruction,
+                address: self.stage1_address,
+            })?;
+
+        // Move instruction stream forward
+        self.stage2_instruction = self.stage1_instruction;
+        self.stage2_address = self.stage1_address;
+        self.stage2_step = self.stage1_step;
+        self.stage1_instruction = self.stage0_instruction;
+        self.stage1_address = self.stage0_address;
+        self.stage1_step = self.stage0_step;
 
         // The state of the PC-RA flipping is defined by the pipeline ROM output
-        self.flags
-            .set(Flags::PC_RA_FLIP, pipe2b_data.flip_pc_ra_or_err()?);
+        self.flags.set(Flags::PC_RA_FLIP, stage2.flip_pc_ra);
 
         // Wether we can fetch this cycle based on pipeline stage 1
-        let fetch_stage1 = !pipe1b_data.no_fetch_or_err()?;
+        let fetch_stage1 = !stage1.no_fetch;
         // Wether we can fetch and increment the PC this cycle based on pipeline stage 2
-        let fetch_stage2 = !pipe2b_data.bus_request_or_err()?;
+        let fetch_stage2 = !stage2.bus_request;
 
         //
         // --------------------- Stage 2 ---------------------
         //
 
-        let address = match pipe2b_data.address_bus_assert_or_err()? {
+        let address = match stage2.address_bus_assert {
             AddressBusRegister::None => 0,
             AddressBusRegister::PcRa0 => self.pc_ra_0,
             AddressBusRegister::PcRa1 => self.pc_ra_1,
@@ -529,6 +1219,7 @@ impl Cpu {
 
         let alu_result = self.execute_alu();
         let mem_data = memory.read(vga, address);
+        self.bus_address = stage2.bus_request.then_some(address);
 
         // If stage 2 doesn't access the memory bus, increment PC
         if fetch_stage2 {
@@ -537,7 +1228,7 @@ impl Cpu {
             self.inc_pc();
         }
 
-        let main_bus = match pipe2a_data.main_bus_assert_or_err()? {
+        let main_bus = match stage2.main_bus_assert {
             MainBusAssertDevice::None => 0,
             MainBusAssertDevice::A => self.a,
             MainBusAssertDevice::B => self.b,
@@ -555,7 +1246,7 @@ impl Cpu {
             MainBusAssertDevice::MemBridge => mem_data,
         };
 
-        match pipe2a_data.main_bus_load_or_err()? {
+        match stage2.main_bus_load {
             MainBusLoadDevice::None => {}
             MainBusLoadDevice::A => self.a = main_bus,
             MainBusLoadDevice::B => self.b = main_bus,
@@ -571,9 +1262,9 @@ impl Cpu {
             MainBusLoadDevice::MemBridge => memory.write(vga, address, main_bus),
         }
 
-        match pipe2b_data.increment_register_or_err()? {
+        match stage2.increment_register {
             IncrementRegister::None => {}
-            IncrementRegister::Sp => self.sp = self.sp.wrapping_add(1),
+            IncrementRegister::Sp => self.pop_sp(),
             IncrementRegister::Si => self.si = self.si.wrapping_add(1),
             IncrementRegister::Di => self.di = self.di.wrapping_add(1),
         }
@@ -582,9 +1273,9 @@ impl Cpu {
         // --------------------- Stage 1 ---------------------
         //
 
-        let lhs_bus = self.get_alu_bus_value(pipe1a_data.lhs_bus_assert_or_err()?);
-        let rhs_bus = self.get_alu_bus_value(pipe1a_data.rhs_bus_assert_or_err()?);
-        let alu_op = pipe1a_data.alu_op_or_err()?;
+        let lhs_bus = self.get_alu_bus_value(stage1.lhs_bus_assert);
+        let rhs_bus = self.get_alu_bus_value(stage1.rhs_bus_assert);
+        let alu_op = stage1.alu_op;
 
         let (lhs_out, cl_out) =
             execute_alu_lhs_op(lhs_bus, self.flags.contains(Flags::CARRY_L), alu_op.into());
@@ -597,21 +1288,20 @@ impl Cpu {
             self.ca_override = get_ca_override(alu_op);
         }
 
-        if pipe1b_data.load_constant() {
-            match pipe1b_data.transfer_bus_load_or_err()? {
+        if stage1.load_constant {
+            match stage1.transfer_bus_load {
                 TransferBusRegister::None => self.constant = mem_data,
                 TransferBusRegister::PcRa0 => self.pc_ra_0 = self.pc_ra_0.wrapping_sub(1),
                 TransferBusRegister::PcRa1 => self.pc_ra_1 = self.pc_ra_1.wrapping_sub(1),
-                TransferBusRegister::Sp => self.sp = self.sp.wrapping_sub(1),
+                TransferBusRegister::Sp => self.push_sp(),
                 TransferBusRegister::Si => self.si = self.si.wrapping_sub(1),
                 TransferBusRegister::Di => self.di = self.di.wrapping_sub(1),
                 TransferBusRegister::Tx => self.tx = self.tx.wrapping_sub(1),
             }
         } else {
-            let transfer_bus =
-                self.get_transfer_bus_value(pipe1b_data.transfer_bus_assert_or_err()?);
+            let transfer_bus = self.get_transfer_bus_value(stage1.transfer_bus_assert);
 
-            match pipe1b_data.transfer_bus_load_or_err()? {
+            match stage1.transfer_bus_load {
                 TransferBusRegister::None => {}
                 TransferBusRegister::PcRa0 => self.pc_ra_0 = transfer_bus,
                 TransferBusRegister::PcRa1 => self.pc_ra_1 = transfer_bus,
@@ -627,19 +1317,26 @@ impl Cpu {
         //
 
         // Fetch
+        self.stage0_fetched = fetch_stage1 && fetch_stage2;
         if fetch_stage1 && fetch_stage2 {
             // We can safely fetch
             self.stage0_instruction = mem_data;
+            self.stage0_address = address;
+            self.stage0_step = 0;
         } else if fetch_stage1 || fetch_stage2 {
             // One of the stages prevents the fetch
             self.stage0_instruction = NOP;
+            self.stage0_address = address;
+            self.stage0_step = 0;
         } else {
             // Both stages prevent the fetch. This means we have a pipeline contention,
             // so we have to feed the failed instruction in stage 1 back in.
             self.stage0_instruction = self.stage1_instruction;
+            self.stage0_address = self.stage1_address;
+            self.stage0_step = self.stage1_step.wrapping_add(1);
         }
 
-        pipe2b_data.break_clock_or_err()
+        Ok(stage2.break_clock)
     }
 }
 
@@ -1,6 +1,113 @@
+use crate::state::{StateError, StateReader, StateWriter};
 use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use chrono::{DateTime, Datelike, Local, Timelike};
+use std::collections::BTreeSet;
+
+/// Number of VGA reads per frame that were blocked by a CPU write to the same memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConflictStats {
+    framebuffer: u32,
+    palette: u32,
+    tile_data: u32,
+}
+
+impl ConflictStats {
+    #[inline]
+    pub fn framebuffer(&self) -> u32 {
+        self.framebuffer
+    }
+
+    #[inline]
+    pub fn palette(&self) -> u32 {
+        self.palette
+    }
+
+    #[inline]
+    pub fn tile_data(&self) -> u32 {
+        self.tile_data
+    }
+
+    #[inline]
+    pub fn total(&self) -> u32 {
+        self.framebuffer + self.palette + self.tile_data
+    }
+}
+
+/// A write that matched a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    address: u16,
+    value: u8,
+}
+
+impl WatchHit {
+    #[inline]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
 
+    #[inline]
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+}
+
+/// A named part of the address space with a special purpose
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    name: &'static str,
+    start: u16,
+    last: u16,
+}
+
+impl MemoryRegion {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// The last address that is part of the region
+    #[inline]
+    pub fn last(&self) -> u16 {
+        self.last
+    }
+}
+
+/// The special regions of the memory map, ordered by address
+pub const MEMORY_REGIONS: [MemoryRegion; 5] = [
+    MemoryRegion {
+        name: "Mapped IO",
+        start: Memory::MAP_RANGE_START,
+        last: Memory::MAP_RANGE_END - 1,
+    },
+    MemoryRegion {
+        name: "Palette",
+        start: Memory::PALETTE_START,
+        last: Memory::PALETTE_END - 1,
+    },
+    MemoryRegion {
+        name: "Tile Data",
+        start: Memory::TILE_DATA_START,
+        last: Memory::TILE_DATA_END - 1,
+    },
+    MemoryRegion {
+        name: "Framebuffer",
+        start: Memory::FRAMEBUFFER_START,
+        last: Memory::FRAMEBUFFER_END - 1,
+    },
+    MemoryRegion {
+        name: "Monitor",
+        start: Memory::MONITOR_START,
+        last: u16::MAX,
+    },
+];
+
+#[derive(Clone)]
 pub struct Memory {
     data: Box<[u8]>,
     palette_data: Box<[u8]>,
@@ -11,6 +118,13 @@ pub struct Memory {
     palette_high: u8,
     tile_data_conflict: bool,
     last_tile_data: u8,
+    dirty_pages: [u64; 4],
+    conflict_emulation: bool,
+    frame_conflicts: ConflictStats,
+    last_frame_conflicts: ConflictStats,
+    // Watchpoints without a value match any write to the address
+    watchpoints: BTreeSet<(u16, Option<u8>)>,
+    watch_hit: Option<WatchHit>,
 }
 
 impl Memory {
@@ -30,6 +144,10 @@ impl Memory {
     const TILE_DATA_END: u16 = 0xC000;
     const TILE_DATA_MASK: u16 = 0x1FFF;
 
+    const MONITOR_START: u16 = 0xE000;
+
+    const PAGE_SIZE: usize = 0x100;
+
     #[inline]
     #[allow(dead_code)]
     pub fn new() -> Self {
@@ -43,15 +161,111 @@ impl Memory {
             palette_high: 0,
             tile_data_conflict: false,
             last_tile_data: 0,
+            dirty_pages: [u64::MAX; 4],
+            conflict_emulation: true,
+            frame_conflicts: ConflictStats::default(),
+            last_frame_conflicts: ConflictStats::default(),
+            watchpoints: BTreeSet::new(),
+            watch_hit: None,
         }
     }
 
+    #[inline]
+    pub fn add_watchpoint(&mut self, addr: u16, value: Option<u8>) -> bool {
+        self.watchpoints.insert((addr, value))
+    }
+
+    #[inline]
+    pub fn remove_watchpoint(&mut self, addr: u16, value: Option<u8>) -> bool {
+        self.watchpoints.remove(&(addr, value))
+    }
+
+    #[inline]
+    pub fn watchpoints(&self) -> impl Iterator<Item = (u16, Option<u8>)> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    /// Returns the first write that matched a watchpoint since the last call
+    #[inline]
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.data);
+        writer.bytes(&self.palette_data);
+        writer.bool(self.framebuffer_conflict);
+        writer.u8(self.last_framebuffer_data);
+        writer.bool(self.palette_conflict);
+        writer.bytes(&self.last_palette_data.channels);
+        writer.u8(self.palette_high);
+        writer.bool(self.tile_data_conflict);
+        writer.u8(self.last_tile_data);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.data.copy_from_slice(reader.bytes(self.data.len())?);
+        self.palette_data
+            .copy_from_slice(reader.bytes(self.palette_data.len())?);
+        self.framebuffer_conflict = reader.bool()?;
+        self.last_framebuffer_data = reader.u8()?;
+        self.palette_conflict = reader.bool()?;
+        self.last_palette_data
+            .channels
+            .copy_from_slice(reader.bytes(4)?);
+        self.palette_high = reader.u8()?;
+        self.tile_data_conflict = reader.bool()?;
+        self.last_tile_data = reader.u8()?;
+
+        self.dirty_pages = [u64::MAX; 4];
+        Ok(())
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self, addr: usize) {
+        let page = addr / Self::PAGE_SIZE;
+        self.dirty_pages[page / 64] |= 1 << (page % 64);
+    }
+
     pub fn init_region(&mut self, data: &[u8], addr: u16) {
         let start = addr as usize;
         let end = start + data.len();
         assert!(end <= 0x10000);
 
         self.data[start..end].copy_from_slice(data);
+        for addr in (start..end).step_by(Self::PAGE_SIZE) {
+            self.mark_dirty(addr);
+        }
+        if end > start {
+            self.mark_dirty(end - 1);
+        }
+    }
+
+    // Copies all pages that changed since the last call into `view`.
+    // The VGA registers are always copied since the VGA can change them without a write.
+    pub fn update_view(&mut self, vga: &Vga, view: &mut [u8]) {
+        assert_eq!(view.len(), 0x10000);
+
+        let dirty_pages = std::mem::take(&mut self.dirty_pages);
+        for (i, mut mask) in dirty_pages.into_iter().enumerate() {
+            while mask != 0 {
+                let page = i * 64 + (mask.trailing_zeros() as usize);
+                mask &= mask - 1;
+
+                let start = page * Self::PAGE_SIZE;
+                let end = start + Self::PAGE_SIZE;
+                if (start as u16) == Self::MAP_RANGE_START {
+                    // Memory mapped IO range, only the VGA registers read back a value
+                    view[start..end].fill(0);
+                } else {
+                    view[start..end].copy_from_slice(&self.data[start..end]);
+                }
+            }
+        }
+
+        for addr in Self::VGA_RANGE_START..Self::VGA_RANGE_END {
+            view[addr as usize] = self.read(vga, addr);
+        }
     }
 
     pub fn read(&self, vga: &Vga, addr: u16) -> u8 {
@@ -70,6 +284,17 @@ impl Memory {
     }
 
     pub fn write(&mut self, vga: &mut Vga, addr: u16, value: u8) {
+        if !self.watchpoints.is_empty()
+            && self.watch_hit.is_none()
+            && (self.watchpoints.contains(&(addr, None))
+                || self.watchpoints.contains(&(addr, Some(value))))
+        {
+            self.watch_hit = Some(WatchHit {
+                address: addr,
+                value,
+            });
+        }
+
         if (addr >= Self::MAP_RANGE_START) && (addr < Self::MAP_RANGE_END) {
             // Memory mapped IO range
 
@@ -99,12 +324,14 @@ impl Memory {
             }
 
             self.data[addr as usize] = value;
+            self.mark_dirty(addr as usize);
         }
     }
 
     pub fn framebuffer_read(&mut self, addr: u16) -> u8 {
         // If we currently have a bus conflict we have to return the last value that was read by the VGA.
-        if self.framebuffer_conflict {
+        if self.framebuffer_conflict && self.conflict_emulation {
+            self.frame_conflicts.framebuffer += 1;
             self.last_framebuffer_data
         } else {
             let addr = ((addr & Self::FRAMEBUFFER_MASK) + Self::FRAMEBUFFER_START) as usize;
@@ -116,7 +343,8 @@ impl Memory {
 
     pub fn palette_read(&mut self, index: u8) -> Color {
         // If we currently have a bus conflict we have to return the last value that was read by the VGA.
-        if self.palette_conflict {
+        if self.palette_conflict && self.conflict_emulation {
+            self.frame_conflicts.palette += 1;
             self.last_palette_data
         } else {
             let palette_addr_high = (self.palette_high as u16) << 10;
@@ -133,7 +361,8 @@ impl Memory {
 
     pub fn tile_data_read(&mut self, addr: u16) -> u8 {
         // If we currently have a bus conflict we have to return the last value that was read by the VGA.
-        if self.tile_data_conflict {
+        if self.tile_data_conflict && self.conflict_emulation {
+            self.frame_conflicts.tile_data += 1;
             self.last_tile_data
         } else {
             let addr = ((addr & Self::TILE_DATA_MASK) + Self::TILE_DATA_START) as usize;
@@ -149,8 +378,30 @@ impl Memory {
         self.palette_conflict = false;
         self.tile_data_conflict = false;
     }
+
+    #[inline]
+    pub fn conflict_emulation(&self) -> bool {
+        self.conflict_emulation
+    }
+
+    #[inline]
+    pub fn set_conflict_emulation(&mut self, enabled: bool) {
+        self.conflict_emulation = enabled;
+    }
+
+    /// Bus conflicts during the last completed frame
+    #[inline]
+    pub fn conflict_stats(&self) -> ConflictStats {
+        self.last_frame_conflicts
+    }
+
+    #[inline]
+    fn finish_conflict_frame(&mut self) {
+        self.last_frame_conflicts = std::mem::take(&mut self.frame_conflicts);
+    }
 }
 
+#[derive(Clone)]
 struct Queue<T, const N: usize> {
     items: [Option<T>; N],
     start: usize,
@@ -208,9 +459,43 @@ impl<T, const N: usize> Queue<T, N> {
     }
 }
 
+impl<const N: usize> Queue<u8, N> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.len as u8);
+        for i in 0..self.len {
+            writer.u8(self.items[(self.start + i) % N].unwrap());
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let len = reader.u8()? as usize;
+        if len > N {
+            return Err(StateError::InvalidData);
+        }
+
+        *self = Self::new();
+        for _ in 0..len {
+            self.enqueue(reader.u8()?);
+        }
+
+        Ok(())
+    }
+}
+
+// 8N1 framing: start bit, 8 data bits and stop bit
+const UART_FRAME_BITS: u8 = 10;
+
+#[derive(Clone)]
 pub struct Uart {
     receive_fifo: Queue<u8, 8>,
     transmit_fifo: Queue<u8, 8>,
+    // Byte currently being shifted out and how many bits of its frame are left
+    transmit_shift: u8,
+    transmit_bits: u8,
+    // Same for the byte currently being shifted in from the host
+    receive_shift: u8,
+    receive_bits: u8,
+    loopback: bool,
 }
 
 impl Uart {
@@ -219,15 +504,55 @@ impl Uart {
         Self {
             receive_fifo: Queue::new(),
             transmit_fifo: Queue::new(),
+            transmit_shift: 0,
+            transmit_bits: 0,
+            receive_shift: 0,
+            receive_bits: 0,
+            loopback: false,
+        }
+    }
+
+    #[inline]
+    pub fn loopback(&self) -> bool {
+        self.loopback
+    }
+
+    /// Routes transmitted bytes back into the receive FIFO instead of to the host
+    #[inline]
+    pub fn set_loopback(&mut self, loopback: bool) {
+        self.loopback = loopback;
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        self.receive_fifo.save_state(writer);
+        self.transmit_fifo.save_state(writer);
+        writer.u8(self.transmit_shift);
+        writer.u8(self.transmit_bits);
+        writer.u8(self.receive_shift);
+        writer.u8(self.receive_bits);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.receive_fifo.load_state(reader)?;
+        self.transmit_fifo.load_state(reader)?;
+        self.transmit_shift = reader.u8()?;
+        self.transmit_bits = reader.u8()?;
+        self.receive_shift = reader.u8()?;
+        self.receive_bits = reader.u8()?;
+
+        if (self.transmit_bits > UART_FRAME_BITS) || (self.receive_bits > UART_FRAME_BITS) {
+            return Err(StateError::InvalidData);
         }
+
+        Ok(())
     }
 
     // Lower 4 bits count how many received bytes are ready to be read,
-    // upper 4 bits count how many bytes have yet to be transmitted
+    // upper 4 bits count how many bytes have yet to be transmitted, including the one in flight
     #[inline]
     pub fn read_ctrl(&mut self) -> u8 {
         let receive_bytes = self.receive_fifo.len() as u8;
-        let transmit_bytes = self.transmit_fifo.len() as u8;
+        let transmit_bytes = (self.transmit_fifo.len() as u8) + u8::from(self.transmit_bits > 0);
 
         receive_bytes | (transmit_bytes << 4)
     }
@@ -239,22 +564,65 @@ impl Uart {
 
     #[inline]
     pub fn write_data(&mut self, value: u8) {
-        let full = !self.transmit_fifo.enqueue(value);
-        assert!(!full, "Cannot transmit any more data, buffer is full");
+        if !self.transmit_fifo.enqueue(value) {
+            log::warn!("UART transmit overrun, dropped 0x{value:0>2X}");
+        }
     }
 
-    #[inline]
-    pub fn host_read(&mut self) -> Option<u8> {
-        self.transmit_fifo.dequeue()
+    /// Shifts out one bit, has to be called once per baud period.
+    /// Returns a transmitted byte once the stop bit of its frame has been sent,
+    /// in loopback mode the byte has been received again at that point.
+    pub fn clock_baud(&mut self) -> Option<u8> {
+        if self.transmit_bits == 0 {
+            self.transmit_shift = self.transmit_fifo.dequeue()?;
+            self.transmit_bits = UART_FRAME_BITS;
+        }
+
+        self.transmit_bits -= 1;
+        if self.transmit_bits > 0 {
+            return None;
+        }
+
+        if self.loopback {
+            self.receive(self.transmit_shift);
+        }
+
+        Some(self.transmit_shift)
+    }
+
+    // Overruns drop the byte like the hardware does, regardless of where it came from
+    fn receive(&mut self, value: u8) {
+        if !self.receive_fifo.enqueue(value) {
+            log::warn!("UART receive overrun, dropped 0x{value:0>2X}");
+        }
     }
 
+    /// Writes a byte into the receive FIFO right away, bypassing the frame timing
     #[inline]
     pub fn host_write(&mut self, value: u8) {
-        let full = !self.receive_fifo.enqueue(value);
-        assert!(!full, "Cannot receive any more data, buffer is full");
+        self.receive(value);
+    }
+
+    /// Shifts in one bit sent by the host, has to be called once per baud period.
+    /// `next` is asked for the byte to send whenever no frame is in progress.
+    /// Returns the byte once the stop bit of its frame has been received.
+    pub fn host_clock_baud(&mut self, next: impl FnOnce() -> Option<u8>) -> Option<u8> {
+        if self.receive_bits == 0 {
+            self.receive_shift = next()?;
+            self.receive_bits = UART_FRAME_BITS;
+        }
+
+        self.receive_bits -= 1;
+        if self.receive_bits > 0 {
+            return None;
+        }
+
+        self.receive(self.receive_shift);
+        Some(self.receive_shift)
     }
 }
 
+#[derive(Clone)]
 struct SquareWaveChannel {
     volume: f32,
     frequency: u16,
@@ -273,6 +641,21 @@ impl SquareWaveChannel {
         }
     }
 
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.f32(self.volume);
+        writer.u16(self.frequency);
+        writer.u16(self.counter);
+        writer.f32(self.state);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.volume = reader.f32()?;
+        self.frequency = reader.u16()?;
+        self.counter = reader.u16()?;
+        self.state = reader.f32()?;
+        Ok(())
+    }
+
     fn write(&mut self, data: u16) {
         self.volume = 1.0 - (((data >> 12) as f32) / (0xF as f32));
         self.frequency = data & 0x0FFF;
@@ -297,6 +680,7 @@ enum AudioWriteCycleState {
     HighData,
 }
 
+#[derive(Clone)]
 pub struct Audio {
     channel0: SquareWaveChannel,
     channel1: SquareWaveChannel,
@@ -323,6 +707,38 @@ impl Audio {
         }
     }
 
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        self.channel0.save_state(writer);
+        self.channel1.save_state(writer);
+        self.channel2.save_state(writer);
+        self.channel3.save_state(writer);
+
+        writer.u8(match self.cycle_state {
+            AudioWriteCycleState::ChannelSelect => 0,
+            AudioWriteCycleState::LowData => 1,
+            AudioWriteCycleState::HighData => 2,
+        });
+        writer.u8(self.channel_index);
+        writer.u8(self.low_data);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.channel0.load_state(reader)?;
+        self.channel1.load_state(reader)?;
+        self.channel2.load_state(reader)?;
+        self.channel3.load_state(reader)?;
+
+        self.cycle_state = match reader.u8()? {
+            0 => AudioWriteCycleState::ChannelSelect,
+            1 => AudioWriteCycleState::LowData,
+            2 => AudioWriteCycleState::HighData,
+            _ => return Err(StateError::InvalidData),
+        };
+        self.channel_index = reader.u8()?;
+        self.low_data = reader.u8()?;
+        Ok(())
+    }
+
     #[inline]
     pub fn write_data(&mut self, value: u8) {
         let reset_cycle = (self.channel_index & 0x80) != 0;
@@ -385,6 +801,7 @@ impl Color {
     }
 }
 
+#[derive(Clone)]
 pub struct PixelBuffer {
     pixels: Box<[Color]>,
     width: usize,
@@ -421,6 +838,10 @@ impl PixelBuffer {
     }
 }
 
+const H_PIXELS: u16 = 800; // Number of pixels horizontally (including blanking)
+const V_PIXELS: u16 = 525; // Number of pixels vertically (including blanking)
+
+#[derive(Clone)]
 pub struct Vga {
     buffer: PixelBuffer,
     h_counter: u16,
@@ -458,6 +879,36 @@ impl Vga {
         self.update_vscroll = false;
     }
 
+    // The rendered image is not part of the state, it is redrawn within one frame
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.u16(self.h_counter);
+        writer.u16(self.v_counter);
+        writer.u16(self.h_pixel);
+        writer.u16(self.v_pixel);
+        writer.u16(self.h_offset);
+        writer.u16(self.v_offset);
+        writer.bool(self.update_vscroll);
+    }
+
+    // Either loads all registers or none, so the image is kept intact on failure
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let counters = [reader.u16()?, reader.u16()?];
+        let pixels = [reader.u16()?, reader.u16()?];
+        let offsets = [reader.u16()?, reader.u16()?];
+        let update_vscroll = reader.bool()?;
+
+        // The pixel positions wrap around and are masked when used, the counters are not
+        if (counters[0] >= H_PIXELS) || (counters[1] >= V_PIXELS) {
+            return Err(StateError::InvalidData);
+        }
+
+        [self.h_counter, self.v_counter] = counters;
+        [self.h_pixel, self.v_pixel] = pixels;
+        [self.h_offset, self.v_offset] = offsets;
+        self.update_vscroll = update_vscroll;
+        Ok(())
+    }
+
     #[inline]
     pub fn framebuffer(&self) -> &PixelBuffer {
         &self.buffer
@@ -549,14 +1000,13 @@ impl Vga {
         }
     }
 
-    pub fn clock(&mut self, mem: &mut Memory, n: u32) {
-        const H_PIXELS: u16 = 800; // Number of pixels horizontally (including blanking)
-        const V_PIXELS: u16 = 525; // Number of pixels vertically (including blanking)
-
+    // Returns true if the visible part of a frame was completed
+    pub fn clock(&mut self, mem: &mut Memory, n: u32) -> bool {
         // In hardware the scroll offsets include the front porch region of the screen.
         const BASE_H_OFFSET: u16 = 47;
         const BASE_V_OFFSET: u16 = 33;
 
+        let mut frame_complete = false;
         for _ in 0..n {
             self.h_counter += 1;
             self.h_pixel = self.h_pixel.wrapping_add(1);
@@ -577,6 +1027,11 @@ impl Vga {
                     self.v_pixel = self.v_offset.wrapping_add(BASE_V_OFFSET);
                     self.update_vscroll = false;
                 }
+
+                if self.v_counter == SCREEN_HEIGHT {
+                    frame_complete = true;
+                    mem.finish_conflict_frame();
+                }
             }
 
             if (self.h_counter < SCREEN_WIDTH) && (self.v_counter < SCREEN_HEIGHT) {
@@ -598,6 +1053,8 @@ impl Vga {
                     .set_pixel_at(self.h_counter as usize, self.v_counter as usize, color);
             }
         }
+
+        frame_complete
     }
 }
 
@@ -618,6 +1075,7 @@ pub enum ControlerButton {
     Select,
 }
 
+#[derive(Clone)]
 pub struct Controler {
     low: u8,
     high: u8,
@@ -633,6 +1091,19 @@ impl Controler {
         }
     }
 
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.low);
+        writer.u8(self.high);
+        writer.bool(self.state);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.low = reader.u8()?;
+        self.high = reader.u8()?;
+        self.state = reader.bool()?;
+        Ok(())
+    }
+
     pub fn host_button_down(&mut self, button: ControlerButton) {
         match button {
             ControlerButton::A => self.high |= 0x1,
@@ -699,6 +1170,7 @@ enum RtcState {
     WriteData,
 }
 
+#[derive(Clone)]
 struct Rtc {
     time: DateTime<Local>,
     state: RtcState,
@@ -714,6 +1186,27 @@ impl Rtc {
         }
     }
 
+    // The time is taken from the host clock on every select and is not saved
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(match self.state {
+            RtcState::ReadAddress => 0,
+            RtcState::ReadData => 1,
+            RtcState::WriteData => 2,
+        });
+        writer.u8(self.addr);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.state = match reader.u8()? {
+            0 => RtcState::ReadAddress,
+            1 => RtcState::ReadData,
+            2 => RtcState::WriteData,
+            _ => return Err(StateError::InvalidData),
+        };
+        self.addr = reader.u8()?;
+        Ok(())
+    }
+
     #[inline]
     fn select(&mut self) {
         self.time = Local::now();
@@ -770,6 +1263,7 @@ impl Rtc {
     }
 }
 
+#[derive(Clone)]
 struct Mcp {}
 
 impl Mcp {
@@ -785,6 +1279,7 @@ impl Mcp {
     }
 }
 
+#[derive(Clone)]
 struct Sd {}
 
 impl Sd {
@@ -816,6 +1311,7 @@ enum SpiDevice {
     Sd,
 }
 
+#[derive(Clone)]
 pub struct Spi {
     rtc: Rtc,
     mcp: Mcp,
@@ -854,6 +1350,51 @@ impl Spi {
         self.cycles = 0;
     }
 
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        self.rtc.save_state(writer);
+
+        writer.u8(match self.clock_mode {
+            SpiClockMode::Low => 0,
+            SpiClockMode::High => 1,
+            SpiClockMode::Auto => 2,
+            SpiClockMode::AutoInv => 3,
+        });
+        writer.bool(self.clock_inv);
+        writer.u8(match self.device {
+            SpiDevice::None => 0,
+            SpiDevice::Rtc => 1,
+            SpiDevice::Mcp => 2,
+            SpiDevice::Sd => 3,
+        });
+        writer.u8(self.cycles);
+        writer.u8(self.data_out);
+        writer.u8(self.data_in);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.rtc.load_state(reader)?;
+
+        self.clock_mode = match reader.u8()? {
+            0 => SpiClockMode::Low,
+            1 => SpiClockMode::High,
+            2 => SpiClockMode::Auto,
+            3 => SpiClockMode::AutoInv,
+            _ => return Err(StateError::InvalidData),
+        };
+        self.clock_inv = reader.bool()?;
+        self.device = match reader.u8()? {
+            0 => SpiDevice::None,
+            1 => SpiDevice::Rtc,
+            2 => SpiDevice::Mcp,
+            3 => SpiDevice::Sd,
+            _ => return Err(StateError::InvalidData),
+        };
+        self.cycles = reader.u8()?;
+        self.data_out = reader.u8()?;
+        self.data_in = reader.u8()?;
+        Ok(())
+    }
+
     fn update_data(&mut self) {
         if self.device == SpiDevice::None {
             return;
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+
+// Instruction text for every opcode, `imm8` is replaced by the byte following the opcode.
+// Opcodes the assembler never emits on their own are left empty.
+#[rustfmt::skip]
+const OPCODES: [&str; 0x100] = [
+    /* 0x00 */ "nop",
+    /* 0x01 */ "mov a, imm8",
+    /* 0x02 */ "mov b, imm8",
+    /* 0x03 */ "mov c, imm8",
+    /* 0x04 */ "mov d, imm8",
+    /* 0x05 */ "mov tl, imm8",
+    /* 0x06 */ "mov th, imm8",
+    /* 0x07 */ "mov a, b",
+    /* 0x08 */ "mov a, c",
+    /* 0x09 */ "mov a, d",
+    /* 0x0A */ "mov b, a",
+    /* 0x0B */ "mov b, c",
+    /* 0x0C */ "mov b, d",
+    /* 0x0D */ "mov c, a",
+    /* 0x0E */ "mov c, b",
+    /* 0x0F */ "mov c, d",
+    /* 0x10 */ "mov d, a",
+    /* 0x11 */ "mov d, b",
+    /* 0x12 */ "mov d, c",
+    /* 0x13 */ "mov tl, a",
+    /* 0x14 */ "mov tl, b",
+    /* 0x15 */ "mov tl, c",
+    /* 0x16 */ "mov tl, d",
+    /* 0x17 */ "mov th, a",
+    /* 0x18 */ "mov th, b",
+    /* 0x19 */ "mov th, c",
+    /* 0x1A */ "mov th, d",
+    /* 0x1B */ "mov a, tl",
+    /* 0x1C */ "mov b, tl",
+    /* 0x1D */ "mov c, tl",
+    /* 0x1E */ "mov d, tl",
+    /* 0x1F */ "mov a, th",
+    /* 0x20 */ "mov b, th",
+    /* 0x21 */ "mov c, th",
+    /* 0x22 */ "mov d, th",
+    /* 0x23 */ "mov ra, tx",
+    /* 0x24 */ "mov tx, ra",
+    /* 0x25 */ "mov sp, tx",
+    /* 0x26 */ "mov tx, sp",
+    /* 0x27 */ "mov si, tx",
+    /* 0x28 */ "mov tx, si",
+    /* 0x29 */ "mov di, tx",
+    /* 0x2A */ "mov tx, di",
+    /* 0x2B */ "mov di, si",
+    /* 0x2C */ "mov si, di",
+    /* 0x2D */ "mov si, sp",
+    /* 0x2E */ "mov di, sp",
+    /* 0x2F */ "subae d, c",
+    /* 0x30 */ "reti",
+    /* 0x31 */ "in a, vga",
+    /* 0x32 */ "dec si",
+    /* 0x33 */ "dec di",
+    /* 0x34 */ "incc si",
+    /* 0x35 */ "inc si",
+    /* 0x36 */ "inc di",
+    /* 0x37 */ "out gpio, a",
+    /* 0x38 */ "",
+    /* 0x39 */ "out uart_data, a",
+    /* 0x3A */ "in a, uart_data",
+    /* 0x3B */ "in a, uart_ctrl",
+    /* 0x3C */ "out audio_data, a",
+    /* 0x3D */ "in a, cntrl_data",
+    /* 0x3E */ "in a, gpio",
+    /* 0x3F */ "break",
+    /* 0x40 */ "mov a, [si]",
+    /* 0x41 */ "mov b, [si]",
+    /* 0x42 */ "mov c, [si]",
+    /* 0x43 */ "mov d, [si]",
+    /* 0x44 */ "mov a, [di]",
+    /* 0x45 */ "mov b, [di]",
+    /* 0x46 */ "mov c, [di]",
+    /* 0x47 */ "mov d, [di]",
+    /* 0x48 */ "mov a, [tx]",
+    /* 0x49 */ "mov b, [tx]",
+    /* 0x4A */ "mov c, [tx]",
+    /* 0x4B */ "mov d, [tx]",
+    /* 0x4C */ "mov [si], a",
+    /* 0x4D */ "mov [si], b",
+    /* 0x4E */ "mov [si], c",
+    /* 0x4F */ "mov [si], d",
+    /* 0x50 */ "mov [di], a",
+    /* 0x51 */ "mov [di], b",
+    /* 0x52 */ "mov [di], c",
+    /* 0x53 */ "mov [di], d",
+    /* 0x54 */ "mov [tx], a",
+    /* 0x55 */ "mov [tx], b",
+    /* 0x56 */ "mov [tx], c",
+    /* 0x57 */ "mov [tx], d",
+    /* 0x58 */ "addc b, b",
+    /* 0x59 */ "add b, b",
+    /* 0x5A */ "addac c, a",
+    /* 0x5B */ "lodsb",
+    /* 0x5C */ "callbd tx",
+    /* 0x5D */ "callbd di",
+    /* 0x5E */ "retbd",
+    /* 0x5F */ "",
+    /* 0x60 */ "",
+    /* 0x61 */ "",
+    /* 0x62 */ "",
+    /* 0x63 */ "",
+    /* 0x64 */ "",
+    /* 0x65 */ "",
+    /* 0x66 */ "",
+    /* 0x67 */ "",
+    /* 0x68 */ "",
+    /* 0x69 */ "",
+    /* 0x6A */ "",
+    /* 0x6B */ "",
+    /* 0x6C */ "",
+    /* 0x6D */ "",
+    /* 0x6E */ "",
+    /* 0x6F */ "",
+    /* 0x70 */ "",
+    /* 0x71 */ "",
+    /* 0x72 */ "push a",
+    /* 0x73 */ "push b",
+    /* 0x74 */ "push c",
+    /* 0x75 */ "push d",
+    /* 0x76 */ "push tl",
+    /* 0x77 */ "push th",
+    /* 0x78 */ "pop a",
+    /* 0x79 */ "pop b",
+    /* 0x7A */ "pop c",
+    /* 0x7B */ "pop d",
+    /* 0x7C */ "pop tl",
+    /* 0x7D */ "pop th",
+    /* 0x7E */ "stosb",
+    /* 0x7F */ "clc",
+    /* 0x80 */ "shl a",
+    /* 0x81 */ "shl b",
+    /* 0x82 */ "shl c",
+    /* 0x83 */ "shl d",
+    /* 0x84 */ "shr a",
+    /* 0x85 */ "shr b",
+    /* 0x86 */ "shr c",
+    /* 0x87 */ "shr d",
+    /* 0x88 */ "add a, b",
+    /* 0x89 */ "add a, c",
+    /* 0x8A */ "add a, d",
+    /* 0x8B */ "add b, a",
+    /* 0x8C */ "add b, c",
+    /* 0x8D */ "add b, d",
+    /* 0x8E */ "add c, a",
+    /* 0x8F */ "add c, b",
+    /* 0x90 */ "add c, d",
+    /* 0x91 */ "add d, a",
+    /* 0x92 */ "add d, b",
+    /* 0x93 */ "add d, c",
+    /* 0x94 */ "addc a, b",
+    /* 0x95 */ "addc a, c",
+    /* 0x96 */ "addc a, d",
+    /* 0x97 */ "addc b, a",
+    /* 0x98 */ "addc b, c",
+    /* 0x99 */ "addc b, d",
+    /* 0x9A */ "addc c, a",
+    /* 0x9B */ "addc c, b",
+    /* 0x9C */ "addc c, d",
+    /* 0x9D */ "addc d, a",
+    /* 0x9E */ "addc d, b",
+    /* 0x9F */ "addc d, c",
+    /* 0xA0 */ "inc a",
+    /* 0xA1 */ "inc b",
+    /* 0xA2 */ "inc c",
+    /* 0xA3 */ "inc d",
+    /* 0xA4 */ "incc a",
+    /* 0xA5 */ "incc b",
+    /* 0xA6 */ "incc c",
+    /* 0xA7 */ "incc d",
+    /* 0xA8 */ "sub a, b",
+    /* 0xA9 */ "sub a, c",
+    /* 0xAA */ "sub a, d",
+    /* 0xAB */ "sub b, a",
+    /* 0xAC */ "sub b, c",
+    /* 0xAD */ "sub b, d",
+    /* 0xAE */ "sub c, a",
+    /* 0xAF */ "sub c, b",
+    /* 0xB0 */ "sub c, d",
+    /* 0xB1 */ "sub d, a",
+    /* 0xB2 */ "sub d, b",
+    /* 0xB3 */ "sub d, c",
+    /* 0xB4 */ "subb a, b",
+    /* 0xB5 */ "subb a, c",
+    /* 0xB6 */ "subb a, d",
+    /* 0xB7 */ "subb b, a",
+    /* 0xB8 */ "subb b, c",
+    /* 0xB9 */ "subb b, d",
+    /* 0xBA */ "subb c, a",
+    /* 0xBB */ "subb c, b",
+    /* 0xBC */ "subb c, d",
+    /* 0xBD */ "subb d, a",
+    /* 0xBE */ "subb d, b",
+    /* 0xBF */ "subb d, c",
+    /* 0xC0 */ "dec a",
+    /* 0xC1 */ "dec b",
+    /* 0xC2 */ "dec c",
+    /* 0xC3 */ "dec d",
+    /* 0xC4 */ "and a, b",
+    /* 0xC5 */ "and a, c",
+    /* 0xC6 */ "and a, d",
+    /* 0xC7 */ "and b, a",
+    /* 0xC8 */ "and b, c",
+    /* 0xC9 */ "and b, d",
+    /* 0xCA */ "and c, a",
+    /* 0xCB */ "and c, b",
+    /* 0xCC */ "and c, d",
+    /* 0xCD */ "and d, a",
+    /* 0xCE */ "and d, b",
+    /* 0xCF */ "and d, c",
+    /* 0xD0 */ "or a, b",
+    /* 0xD1 */ "or a, c",
+    /* 0xD2 */ "or a, d",
+    /* 0xD3 */ "or b, a",
+    /* 0xD4 */ "or b, c",
+    /* 0xD5 */ "or b, d",
+    /* 0xD6 */ "or c, a",
+    /* 0xD7 */ "or c, b",
+    /* 0xD8 */ "or c, d",
+    /* 0xD9 */ "or d, a",
+    /* 0xDA */ "or d, b",
+    /* 0xDB */ "or d, c",
+    /* 0xDC */ "xor a, b",
+    /* 0xDD */ "xor a, c",
+    /* 0xDE */ "xor a, d",
+    /* 0xDF */ "xor b, a",
+    /* 0xE0 */ "xor b, c",
+    /* 0xE1 */ "xor b, d",
+    /* 0xE2 */ "xor c, a",
+    /* 0xE3 */ "xor c, b",
+    /* 0xE4 */ "xor c, d",
+    /* 0xE5 */ "xor d, a",
+    /* 0xE6 */ "xor d, b",
+    /* 0xE7 */ "xor d, c",
+    /* 0xE8 */ "xor a, a",
+    /* 0xE9 */ "xor b, b",
+    /* 0xEA */ "xor c, c",
+    /* 0xEB */ "xor d, d",
+    /* 0xEC */ "not a",
+    /* 0xED */ "not b",
+    /* 0xEE */ "not c",
+    /* 0xEF */ "not d",
+    /* 0xF0 */ "cmp a, b",
+    /* 0xF1 */ "cmp a, c",
+    /* 0xF2 */ "cmp a, d",
+    /* 0xF3 */ "cmp b, a",
+    /* 0xF4 */ "cmp b, c",
+    /* 0xF5 */ "cmp b, d",
+    /* 0xF6 */ "cmp c, a",
+    /* 0xF7 */ "cmp c, b",
+    /* 0xF8 */ "cmp c, d",
+    /* 0xF9 */ "cmp d, a",
+    /* 0xFA */ "cmp d, b",
+    /* 0xFB */ "cmp d, c",
+    /* 0xFC */ "test a",
+    /* 0xFD */ "test b",
+    /* 0xFE */ "test c",
+    /* 0xFF */ "test d",
+];
+
+const JUMP_OPCODE: u8 = 0x5F;
+
+// Jumps are encoded as `0x5F` followed by a byte selecting the condition
+#[rustfmt::skip]
+const JUMP_CONDITIONS: [&str; 0x12] = [
+    "jmp tx", "jo tx",  "jno tx", "js tx",  "jns tx", "jz tx",
+    "jnz tx", "jc tx",  "jnc tx", "jbe tx", "ja tx",  "jl tx",
+    "jge tx", "jle tx", "jg tx",  "jlc tx", "jnlc tx", "jmp di",
+];
+
+const JUMP_CONDITION_BASE: u8 = 0x60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    text: String,
+    length: u16,
+    valid: bool,
+}
+
+impl DisassembledInstruction {
+    /// The instruction in assembler syntax
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The number of bytes the instruction occupies
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    #[inline]
+    pub fn valid(&self) -> bool {
+        self.valid
+    }
+}
+
+impl Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn invalid(opcode: u8) -> DisassembledInstruction {
+    DisassembledInstruction {
+        text: format!("(invalid 0x{opcode:0>2X})"),
+        length: 1,
+        valid: false,
+    }
+}
+
+/// The instruction an opcode encodes with `imm8` in place of an immediate, or `None` for
+/// undefined opcodes. All jumps share one opcode and are named `jmp`.
+pub fn opcode_name(opcode: u8) -> Option<&'static str> {
+    if opcode == JUMP_OPCODE {
+        Some("jmp")
+    } else {
+        Some(OPCODES[opcode as usize]).filter(|text| !text.is_empty())
+    }
+}
+
+/// Decodes the machine instruction at the start of `bytes`.
+/// Pseudo instructions like `jmp label` are made up of several machine instructions,
+/// which are decoded one at a time.
+pub fn disassemble(bytes: &[u8]) -> Option<DisassembledInstruction> {
+    let (&opcode, rest) = bytes.split_first()?;
+
+    if opcode == JUMP_OPCODE {
+        let Some(&condition) = rest.first() else {
+            return Some(invalid(opcode));
+        };
+
+        let text = condition
+            .checked_sub(JUMP_CONDITION_BASE)
+            .and_then(|index| JUMP_CONDITIONS.get(index as usize));
+
+        return Some(match text {
+            Some(text) => DisassembledInstruction {
+                text: (*text).to_owned(),
+                length: 2,
+                valid: true,
+            },
+            None => invalid(opcode),
+        });
+    }
+
+    let text = OPCODES[opcode as usize];
+    if text.is_empty() {
+        return Some(invalid(opcode));
+    }
+
+    if text.contains("imm8") {
+        let Some(&value) = rest.first() else {
+            return Some(invalid(opcode));
+        };
+
+        Some(DisassembledInstruction {
+            text: text.replace("imm8", &format!("0x{value:0>2X}")),
+            length: 2,
+            valid: true,
+        })
+    } else {
+        Some(DisassembledInstruction {
+            text: text.to_owned(),
+            length: 1,
+            valid: true,
+        })
+    }
+}
+
+/// A decoded instruction together with where it is located in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassemblyLine {
+    address: u16,
+    bytes: Vec<u8>,
+    instruction: DisassembledInstruction,
+}
+
+impl DisassemblyLine {
+    #[inline]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    #[inline]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    #[inline]
+    pub fn instruction(&self) -> &DisassembledInstruction {
+        &self.instruction
+    }
+}
+
+fn disassemble_at(read: &impl Fn(u16) -> u8, address: u16) -> DisassemblyLine {
+    let bytes = [read(address), read(address.wrapping_add(1))];
+    let instruction = disassemble(&bytes).unwrap();
+
+    DisassemblyLine {
+        address,
+        bytes: bytes[..(instruction.length() as usize)].to_vec(),
+        instruction,
+    }
+}
+
+/// The most lines `disassemble_around` decodes, finding the preceding ones takes quadratic time
+pub const MAX_AROUND_LINES: u32 = 256;
+
+/// Decodes `count` instructions around `address`, with about half of them preceding it.
+/// `read` returns the byte at an address, wrapping around at the end of the address space.
+/// At most `MAX_AROUND_LINES` instructions are decoded.
+pub fn disassemble_around(
+    read: impl Fn(u16) -> u8,
+    address: u16,
+    count: u32,
+) -> Vec<DisassemblyLine> {
+    let count = count.min(MAX_AROUND_LINES);
+    let before = count / 2;
+
+    // Instructions can't be decoded backwards, so decode forward from a point early enough to
+    // fit all preceding instructions and use the first one that lines up with `address`.
+    // If none does, `address` is in the middle of an instruction and that one is left out.
+    let mut lines = None;
+    let mut fallback = Vec::new();
+    for distance in (before..=(before * 2)).rev() {
+        let mut candidate = Vec::new();
+        let mut offset = 0;
+        while offset < distance {
+            let line = disassemble_at(&read, address.wrapping_sub((distance - offset) as u16));
+            offset += line.bytes.len() as u32;
+            candidate.push(line);
+        }
+
+        if offset == distance {
+            if candidate.len() >= (before as usize) {
+                lines = Some(candidate.split_off(candidate.len() - (before as usize)));
+                break;
+            }
+        } else if fallback.is_empty() {
+            candidate.pop();
+            let skip = candidate.len().saturating_sub(before as usize);
+            fallback = candidate.split_off(skip);
+        }
+    }
+
+    let mut lines = lines.unwrap_or(fallback);
+
+    let mut next = address;
+    while lines.len() < (count as usize) {
+        let line = disassemble_at(&read, next);
+        next = next.wrapping_add(line.bytes.len() as u16);
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Decodes all of `bytes` as consecutive instructions, the first one located at `base`
+pub fn disassemble_program(bytes: &[u8], base: u16) -> Vec<DisassemblyLine> {
+    let mut lines = Vec::new();
+
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let instruction = disassemble(&bytes[offset..]).unwrap();
+        let end = offset + (instruction.length() as usize);
+
+        lines.push(DisassemblyLine {
+            address: base.wrapping_add(offset as u16),
+            bytes: bytes[offset..end].to_vec(),
+            instruction,
+        });
+        offset = end;
+    }
+
+    lines
+}
+
+/// Formats the lines as a listing with their address and bytes,
+/// preceded by a label if one of `symbols` points to the address
+pub fn format_listing(lines: &[DisassemblyLine], symbols: &HashMap<u16, String>) -> String {
+    let mut output = String::new();
+
+    for line in lines {
+        if let Some(name) = symbols.get(&line.address) {
+            writeln!(output, "{name}:").unwrap();
+        }
+
+        let bytes: Vec<_> = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:0>2X}"))
+            .collect();
+        writeln!(
+            output,
+            "    {:0>4X}  {:<6} {}",
+            line.address,
+            bytes.join(" "),
+            line.instruction
+        )
+        .unwrap();
+    }
+
+    output
+}
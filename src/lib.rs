@@ -6,26 +6,51 @@
 #![feature(try_trait_v2)]
 
 pub mod assembler;
+pub mod capture;
+pub mod coverage;
 pub mod cpu;
 mod device;
-
-use cpu::Cpu;
-use device::{Audio, Controler, ControlerButton, Memory, Spi, Uart, Vga};
-
+pub mod disassembler;
+pub mod state;
+pub mod trace;
+
+use coverage::OpcodeCoverage;
+use cpu::{Cpu, Flags, InvalidInstruction, Register, StackFault};
+use device::{Audio, Controler, Memory, Spi, Uart, Vga};
+pub use device::{ConflictStats, ControlerButton, MemoryRegion, WatchHit, MEMORY_REGIONS};
+use state::{StateError, StateReader, StateWriter};
+use trace::{Divergence, RegisterState, TraceEntry};
+
+#[cfg(feature = "audio")]
 use crossbeam::queue::SegQueue;
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+#[cfg(feature = "audio")]
 use std::sync::Arc;
 use std::time::Duration;
 
 const INITIAL_CLOCK_RATE: f64 = 4_000_000.0; // 4 MHz
 pub const FRAME_RATE: f64 = 59.94047619047765; // Actual VGA 60 Hz frequency
 const CPU_RESET_PC: u16 = 0xE000;
+const MONITOR_ROM_SIZE: usize = 0x10000 - (CPU_RESET_PC as usize);
+const MAX_STEP_CYCLES: u32 = 64; // Upper bound for the pipeline to fetch the next instruction
+const MAX_STEP_OVER_FRAMES: u64 = 60; // Upper bound for a call to return when stepping over it
+const DEFAULT_MONITOR: &[u8] = include_bytes!("../res/Monitor.bin");
+pub const UART_INTERRUPT_VECTOR: u16 = 0x8AF0;
+pub const VGA_INTERRUPT_VECTOR: u16 = 0x8AF8;
 
 const UART_BAUD_RATE: f64 = 115_200.0; // 115.2 kHz
 
-const AUDIO_CLOCK_RATE: f64 = 1_843_200.0 / 8.0; // 1.8432 MHz with fixed by 16 divider
+const AUDIO_CLOCK_HZ: u64 = 1_843_200 / 8; // 1.8432 MHz with fixed by 16 divider
+const AUDIO_CLOCK_RATE: f64 = AUDIO_CLOCK_HZ as f64;
 const SAMPLE_RATE: u32 = 44100;
 const AUDIO_CYCLES_PER_SAMPLE: f64 = AUDIO_CLOCK_RATE / (SAMPLE_RATE as f64);
+const LOW_PASS_CUTOFF: f64 = 18_000.0; // Just below the 22.05 kHz Nyquist frequency of the output
+const SAMPLE_MIRROR_LEN: usize = 2048; // About 46 ms of output
 
 const VGA_CLOCK_RATE: f64 = 25_175_000.0; // 25.175 MHz
 pub const SCREEN_WIDTH: u16 = 640;
@@ -44,10 +69,12 @@ pub fn format_clock_rate(clock_rate: f64) -> String {
     }
 }
 
+#[cfg(feature = "audio")]
 struct SampleSource {
     sample_buffer: Arc<SegQueue<f32>>,
     last_sample: f32,
 }
+#[cfg(feature = "audio")]
 impl SampleSource {
     #[inline]
     pub fn new(sample_buffer: Arc<SegQueue<f32>>) -> Self {
@@ -57,6 +84,7 @@ impl SampleSource {
         }
     }
 }
+#[cfg(feature = "audio")]
 impl Iterator for SampleSource {
     type Item = f32;
 
@@ -69,6 +97,7 @@ impl Iterator for SampleSource {
         }
     }
 }
+#[cfg(feature = "audio")]
 impl rodio::Source for SampleSource {
     #[inline]
     fn current_frame_len(&self) -> Option<usize> {
@@ -88,11 +117,93 @@ impl rodio::Source for SampleSource {
     }
 }
 
+// Two cascaded one-pole stages running at the audio clock rate, which removes
+// most of the square wave harmonics that would alias when decimating
+#[derive(Clone)]
+struct LowPassFilter {
+    alpha: f32,
+    stages: [f32; 2],
+}
+
+impl LowPassFilter {
+    fn new(cutoff: f64, sample_rate: f64) -> Self {
+        let alpha = 1.0 - (-std::f64::consts::TAU * cutoff / sample_rate).exp();
+
+        Self {
+            alpha: alpha as f32,
+            stages: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let mut value = sample;
+        for stage in &mut self.stages {
+            *stage += (value - *stage) * self.alpha;
+            value = *stage;
+        }
+        value
+    }
+}
+
+// Ring buffer holding a copy of the most recently played samples. The sample buffer itself is
+// drained by the audio thread, so it can't be read for visualization.
+#[derive(Clone)]
+struct SampleMirror {
+    samples: Box<[f32]>,
+    next: usize,
+}
+
+impl SampleMirror {
+    fn new() -> Self {
+        Self {
+            samples: vec![0.0; SAMPLE_MIRROR_LEN].into_boxed_slice(),
+            next: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, sample: f32) {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % self.samples.len();
+    }
+
+    fn iter(&self) -> impl Iterator<Item = f32> + '_ {
+        let (newer, older) = self.samples.split_at(self.next);
+        older.iter().chain(newer).copied()
+    }
+}
+
+/// What happens when the pipeline ROMs cannot decode an opcode.
+///
+/// The shipped ROMs decode every opcode with every combination of flags, including 0x30 and
+/// 0x38 which the assembler never emits, so this only matters for modified ROMs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidOpcodePolicy {
+    /// Panic with the opcode and its address
+    #[default]
+    Fault,
+    /// Execute the opcode as a `nop`, e.g. to keep running random programs
+    Nop,
+    /// Stop in front of the instruction like at a break point, see [`System::invalid_instruction`]
+    Halt,
+}
+
 pub trait Terminal: vte::Perform {
     fn reset(&mut self);
     fn flush(&mut self);
 }
 
+// Reads a clock accumulator, which is always less than one `period`
+fn read_fraction(reader: &mut StateReader, period: f64) -> Result<f64, StateError> {
+    let value = reader.f64()?;
+    if (0.0..period).contains(&value) {
+        Ok(value)
+    } else {
+        Err(StateError::InvalidData)
+    }
+}
+
+#[cfg(feature = "input")]
 fn map_button(button: gilrs::Button) -> Option<ControlerButton> {
     match button {
         gilrs::Button::South => Some(ControlerButton::B),
@@ -118,11 +229,63 @@ fn map_button(button: gilrs::Button) -> Option<ControlerButton> {
     }
 }
 
+#[cfg(feature = "audio")]
 struct AudioState {
     _audio_stream: rodio::OutputStream,
     sample_buffer: Arc<SegQueue<f32>>,
 }
 
+#[cfg(feature = "audio")]
+impl AudioState {
+    fn open() -> Result<Self, Box<dyn std::error::Error>> {
+        let (_audio_stream, audio_handle) = rodio::OutputStream::try_default()?;
+
+        let sample_buffer = Arc::new(SegQueue::new());
+        let sample_source = SampleSource::new(Arc::clone(&sample_buffer));
+        audio_handle.play_raw(sample_source)?;
+
+        Ok(Self {
+            _audio_stream,
+            sample_buffer,
+        })
+    }
+}
+
+// The output device is opened when the first sample is produced
+#[cfg(feature = "audio")]
+enum AudioOutput {
+    Closed,
+    Open(AudioState),
+    // There is no usable output device or the system is a fork, samples are discarded
+    Unavailable,
+}
+
+#[cfg(feature = "audio")]
+enum SampleSink {
+    Buffer(Arc<SegQueue<f32>>),
+    Discard,
+}
+
+#[cfg(feature = "audio")]
+impl SampleSink {
+    #[inline]
+    fn push(&self, sample: f32) {
+        if let Self::Buffer(sample_buffer) = self {
+            sample_buffer.push(sample);
+        }
+    }
+}
+
+// Stands in for the sample buffer when there is no audio output
+#[cfg(not(feature = "audio"))]
+struct DiscardSamples;
+
+#[cfg(not(feature = "audio"))]
+impl DiscardSamples {
+    #[inline]
+    fn push(&self, _sample: f32) {}
+}
+
 pub struct System<Term: Terminal> {
     cpu: Cpu,
     memory: Memory,
@@ -144,15 +307,44 @@ pub struct System<Term: Terminal> {
     baud_cycles: f64,
     fractional_audio_cycles: f64,
     audio_cycles: f64,
+    previous_audio_sample: f32,
+    low_pass_filter: LowPassFilter,
+    sample_mirror: SampleMirror,
     vga_cycles: f64,
+    total_cycles: u64,
+
+    // Integer audio clocking, see `set_deterministic_audio`
+    master_volume: f32,
+    audio_filter: bool,
+    deterministic_audio: bool,
+    audio_phase: u64,
+    sample_phase: u64,
+    audio_samples: Vec<f32>,
+
+    invalid_opcode_policy: InvalidOpcodePolicy,
+    invalid_instruction: Option<InvalidInstruction>,
+    breakpoints: BTreeSet<u16>,
+    watch_hit: Option<WatchHit>,
+    stack_fault: Option<StackFault>,
+    breakpoint_hit: Option<u16>,
 
     input_queue: VecDeque<u8>,
     output_queue: VecDeque<u8>,
     terminal_parser: vte::Parser,
     terminal: Term,
-    audio_state: Option<AudioState>,
-    gilrs: gilrs::Gilrs,
+    #[cfg(feature = "audio")]
+    audio_output: AudioOutput,
+    #[cfg(feature = "input")]
+    // `None` if there is no gamepad backend on this platform
+    gilrs: Option<gilrs::Gilrs>,
     memory_view: Vec<u8>,
+    memory_view_time: Duration,
+    monitor: Cow<'static, [u8]>,
+    trace: Option<BufWriter<File>>,
+    coverage: Option<OpcodeCoverage>,
+    uart_log: Option<BufWriter<File>>,
+    // Jumps are fetched as two opcodes but traced as one instruction, so the second fetch is skipped
+    trace_skip_address: Option<u16>,
 }
 
 impl<Term: Terminal> System<Term> {
@@ -178,15 +370,43 @@ impl<Term: Terminal> System<Term> {
             baud_cycles: 0.0,
             fractional_audio_cycles: 0.0,
             audio_cycles: 0.0,
+            previous_audio_sample: 0.0,
+            low_pass_filter: LowPassFilter::new(LOW_PASS_CUTOFF, AUDIO_CLOCK_RATE),
+            sample_mirror: SampleMirror::new(),
             vga_cycles: 0.0,
+            total_cycles: 0,
+
+            master_volume: 1.0,
+            audio_filter: true,
+            deterministic_audio: false,
+            audio_phase: 0,
+            sample_phase: 0,
+            audio_samples: Vec::new(),
+
+            invalid_opcode_policy: InvalidOpcodePolicy::Fault,
+            invalid_instruction: None,
+            breakpoints: BTreeSet::new(),
+            watch_hit: None,
+            stack_fault: None,
+            breakpoint_hit: None,
 
             input_queue: VecDeque::new(),
             output_queue: VecDeque::new(),
             terminal_parser: vte::Parser::new(),
             terminal,
-            audio_state: None,
-            gilrs: gilrs::Gilrs::new().unwrap(),
-            memory_view: Vec::new(),
+            #[cfg(feature = "audio")]
+            audio_output: AudioOutput::Closed,
+            #[cfg(feature = "input")]
+            gilrs: gilrs::Gilrs::new()
+                .inspect_err(|err| log::warn!("no gamepad support, continuing without it: {err}"))
+                .ok(),
+            memory_view: vec![0; 0x10000],
+            memory_view_time: Duration::ZERO,
+            monitor: Cow::Borrowed(DEFAULT_MONITOR),
+            trace: None,
+            coverage: None,
+            uart_log: None,
+            trace_skip_address: None,
         };
 
         system.recalculate_cycles();
@@ -194,18 +414,88 @@ impl<Term: Terminal> System<Term> {
         system
     }
 
+    /// Copies the complete machine state into a new system printing to `terminal`, e.g. to try
+    /// several inputs starting from the same state without a `save_state` round trip.
+    /// Forked systems have no audio output and no gamepad, and they don't trace or log the UART.
+    /// A control sequence the terminal was in the middle of receiving is lost.
+    pub fn fork<T: Terminal>(&self, terminal: T) -> System<T> {
+        System {
+            cpu: self.cpu.clone(),
+            memory: self.memory.clone(),
+            uart: self.uart.clone(),
+            audio: self.audio.clone(),
+            vga: self.vga.clone(),
+            controler: self.controler.clone(),
+            spi: self.spi.clone(),
+
+            clock_rate: self.clock_rate,
+            cycles_per_frame: self.cycles_per_frame,
+            whole_cycles_per_frame: self.whole_cycles_per_frame,
+            fract_cycles_per_frame: self.fract_cycles_per_frame,
+            cycles_per_baud: self.cycles_per_baud,
+            audio_cycles_per_cpu_cylce: self.audio_cycles_per_cpu_cylce,
+            vga_cycles_per_cpu_cycle: self.vga_cycles_per_cpu_cycle,
+
+            fractional_cycles: self.fractional_cycles,
+            baud_cycles: self.baud_cycles,
+            fractional_audio_cycles: self.fractional_audio_cycles,
+            audio_cycles: self.audio_cycles,
+            previous_audio_sample: self.previous_audio_sample,
+            low_pass_filter: self.low_pass_filter.clone(),
+            sample_mirror: self.sample_mirror.clone(),
+            vga_cycles: self.vga_cycles,
+            total_cycles: self.total_cycles,
+
+            master_volume: self.master_volume,
+            audio_filter: self.audio_filter,
+            deterministic_audio: self.deterministic_audio,
+            audio_phase: self.audio_phase,
+            sample_phase: self.sample_phase,
+            audio_samples: self.audio_samples.clone(),
+
+            invalid_opcode_policy: self.invalid_opcode_policy,
+            invalid_instruction: self.invalid_instruction,
+            breakpoints: self.breakpoints.clone(),
+            watch_hit: self.watch_hit,
+            stack_fault: self.stack_fault,
+            breakpoint_hit: self.breakpoint_hit,
+
+            input_queue: self.input_queue.clone(),
+            output_queue: self.output_queue.clone(),
+            terminal_parser: vte::Parser::new(),
+            terminal,
+            #[cfg(feature = "audio")]
+            audio_output: AudioOutput::Unavailable,
+            #[cfg(feature = "input")]
+            gilrs: None,
+            memory_view: self.memory_view.clone(),
+            memory_view_time: self.memory_view_time,
+            monitor: self.monitor.clone(),
+            trace: None,
+            coverage: self.coverage.clone(),
+            uart_log: None,
+            trace_skip_address: self.trace_skip_address,
+        }
+    }
+
     fn recalculate_cycles(&mut self) {
         self.cycles_per_frame = self.clock_rate / FRAME_RATE;
         self.whole_cycles_per_frame = self.cycles_per_frame as u64;
         self.fract_cycles_per_frame = self.cycles_per_frame - (self.whole_cycles_per_frame as f64);
         self.cycles_per_baud = self.clock_rate / UART_BAUD_RATE;
+        // Keep the accumulator below one period, periods the new rate has already completed are
+        // skipped instead of all being clocked on the next cycle
+        self.baud_cycles %= self.cycles_per_baud;
         self.audio_cycles_per_cpu_cylce = AUDIO_CLOCK_RATE / self.clock_rate;
         self.vga_cycles_per_cpu_cycle = VGA_CLOCK_RATE / self.clock_rate;
     }
 
     pub fn reset(&mut self) {
-        const MONITOR_BYTES: &[u8] = include_bytes!("../res/Monitor.bin");
-        self.memory.init_region(MONITOR_BYTES, CPU_RESET_PC);
+        // Clear the part of the ROM a shorter monitor doesn't cover, so no bytes of a previous one
+        // are left behind
+        let mut rom = vec![0; MONITOR_ROM_SIZE];
+        rom[..self.monitor.len()].copy_from_slice(&self.monitor);
+        self.memory.init_region(&rom, CPU_RESET_PC);
 
         self.cpu.reset(CPU_RESET_PC);
         self.vga.reset();
@@ -213,13 +503,141 @@ impl<Term: Terminal> System<Term> {
 
         self.clock_rate = INITIAL_CLOCK_RATE;
         self.recalculate_cycles();
+        self.total_cycles = 0;
+        self.invalid_instruction = None;
+        self.watch_hit = None;
+        self.stack_fault = None;
+        self.breakpoint_hit = None;
 
         self.update_memory_view();
-        self.memory_view.shrink_to_fit();
 
         self.process_terminal();
         self.terminal_parser = vte::Parser::new();
         self.terminal.reset();
+
+        log::debug!("system reset");
+    }
+
+    /// Replaces the monitor ROM that is loaded at 0xE000 on the next reset.
+    /// Fails if the image does not fit into the ROM region.
+    pub fn set_monitor(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        if bytes.len() > MONITOR_ROM_SIZE {
+            return Err(());
+        }
+
+        self.monitor = Cow::Owned(bytes.to_vec());
+        Ok(())
+    }
+
+    /// Goes back to the monitor ROM built into the emulator on the next reset
+    pub fn reset_monitor(&mut self) {
+        self.monitor = Cow::Borrowed(DEFAULT_MONITOR);
+    }
+
+    /// Captures the complete machine state in a compact, run-length compressed format.
+    /// Host side settings like the monitor ROM, tracing and audio output are not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        self.cpu.save_state(&mut writer);
+        self.memory.save_state(&mut writer);
+        self.uart.save_state(&mut writer);
+        self.audio.save_state(&mut writer);
+        self.controler.save_state(&mut writer);
+        self.spi.save_state(&mut writer);
+
+        writer.f64(self.clock_rate);
+        writer.f64(self.fractional_cycles);
+        writer.f64(self.baud_cycles);
+        writer.f64(self.fractional_audio_cycles);
+        writer.f64(self.audio_cycles);
+        writer.f64(self.vga_cycles);
+        writer.u64(self.total_cycles);
+        writer.u64(self.audio_phase);
+        writer.u64(self.sample_phase);
+
+        writer.u32(self.input_queue.len() as u32);
+        for &byte in &self.input_queue {
+            writer.u8(byte);
+        }
+
+        self.vga.save_state(&mut writer);
+        writer.finish()
+    }
+
+    /// Restores a state created by `save_state`. The system is left untouched if loading fails.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(data)?;
+
+        let mut cpu = Cpu::new();
+        cpu.set_stack_bounds(self.cpu.stack_bounds());
+        cpu.load_state(&mut reader)?;
+        let mut memory = Memory::new();
+        memory.set_conflict_emulation(self.memory.conflict_emulation());
+        for (addr, value) in self.memory.watchpoints() {
+            memory.add_watchpoint(addr, value);
+        }
+        memory.load_state(&mut reader)?;
+        let mut uart = Uart::new();
+        uart.set_loopback(self.uart.loopback());
+        uart.load_state(&mut reader)?;
+        let mut audio = Audio::new();
+        audio.load_state(&mut reader)?;
+        let mut controler = Controler::new();
+        controler.load_state(&mut reader)?;
+        let mut spi = Spi::new();
+        spi.load_state(&mut reader)?;
+
+        let clock_rate = reader.f64()?;
+        if !clock_rate.is_finite() || (clock_rate <= 0.0) {
+            return Err(StateError::InvalidData);
+        }
+        // Each accumulator holds what is left of a period after the last cycle, anything else
+        // would make the clock loops run for a very long time or not at all
+        let fractional_cycles = read_fraction(&mut reader, 1.0)?;
+        let baud_cycles = read_fraction(&mut reader, clock_rate / UART_BAUD_RATE)?;
+        let fractional_audio_cycles = read_fraction(&mut reader, 1.0)?;
+        let audio_cycles = read_fraction(&mut reader, AUDIO_CYCLES_PER_SAMPLE)?;
+        let vga_cycles = read_fraction(&mut reader, 1.0)?;
+        let total_cycles = reader.u64()?;
+        let audio_phase = reader.u64()?;
+        let sample_phase = reader.u64()?;
+
+        let input_len = reader.u32()? as usize;
+        let input_queue = reader.bytes(input_len)?.iter().copied().collect();
+
+        // Loaded last since it is the only device that is updated in place
+        self.vga.load_state(&mut reader)?;
+
+        self.cpu = cpu;
+        self.memory = memory;
+        self.uart = uart;
+        self.audio = audio;
+        self.controler = controler;
+        self.spi = spi;
+
+        self.clock_rate = clock_rate;
+        self.recalculate_cycles();
+        self.fractional_cycles = fractional_cycles;
+        self.baud_cycles = baud_cycles;
+        self.fractional_audio_cycles = fractional_audio_cycles;
+        self.audio_cycles = audio_cycles;
+        self.vga_cycles = vga_cycles;
+        self.total_cycles = total_cycles;
+        self.audio_phase = audio_phase;
+        self.sample_phase = sample_phase;
+        self.audio_samples.clear();
+        self.invalid_instruction = None;
+        self.watch_hit = None;
+        self.stack_fault = None;
+        self.breakpoint_hit = None;
+
+        self.input_queue = input_queue;
+        self.process_terminal();
+        self.update_memory_view();
+
+        log::debug!("restored state at cycle {}", self.total_cycles);
+        Ok(())
     }
 
     #[inline]
@@ -227,6 +645,355 @@ impl<Term: Terminal> System<Term> {
         &self.cpu
     }
 
+    /// Allows changing the CPU state, e.g. while paused for debugging
+    #[inline]
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    #[inline]
+    pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+        self.cpu.set_interrupts_enabled(enabled);
+    }
+
+    #[inline]
+    pub fn get_reg(&self, register: Register) -> u16 {
+        self.cpu.register(register)
+    }
+
+    /// Fails if the value does not fit into the register
+    #[inline]
+    pub fn set_reg(&mut self, register: Register, value: u16) -> Result<(), ()> {
+        self.cpu.set_register(register, value)
+    }
+
+    #[inline]
+    pub fn bus_conflict_emulation(&self) -> bool {
+        self.memory.conflict_emulation()
+    }
+
+    /// Enables or disables emulation of bus conflicts between the CPU and the VGA (enabled by default).
+    /// On hardware the VGA shows stale pixels while the CPU writes to video memory, with this
+    /// disabled the VGA always sees the current memory contents, so the rendered image no longer
+    /// matches what the real machine would display.
+    #[inline]
+    pub fn set_bus_conflict_emulation(&mut self, enabled: bool) {
+        self.memory.set_conflict_emulation(enabled);
+    }
+
+    #[inline]
+    pub fn uart_loopback(&self) -> bool {
+        self.uart.loopback()
+    }
+
+    /// Sends the bytes the program transmits over the UART straight back to it, for testing
+    /// serial code. While enabled nothing reaches the terminal and typed input stays queued.
+    #[inline]
+    pub fn set_uart_loopback(&mut self, enabled: bool) {
+        self.uart.set_loopback(enabled);
+    }
+
+    #[inline]
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    /// Scales the played samples, from 0 (muted) to 1 (full volume).
+    /// Samples collected with deterministic audio are not affected.
+    #[inline]
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.clamp(0.0, 1.0);
+    }
+
+    #[inline]
+    pub fn audio_filter(&self) -> bool {
+        self.audio_filter
+    }
+
+    /// Runs the played samples through a low-pass filter to avoid aliasing, enabled by default.
+    /// Samples collected with deterministic audio are not affected.
+    #[inline]
+    pub fn set_audio_filter(&mut self, enabled: bool) {
+        self.audio_filter = enabled;
+    }
+
+    /// The last played samples before applying the master volume, oldest first.
+    /// Silence if nothing has been played yet.
+    #[inline]
+    pub fn recent_samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.sample_mirror.iter()
+    }
+
+    #[inline]
+    pub fn deterministic_audio(&self) -> bool {
+        self.deterministic_audio
+    }
+
+    /// Clocks the audio device using exact integer ratios of the clock rate (rounded to whole Hz)
+    /// instead of floating point accumulators, so sample timing is the same on every machine.
+    /// Samples are recorded into `audio_samples` instead of being played back, which is meant
+    /// for tests comparing against a reference and not for real-time use.
+    pub fn set_deterministic_audio(&mut self, enabled: bool) {
+        self.deterministic_audio = enabled;
+        self.audio_phase = 0;
+        self.sample_phase = 0;
+        self.audio_samples.clear();
+    }
+
+    /// The samples recorded since deterministic audio was enabled
+    #[inline]
+    pub fn audio_samples(&self) -> &[f32] {
+        &self.audio_samples
+    }
+
+    /// Returns the samples recorded with deterministic audio since the last call and clears them,
+    /// so long running tests don't have to keep every sample around
+    #[inline]
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.audio_samples)
+    }
+
+    /// How many VGA reads were blocked by CPU writes during the last frame
+    #[inline]
+    pub fn conflict_stats(&self) -> ConflictStats {
+        self.memory.conflict_stats()
+    }
+
+    /// Reads memory the same way the CPU would, including memory mapped IO
+    #[inline]
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.memory.read(&self.vga, addr)
+    }
+
+    /// Decodes `count` instructions centered on `addr`, reading memory the same way the CPU would
+    pub fn disassemble_around(&self, addr: u16, count: u32) -> Vec<disassembler::DisassemblyLine> {
+        disassembler::disassemble_around(|addr| self.peek(addr), addr, count)
+    }
+
+    /// Writes memory the same way the CPU would, including memory mapped IO
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.memory.write(&mut self.vga, addr, value);
+        // Writes from the host never cause a bus conflict with the VGA or trigger a watchpoint
+        self.memory.reset_vga_conflict();
+        self.memory.take_watch_hit();
+        self.update_memory_view();
+    }
+
+    #[inline]
+    pub fn invalid_opcode_policy(&self) -> InvalidOpcodePolicy {
+        self.invalid_opcode_policy
+    }
+
+    #[inline]
+    pub fn set_invalid_opcode_policy(&mut self, policy: InvalidOpcodePolicy) {
+        self.invalid_opcode_policy = policy;
+    }
+
+    #[inline]
+    pub fn invalid_instruction(&self) -> Option<InvalidInstruction> {
+        self.invalid_instruction
+    }
+
+    /// Stops the system once the instruction at `addr` is fetched, before it executes.
+    /// Returns false if there already was a break point at the address.
+    #[inline]
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    #[inline]
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    #[inline]
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// The address of the break point that stopped the system last time it was clocked
+    #[inline]
+    pub fn breakpoint_hit(&self) -> Option<u16> {
+        self.breakpoint_hit
+    }
+
+    /// Stops the system after the CPU writes to `addr`, e.g. to find what overwrites a variable.
+    /// Returns false if there already was a watchpoint for any value at the address.
+    #[inline]
+    pub fn add_watchpoint(&mut self, addr: u16) -> bool {
+        self.memory.add_watchpoint(addr, None)
+    }
+
+    /// Like `add_watchpoint`, but only stops if `value` is written to `addr`
+    #[inline]
+    pub fn add_value_watchpoint(&mut self, addr: u16, value: u8) -> bool {
+        self.memory.add_watchpoint(addr, Some(value))
+    }
+
+    /// Removes a watchpoint, `value` has to match the one it was added with
+    #[inline]
+    pub fn remove_watchpoint(&mut self, addr: u16, value: Option<u8>) -> bool {
+        self.memory.remove_watchpoint(addr, value)
+    }
+
+    /// All watchpoints as address and value, `None` matches any value
+    #[inline]
+    pub fn watchpoints(&self) -> impl Iterator<Item = (u16, Option<u8>)> + '_ {
+        self.memory.watchpoints()
+    }
+
+    /// The write that stopped the system last time it was clocked
+    #[inline]
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit
+    }
+
+    /// Stops the system if a push moves SP below the start of `bounds` or a pop moves it past the
+    /// end, which is where SP points while the stack is empty.
+    #[inline]
+    pub fn set_stack_bounds(&mut self, bounds: Range<u16>) {
+        self.cpu.set_stack_bounds(Some(bounds));
+    }
+
+    #[inline]
+    pub fn clear_stack_bounds(&mut self) {
+        self.cpu.set_stack_bounds(None);
+    }
+
+    #[inline]
+    pub fn stack_bounds(&self) -> Option<Range<u16>> {
+        self.cpu.stack_bounds()
+    }
+
+    /// The push or pop that stopped the system last time it was clocked
+    #[inline]
+    pub fn stack_fault(&self) -> Option<StackFault> {
+        self.stack_fault
+    }
+
+    /// Writes a line for every instruction the CPU fetches to `path`, so two runs can be diffed.
+    /// See [`TraceEntry`] for the format of the lines.
+    pub fn set_trace_file(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        const TRACE_BUFFER_SIZE: usize = 1 << 20;
+
+        let file = File::create(path)?;
+        self.trace = Some(BufWriter::with_capacity(TRACE_BUFFER_SIZE, file));
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) -> std::io::Result<()> {
+        match self.trace.take() {
+            Some(mut trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts recording which opcodes the CPU executes, discarding what was recorded before
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(OpcodeCoverage::new());
+    }
+
+    /// Stops recording opcodes and returns what was recorded, if recording was started
+    pub fn stop_coverage(&mut self) -> Option<OpcodeCoverage> {
+        self.coverage.take()
+    }
+
+    #[inline]
+    pub fn coverage(&self) -> Option<&OpcodeCoverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Writes a line for every byte sent to (`>`) or received from (`<`) the UART by the host
+    /// to `path`, prefixed with the cycle count, e.g. `1234 < 3E`.
+    pub fn set_uart_log(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        self.uart_log = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    pub fn stop_uart_log(&mut self) -> std::io::Result<()> {
+        match self.uart_log.take() {
+            Some(mut uart_log) => uart_log.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn log_uart(&mut self, direction: char, byte: u8) {
+        if let Some(uart_log) = &mut self.uart_log {
+            // Stop logging instead of failing on every following byte
+            if writeln!(uart_log, "{} {direction} {byte:0>2X}", self.total_cycles).is_err() {
+                self.uart_log = None;
+            }
+        }
+    }
+
+    // Describes the instruction fetched in the last cycle
+    fn fetched_instruction(&mut self) -> Option<TraceEntry> {
+        let address = self.cpu.fetched_instruction()?;
+        if self.trace_skip_address.take() == Some(address) {
+            return None;
+        }
+
+        let bytes = [
+            self.memory.read(&self.vga, address),
+            self.memory.read(&self.vga, address.wrapping_add(1)),
+        ];
+        let instruction = disassembler::disassemble(&bytes).unwrap();
+        if instruction.length() > 1 {
+            self.trace_skip_address = Some(address.wrapping_add(1));
+        }
+
+        Some(TraceEntry::new(
+            self.total_cycles,
+            address,
+            bytes[..(instruction.length() as usize)].to_vec(),
+            instruction.text().to_owned(),
+            RegisterState::capture(&self.cpu),
+        ))
+    }
+
+    /// Runs the system and compares every fetched instruction against the next line of
+    /// `reference_trace`, which uses the format written by [`System::set_trace_file`].
+    /// Returns the first entry where the address or registers differ, or `None` if the run
+    /// matched until the end of the trace, `max_cycles` or a break point was reached.
+    pub fn run_compare(
+        &mut self,
+        reference_trace: impl BufRead,
+        max_cycles: u64,
+    ) -> std::io::Result<Option<Divergence>> {
+        let mut lines = reference_trace.lines().enumerate();
+        let mut divergence = None;
+
+        for _ in 0..max_cycles {
+            let (break_point, entry) = self.clock_cycle(true);
+
+            if let Some(actual) = entry {
+                let Some((index, line)) = lines.next() else {
+                    break;
+                };
+
+                let expected: TraceEntry = line?.parse().map_err(|err| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("line {}: {err}", index + 1),
+                    )
+                })?;
+
+                if !expected.same_state(&actual) {
+                    divergence = Some(Divergence::new(index + 1, expected, actual));
+                    break;
+                }
+            }
+
+            if break_point {
+                break;
+            }
+        }
+
+        self.finish_clock();
+        Ok(divergence)
+    }
+
     #[inline]
     pub fn clock_rate(&self) -> f64 {
         self.clock_rate
@@ -243,6 +1010,11 @@ impl<Term: Terminal> System<Term> {
         self.cycles_per_frame
     }
 
+    #[inline]
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
     #[inline]
     pub fn framebuffer(&self) -> &[u8] {
         self.vga.framebuffer().pixel_data()
@@ -253,11 +1025,58 @@ impl<Term: Terminal> System<Term> {
         &self.memory_view
     }
 
+    /// How long the last rebuild of the memory view took, always zero on the web
+    #[inline]
+    pub fn memory_view_time(&self) -> Duration {
+        self.memory_view_time
+    }
+
+    /// Number of samples waiting to be played, zero without audio output
+    pub fn audio_buffer_len(&self) -> usize {
+        #[cfg(feature = "audio")]
+        if let AudioOutput::Open(audio_state) = &self.audio_output {
+            return audio_state.sample_buffer.len();
+        }
+
+        0
+    }
+
+    /// Whether samples are played. This is false without the `audio` feature or if the default
+    /// output device could not be opened, in which case the emulator keeps running silently.
+    pub fn audio_available(&self) -> bool {
+        #[cfg(feature = "audio")]
+        {
+            !matches!(self.audio_output, AudioOutput::Unavailable)
+        }
+
+        #[cfg(not(feature = "audio"))]
+        {
+            false
+        }
+    }
+
+    /// Number of bytes written by the host that the UART has not received yet
+    #[inline]
+    pub fn input_queue_len(&self) -> usize {
+        self.input_queue.len()
+    }
+
     #[inline]
     pub fn terminal(&mut self) -> &mut Term {
         &mut self.terminal
     }
 
+    pub fn find_in_memory(&self, pattern: &[u8], start: u16) -> Option<u16> {
+        if pattern.is_empty() {
+            return None;
+        }
+
+        self.memory_view[(start as usize)..]
+            .windows(pattern.len())
+            .position(|window| window == pattern)
+            .map(|offset| start + (offset as u16))
+    }
+
     pub fn write_char(&mut self, c: char) {
         let mut buffer = [0; 4];
         let bytes = c.encode_utf8(&mut buffer).as_bytes();
@@ -267,33 +1086,112 @@ impl<Term: Terminal> System<Term> {
         }
     }
 
+    /// Queues all bytes of `s` to be received by the UART, like typing it into the terminal
+    #[inline]
+    pub fn write_str(&mut self, s: &str) {
+        self.input_queue.extend(s.as_bytes());
+    }
+
+    /// Like `write_str`, but also presses enter at the end to submit e.g. a monitor command
+    pub fn write_line(&mut self, line: &str) {
+        self.write_str(line);
+        self.input_queue.push_back(b'\r');
+    }
+
+    #[cfg(feature = "input")]
     fn button_down(&mut self, button: gilrs::Button) {
         if let Some(button) = map_button(button) {
             self.controler.host_button_down(button);
         }
     }
 
+    #[cfg(feature = "input")]
     fn button_up(&mut self, button: gilrs::Button) {
         if let Some(button) = map_button(button) {
             self.controler.host_button_up(button);
         }
     }
 
+    /// Presses a controller button, for hosts that read gamepads themselves
+    #[inline]
+    pub fn controler_button_down(&mut self, button: ControlerButton) {
+        self.controler.host_button_down(button);
+    }
+
+    #[inline]
+    pub fn controler_button_up(&mut self, button: ControlerButton) {
+        self.controler.host_button_up(button);
+    }
+
+    /// Whether gamepads are read. This is false without the `input` feature or if the platform
+    /// has no gamepad backend, `controler_button_down/up` work either way.
+    pub fn gamepad_available(&self) -> bool {
+        #[cfg(feature = "input")]
+        {
+            self.gilrs.is_some()
+        }
+
+        #[cfg(not(feature = "input"))]
+        {
+            false
+        }
+    }
+
     pub fn load_program(&mut self, base_addr: u16, data: &[u8]) -> Result<(), ()> {
         if ((base_addr as usize) + data.len()) >= 0xE000 {
+            log::warn!(
+                "program of {} bytes does not fit below the monitor",
+                data.len()
+            );
             return Err(());
         }
 
         self.memory.init_region(data, 0);
         self.update_memory_view();
+
+        log::debug!("loaded {} bytes at 0x{base_addr:0>4X}", data.len());
+        Ok(())
+    }
+
+    /// Loads a program at `base_addr`, adjusting all absolute addresses in it
+    /// for the base it was assembled for
+    pub fn load_program_relocated(
+        &mut self,
+        base_addr: u16,
+        data: &[u8],
+        relocs: &assembler::Relocations,
+    ) -> Result<(), ()> {
+        if ((base_addr as usize) + data.len()) >= 0xE000 {
+            log::warn!(
+                "program of {} bytes does not fit below the monitor",
+                data.len()
+            );
+            return Err(());
+        }
+
+        let mut data = data.to_vec();
+        relocs.apply(&mut data, base_addr);
+
+        self.memory.init_region(&data, base_addr);
+        self.update_memory_view();
+
+        log::debug!(
+            "loaded {} relocated bytes at 0x{base_addr:0>4X}",
+            data.len()
+        );
         Ok(())
     }
 
     fn update_memory_view(&mut self) {
-        self.memory_view.clear();
-        for addr in u16::MIN..=u16::MAX {
-            let data = self.memory.read(&self.vga, addr);
-            self.memory_view.push(data);
+        // `Instant` is not available in the browser
+        #[cfg(not(target_family = "wasm"))]
+        let start = std::time::Instant::now();
+
+        self.memory.update_view(&self.vga, &mut self.memory_view);
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            self.memory_view_time = start.elapsed();
         }
     }
 
@@ -304,70 +1202,79 @@ impl<Term: Terminal> System<Term> {
         self.terminal.flush();
     }
 
-    pub fn execute_program(&mut self) {
-        let mut loader_finished = false;
-        loop {
-            self.cpu
-                .clock(
-                    &mut self.memory,
-                    &mut self.uart,
-                    &mut self.audio,
-                    &mut self.vga,
-                    &mut self.controler,
-                    &mut self.spi,
-                )
-                .expect("invalid instruction");
-
-            self.baud_cycles += 1.0;
-            while self.baud_cycles >= self.cycles_per_baud {
-                self.baud_cycles -= self.cycles_per_baud;
-
-                if let Some(data) = self.uart.host_read() {
-                    self.output_queue.push_back(data);
-
-                    if data == b'>' {
-                        loader_finished = true;
-                    }
-                }
+    /// Clocks the system until the UART has sent `pattern` or `max_cycles` have passed, e.g. to wait
+    /// for the response to a command queued with `write_line`. Only output sent after the call is
+    /// searched. Returns false if the budget ran out or a break point was reached first.
+    pub fn run_until_output(&mut self, pattern: &[u8], max_cycles: u64) -> bool {
+        self.invalid_instruction = None;
+        self.watch_hit = None;
+        self.stack_fault = None;
+        self.breakpoint_hit = None;
+
+        // The output is only drained into the terminal by `finish_clock`
+        let start = self.output_queue.len();
+        let mut found = pattern.is_empty();
+        let mut cycles = 0;
+        while !found && (cycles < max_cycles) {
+            let output_len = self.output_queue.len();
+            if self.clock_cycle(false).0 {
+                break;
             }
+            cycles += 1;
 
-            if loader_finished {
-                break;
+            if self.output_queue.len() != output_len {
+                let output = self.output_queue.range(start..);
+                found = (output.len() >= pattern.len())
+                    && output.rev().zip(pattern.iter().rev()).all(|(a, b)| a == b);
             }
         }
 
-        self.update_memory_view();
-        self.process_terminal();
+        self.finish_clock();
 
-        self.uart.host_write(b'j');
-        self.uart.host_write(b'm');
-        self.uart.host_write(b'p');
-        self.uart.host_write(b' ');
-        self.uart.host_write(b'0');
-        self.uart.host_write(b'\r');
+        found
     }
 
-    fn sample_buffer(&mut self) -> Arc<SegQueue<f32>> {
-        if let Some(audio_state) = &self.audio_state {
-            Arc::clone(&audio_state.sample_buffer)
-        } else {
-            let (_audio_stream, audio_handle) = rodio::OutputStream::try_default().unwrap();
+    pub fn execute_program(&mut self) {
+        // The monitor prints its prompt once it is ready for the next command
+        self.run_until_output(b">", u64::MAX);
 
-            let sample_buffer = Arc::new(SegQueue::new());
-            self.audio_state = Some(AudioState {
-                _audio_stream,
-                sample_buffer: Arc::clone(&sample_buffer),
-            });
+        for &data in b"jmp 0\r" {
+            self.log_uart('>', data);
+            self.uart.host_write(data);
+        }
+    }
 
-            let sample_source = SampleSource::new(Arc::clone(&sample_buffer));
-            audio_handle.play_raw(sample_source).unwrap();
+    #[cfg(feature = "audio")]
+    fn sample_buffer(&mut self) -> SampleSink {
+        if let AudioOutput::Closed = self.audio_output {
+            self.audio_output = match AudioState::open() {
+                Ok(audio_state) => AudioOutput::Open(audio_state),
+                Err(err) => {
+                    log::warn!("no audio output, continuing without sound: {err}");
+                    AudioOutput::Unavailable
+                }
+            };
+        }
 
-            sample_buffer
+        match &self.audio_output {
+            AudioOutput::Open(audio_state) => {
+                SampleSink::Buffer(Arc::clone(&audio_state.sample_buffer))
+            }
+            AudioOutput::Closed | AudioOutput::Unavailable => SampleSink::Discard,
         }
     }
 
+    #[cfg(not(feature = "audio"))]
+    #[inline]
+    fn sample_buffer(&mut self) -> DiscardSamples {
+        DiscardSamples
+    }
+
     pub fn clock(&mut self, n: u64) -> bool {
-        while let Some(gilrs::Event { event, .. }) = self.gilrs.next_event() {
+        #[cfg(feature = "input")]
+        while let Some(gilrs::Event { event, .. }) =
+            self.gilrs.as_mut().and_then(|gilrs| gilrs.next_event())
+        {
             match event {
                 gilrs::EventType::ButtonPressed(button, _) => self.button_down(button),
                 gilrs::EventType::ButtonReleased(button, _) => self.button_up(button),
@@ -375,64 +1282,259 @@ impl<Term: Terminal> System<Term> {
             }
         }
 
+        self.invalid_instruction = None;
+        self.watch_hit = None;
+        self.stack_fault = None;
+        self.breakpoint_hit = None;
+
         let mut break_point = false;
         for _ in 0..n {
-            break_point = self
-                .cpu
-                .clock(
-                    &mut self.memory,
-                    &mut self.uart,
-                    &mut self.audio,
-                    &mut self.vga,
-                    &mut self.controler,
-                    &mut self.spi,
-                )
-                .expect("invalid instruction");
-
-            self.baud_cycles += 1.0;
-            while self.baud_cycles >= self.cycles_per_baud {
-                self.baud_cycles -= self.cycles_per_baud;
-
-                if let Some(data) = self.uart.host_read() {
-                    self.output_queue.push_back(data);
+            if self.clock_cycle(false).0 {
+                break_point = true;
+                break;
+            }
+        }
+
+        self.finish_clock();
+
+        break_point
+    }
+
+    /// Runs the system until the CPU fetches the next instruction and returns it,
+    /// together with whether a break point was reached on the way.
+    pub fn step(&mut self) -> (bool, Option<TraceEntry>) {
+        self.invalid_instruction = None;
+        self.watch_hit = None;
+        self.stack_fault = None;
+        self.breakpoint_hit = None;
+
+        let mut result = (false, None);
+        for _ in 0..MAX_STEP_CYCLES {
+            let (break_point, entry) = self.clock_cycle(true);
+            if break_point || entry.is_some() {
+                result = (break_point, entry);
+                break;
+            }
+        }
+
+        self.finish_clock();
+
+        result
+    }
+
+    /// Like `step`, but if the instruction is a call, runs until the call has returned
+    /// and returns the first instruction fetched after it.
+    /// Gives up after one second of emulated time if the call doesn't return.
+    pub fn step_over(&mut self) -> (bool, Option<TraceEntry>) {
+        let (break_point, entry) = self.step();
+
+        let is_call = entry
+            .as_ref()
+            .is_some_and(|entry| entry.instruction().starts_with("call"));
+        if break_point || !is_call {
+            return (break_point, entry);
+        }
+
+        // The call swaps PC and RA, after that RA holds the address the call returns to
+        let caller_flip = self.cpu.flags().contains(Flags::PC_RA_FLIP);
+        let mut return_address = None;
+
+        let mut result = (false, None);
+        for _ in 0..(self.whole_cycles_per_frame * MAX_STEP_OVER_FRAMES) {
+            let (break_point, entry) = self.clock_cycle(true);
+            if return_address.is_none()
+                && (self.cpu.flags().contains(Flags::PC_RA_FLIP) != caller_flip)
+            {
+                return_address = Some(self.cpu.ra());
+            }
+
+            let returned = return_address.is_some()
+                && (entry.as_ref().map(TraceEntry::address) == return_address);
+            if break_point || returned {
+                result = (break_point, entry);
+                break;
+            }
+        }
+
+        self.finish_clock();
+
+        result
+    }
+
+    // Runs the system for a single cycle and returns whether a break point was reached.
+    // If `observe` is set or a trace is running, the instruction fetched this cycle is returned.
+    fn clock_cycle(&mut self, observe: bool) -> (bool, Option<TraceEntry>) {
+        // A failed clock leaves the CPU unchanged, so it can be retried after replacing the
+        // instruction. Both the fetched and the decoded instruction may need to be replaced.
+        let result = loop {
+            let result = self.cpu.clock(
+                &mut self.memory,
+                &mut self.uart,
+                &mut self.audio,
+                &mut self.vga,
+                &mut self.controler,
+                &mut self.spi,
+            );
+
+            match result {
+                Err(err) if self.invalid_opcode_policy == InvalidOpcodePolicy::Nop => {
+                    self.cpu.replace_with_nop(err);
                 }
+                result => break result,
+            }
+        };
+
+        let mut break_point = match result {
+            Ok(break_point) => break_point,
+            Err(err) if self.invalid_opcode_policy == InvalidOpcodePolicy::Halt => {
+                log::warn!("{err}");
+                self.invalid_instruction = Some(err);
+                return (true, None);
+            }
+            Err(err) => panic!("{err}"),
+        };
+        self.total_cycles += 1;
+
+        if let Some(hit) = self.memory.take_watch_hit() {
+            self.watch_hit = Some(hit);
+            break_point = true;
+        }
+
+        if let Some(fault) = self.cpu.take_stack_fault() {
+            log::warn!("{fault}");
+            self.stack_fault = Some(fault);
+            break_point = true;
+        }
 
-                if let Some(data) = self.input_queue.pop_front() {
-                    self.uart.host_write(data);
+        if !self.breakpoints.is_empty() {
+            if let Some(address) = self.cpu.fetched_instruction() {
+                if self.breakpoints.contains(&address) {
+                    self.breakpoint_hit = Some(address);
+                    break_point = true;
                 }
             }
+        }
+
+        let entry = if observe || self.trace.is_some() || self.coverage.is_some() {
+            self.fetched_instruction()
+        } else {
+            None
+        };
+
+        if let (Some(coverage), Some(entry)) = (&mut self.coverage, &entry) {
+            coverage.record(entry.bytes()[0]);
+        }
+
+        if let (Some(trace), Some(entry)) = (&mut self.trace, &entry) {
+            // Stop tracing instead of failing on every following instruction
+            if writeln!(trace, "{entry}").is_err() {
+                self.trace = None;
+            }
+        }
+
+        self.baud_cycles += 1.0;
+        while self.baud_cycles >= self.cycles_per_baud {
+            self.baud_cycles -= self.cycles_per_baud;
+
+            if self.uart.loopback() {
+                // The host is bypassed, its input stays queued until loopback is disabled
+                if self.uart.clock_baud().is_some() {
+                    self.cpu.request_interrupt(UART_INTERRUPT_VECTOR);
+                }
+            } else {
+                if let Some(data) = self.uart.clock_baud() {
+                    self.log_uart('<', data);
+                    self.output_queue.push_back(data);
+                }
 
-            self.fractional_audio_cycles += self.audio_cycles_per_cpu_cylce;
-            let whole_audio_cycles = self.fractional_audio_cycles as u32;
-            self.fractional_audio_cycles -= whole_audio_cycles as f64;
-
-            let sample_buffer = self.sample_buffer();
-            for _ in 0..whole_audio_cycles {
-                let sample = self.audio.clock();
-                self.audio_cycles += 1.0;
-                while self.audio_cycles >= AUDIO_CYCLES_PER_SAMPLE {
-                    self.audio_cycles -= AUDIO_CYCLES_PER_SAMPLE;
-                    sample_buffer.push(sample);
+                let input_queue = &mut self.input_queue;
+                if let Some(data) = self.uart.host_clock_baud(|| input_queue.pop_front()) {
+                    self.log_uart('>', data);
+                    self.cpu.request_interrupt(UART_INTERRUPT_VECTOR);
                 }
             }
+        }
 
-            self.vga_cycles += self.vga_cycles_per_cpu_cycle;
-            let whole_vga_cycles = self.vga_cycles as u32;
-            self.vga_cycles -= whole_vga_cycles as f64;
-            self.vga.clock(&mut self.memory, whole_vga_cycles);
-            self.memory.reset_vga_conflict();
+        if self.deterministic_audio {
+            self.clock_audio_exact();
+        } else {
+            self.clock_audio();
+        }
 
-            self.spi.clock();
+        self.vga_cycles += self.vga_cycles_per_cpu_cycle;
+        let whole_vga_cycles = self.vga_cycles as u32;
+        self.vga_cycles -= whole_vga_cycles as f64;
+        if self.vga.clock(&mut self.memory, whole_vga_cycles) {
+            self.cpu.request_interrupt(VGA_INTERRUPT_VECTOR);
+        }
+        self.memory.reset_vga_conflict();
 
-            if break_point {
-                break;
+        self.spi.clock();
+
+        (break_point, entry)
+    }
+
+    fn clock_audio(&mut self) {
+        self.fractional_audio_cycles += self.audio_cycles_per_cpu_cylce;
+        let whole_audio_cycles = self.fractional_audio_cycles as u32;
+        self.fractional_audio_cycles -= whole_audio_cycles as f64;
+
+        // Output samples fall between two audio cycles, so they are interpolated from both instead
+        // of repeating the latest one. Otherwise the sample timing jitters whenever the clock rate
+        // changes the number of audio cycles per CPU cycle, which is audible as pops.
+        let sample_buffer = self.sample_buffer();
+        for _ in 0..whole_audio_cycles {
+            let mut sample = self.audio.clock();
+            if self.audio_filter {
+                sample = self.low_pass_filter.process(sample);
+            }
+
+            self.audio_cycles += 1.0;
+            while self.audio_cycles >= AUDIO_CYCLES_PER_SAMPLE {
+                self.audio_cycles -= AUDIO_CYCLES_PER_SAMPLE;
+
+                let t = (1.0 - self.audio_cycles) as f32;
+                let interpolated =
+                    self.previous_audio_sample + (sample - self.previous_audio_sample) * t;
+                sample_buffer.push(interpolated * self.master_volume);
+                self.sample_mirror.push(interpolated);
+            }
+            self.previous_audio_sample = sample;
+        }
+    }
+
+    // Same as `clock_audio`, but both ratios are tracked as integer fractions
+    fn clock_audio_exact(&mut self) {
+        let clock_rate = (self.clock_rate.round() as u64).max(1);
+
+        self.audio_phase += AUDIO_CLOCK_HZ;
+        while self.audio_phase >= clock_rate {
+            self.audio_phase -= clock_rate;
+
+            let sample = self.audio.clock();
+            self.sample_phase += SAMPLE_RATE as u64;
+            while self.sample_phase >= AUDIO_CLOCK_HZ {
+                self.sample_phase -= AUDIO_CLOCK_HZ;
+                self.audio_samples.push(sample);
+            }
+        }
+    }
+
+    fn finish_clock(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            if trace.flush().is_err() {
+                self.trace = None;
+            }
+        }
+
+        if let Some(uart_log) = &mut self.uart_log {
+            if uart_log.flush().is_err() {
+                self.uart_log = None;
             }
         }
 
         self.update_memory_view();
         self.process_terminal();
-
-        break_point
     }
 
     pub fn clock_frame(&mut self) -> bool {
@@ -447,6 +1549,9 @@ impl<Term: Terminal> System<Term> {
 
 #[cfg(target_family = "wasm")]
 mod wasm {
+    use super::assembler::MemoryFileProvider;
+    use serde::Serialize;
+    use std::cell::RefCell;
     use wasm_bindgen::prelude::*;
     use wasm_bindgen::Clamped;
 
@@ -546,6 +1651,127 @@ mod wasm {
         fn flush(&mut self) {}
     }
 
+    // Button indices of the browser's standard gamepad mapping, laid out like `map_button`
+    fn map_standard_button(index: u32) -> Option<super::ControlerButton> {
+        use super::ControlerButton;
+
+        match index {
+            0 => Some(ControlerButton::B),
+            1 => Some(ControlerButton::A),
+            2 => Some(ControlerButton::Y),
+            3 => Some(ControlerButton::X),
+            4 => Some(ControlerButton::L),
+            5 => Some(ControlerButton::R),
+            8 => Some(ControlerButton::Select),
+            9 => Some(ControlerButton::Start),
+            12 => Some(ControlerButton::Up),
+            13 => Some(ControlerButton::Down),
+            14 => Some(ControlerButton::Left),
+            15 => Some(ControlerButton::Right),
+            _ => None,
+        }
+    }
+
+    #[derive(Serialize)]
+    struct AssembledProgram<'a> {
+        base: u16,
+        data: &'a [u8],
+    }
+
+    // Lines and columns are zero-based, they are missing for errors that aren't tied to the code
+    #[derive(Serialize)]
+    struct WebDiagnostic<'a> {
+        line: Option<u32>,
+        column: Option<u32>,
+        message: &'a str,
+    }
+
+    thread_local! {
+        // Included files can't be read in the browser, so they are registered by the page instead
+        static FILES: RefCell<MemoryFileProvider> = RefCell::new(MemoryFileProvider::new());
+    }
+
+    /// Makes `contents` available to `.include "name"`, e.g. for the other open editor tabs.
+    /// Registering a name again replaces its contents.
+    #[wasm_bindgen]
+    pub fn register_file(name: &str, contents: &str) {
+        FILES.with_borrow_mut(|files| files.add_file(name, contents));
+    }
+
+    #[wasm_bindgen]
+    pub fn unregister_file(name: &str) -> bool {
+        FILES.with_borrow_mut(|files| files.remove_file(name))
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_files() {
+        FILES.with_borrow_mut(MemoryFileProvider::clear);
+    }
+
+    /// Assembles code without loading it, returning either `{ base, data }`
+    /// or an array of `{ line, column, message }` diagnostics
+    #[wasm_bindgen]
+    pub fn assemble(code: &str) -> JsValue {
+        let result = FILES.with_borrow(|files| {
+            super::assembler::assemble_code_diagnostics_with_provider(code, files)
+        });
+
+        let json = match result {
+            Ok(program) => serde_json::to_string(&AssembledProgram {
+                base: program.base(),
+                data: program.data(),
+            }),
+            Err(diagnostics) => {
+                let diagnostics: Vec<_> = diagnostics
+                    .iter()
+                    .map(|diagnostic| WebDiagnostic {
+                        line: diagnostic
+                            .location()
+                            .map(|location| location.start().line()),
+                        column: diagnostic
+                            .location()
+                            .map(|location| location.start().column()),
+                        message: diagnostic.message(),
+                    })
+                    .collect();
+
+                serde_json::to_string(&diagnostics)
+            }
+        }
+        .expect("assembler output is always serializable");
+
+        to_js(&json)
+    }
+
+    fn to_js(json: &str) -> JsValue {
+        js_sys::JSON::parse(json).expect("serde_json produces valid JSON")
+    }
+
+    #[derive(Serialize)]
+    struct WebDisassemblyLine<'a> {
+        address: u16,
+        bytes: &'a [u8],
+        text: &'a str,
+    }
+
+    #[derive(Serialize)]
+    struct StepResult<'a> {
+        break_point: bool,
+        address: Option<u16>,
+        instruction: Option<&'a str>,
+        pc: u16,
+        ra: u16,
+        sp: u16,
+        si: u16,
+        di: u16,
+        tx: u16,
+        a: u8,
+        b: u8,
+        c: u8,
+        d: u8,
+        flags: u8,
+    }
+
     #[wasm_bindgen]
     pub struct System {
         inner: super::System<WebTerminal>,
@@ -639,26 +1865,96 @@ mod wasm {
             self.inner.load_program(base_addr, data).is_ok()
         }
 
+        pub fn save_state(&self) -> Vec<u8> {
+            self.inner.save_state()
+        }
+
+        pub fn load_state(&mut self, data: &[u8]) -> bool {
+            self.inner.load_state(data).is_ok()
+        }
+
         pub fn clock(&mut self, n: u64) -> bool {
-            for c in terminal::read_uart_data().chars() {
-                self.inner.write_char(c);
-            }
+            self.inner.write_str(&terminal::read_uart_data());
 
             self.inner.clock(n)
         }
 
         pub fn clock_frame(&mut self) -> bool {
-            for c in terminal::read_uart_data().chars() {
-                self.inner.write_char(c);
-            }
+            self.inner.write_str(&terminal::read_uart_data());
 
             self.inner.clock_frame()
         }
 
+        /// Returns `count` instructions centered on `addr` as `{ address, bytes, text }`
+        pub fn disassemble_around(&self, addr: u16, count: u32) -> JsValue {
+            let lines = self.inner.disassemble_around(addr, count);
+            let lines: Vec<_> = lines
+                .iter()
+                .map(|line| WebDisassemblyLine {
+                    address: line.address(),
+                    bytes: line.bytes(),
+                    text: line.instruction().text(),
+                })
+                .collect();
+
+            to_js(&serde_json::to_string(&lines).expect("disassembly is always serializable"))
+        }
+
+        /// Takes a button index of the standard mapping from `navigator.getGamepads()`
+        pub fn button_down(&mut self, index: u32) {
+            if let Some(button) = map_standard_button(index) {
+                self.inner.controler_button_down(button);
+            }
+        }
+
+        pub fn button_up(&mut self, index: u32) {
+            if let Some(button) = map_standard_button(index) {
+                self.inner.controler_button_up(button);
+            }
+        }
+
+        pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+            self.inner.add_breakpoint(addr)
+        }
+
+        pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+            self.inner.remove_breakpoint(addr)
+        }
+
+        /// Runs until the next instruction is fetched, returning `{ break_point, address,
+        /// instruction }` along with all registers after the step
+        pub fn step(&mut self) -> JsValue {
+            self.inner.write_str(&terminal::read_uart_data());
+
+            let (break_point, entry) = self.inner.step();
+            let cpu = self.inner.cpu();
+            let result = StepResult {
+                break_point,
+                address: entry.as_ref().map(|entry| entry.address()),
+                instruction: entry.as_ref().map(|entry| entry.instruction()),
+                pc: cpu.pc(),
+                ra: cpu.ra(),
+                sp: cpu.sp(),
+                si: cpu.si(),
+                di: cpu.di(),
+                tx: cpu.tx(),
+                a: cpu.a(),
+                b: cpu.b(),
+                c: cpu.c(),
+                d: cpu.d(),
+                flags: cpu.flags().bits(),
+            };
+
+            to_js(&serde_json::to_string(&result).expect("step results are always serializable"))
+        }
+
         pub fn assemble(&mut self, code: &str) -> String {
-            match super::assembler::assemble_code(code, false) {
-                Ok((base_addr, data)) => {
-                    if let Err(_) = self.inner.load_program(base_addr, &data) {
+            let result = FILES
+                .with_borrow(|files| super::assembler::assemble_code_with_provider(code, files));
+
+            match result {
+                Ok(program) => {
+                    if let Err(_) = self.inner.load_program(program.base(), program.data()) {
                         "\x1B\x5B1m\x1B\x5B31mError\x1B\x5B39m: assembled binary is too big\x1B\x5B22m".to_owned()
                     } else {
                         "".to_owned()
@@ -669,3 +1965,45 @@ mod wasm {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureTerminal;
+
+    #[test]
+    fn out_of_range_accumulators_are_rejected() {
+        let fields: [fn(&mut System<CaptureTerminal>) -> &mut f64; 5] = [
+            |system| &mut system.fractional_cycles,
+            |system| &mut system.baud_cycles,
+            |system| &mut system.fractional_audio_cycles,
+            |system| &mut system.audio_cycles,
+            |system| &mut system.vga_cycles,
+        ];
+
+        let mut system = System::create(CaptureTerminal::new());
+        system.reset();
+        let period = system.cycles_per_baud;
+        for field in fields {
+            for value in [f64::NAN, f64::INFINITY, -1.0, period, 1e300] {
+                let mut corrupted = System::create(CaptureTerminal::new());
+                corrupted.reset();
+                *field(&mut corrupted) = value;
+
+                let state = corrupted.save_state();
+                assert_eq!(system.load_state(&state), Err(StateError::InvalidData));
+            }
+        }
+    }
+
+    #[test]
+    fn changing_the_clock_rate_keeps_states_loadable() {
+        let mut system = System::create(CaptureTerminal::new());
+        system.reset();
+        system.clock(10);
+        system.set_clock_rate(10_000.0);
+
+        let state = system.save_state();
+        assert_eq!(system.load_state(&state), Ok(()));
+    }
+}
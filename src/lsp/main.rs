@@ -0,0 +1,362 @@
+mod rpc;
+
+use jam1emu_lib::assembler::{self, Analysis, DiagnosticLocation, KeywordKind, Severity};
+use langbox::{FileId, FileServer};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut chars = path.bytes();
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    let path = String::from_utf8(bytes).ok()?;
+
+    // Windows paths are written as `/C:/...` in URIs
+    #[cfg(windows)]
+    let path = path.strip_prefix('/').map(str::to_owned).unwrap_or(path);
+
+    Some(PathBuf::from(path))
+}
+
+fn path_to_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/:-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+
+    uri
+}
+
+fn lsp_range(location: Option<&DiagnosticLocation>) -> Value {
+    let (start, end) = location.map_or(((0, 0), (0, 0)), |location| {
+        (
+            (location.start().line(), location.start().column()),
+            (location.end().line(), location.end().column()),
+        )
+    });
+
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+fn lsp_location(location: &DiagnosticLocation) -> Value {
+    json!({
+        "uri": path_to_uri(location.file()),
+        "range": lsp_range(Some(location)),
+    })
+}
+
+struct Document {
+    file_server: FileServer,
+    file: FileId,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn analyze(path: PathBuf, text: &str) -> Option<Self> {
+        let mut file_server = FileServer::new();
+        let file = file_server
+            .register_file_memory(path, text.to_owned())
+            .ok()?;
+        let analysis = assembler::analyze(&mut file_server, file, true);
+
+        Some(Self {
+            file_server,
+            file,
+            analysis,
+        })
+    }
+
+    fn identifier_at(&self, position: &Value) -> Option<String> {
+        let line = position["line"].as_u64()? as u32;
+        let column = position["character"].as_u64()? as u32;
+
+        assembler::identifier_at(&self.file_server, self.file, line, column)
+            .map(|name| name.to_string())
+    }
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    // URIs that diagnostics were published to, per open document
+    published: HashMap<String, HashSet<String>>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            published: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    fn update_document(&mut self, uri: &str, text: &str, notifications: &mut Vec<Value>) {
+        let Some(path) = uri_to_path(uri) else {
+            return;
+        };
+        let Some(document) = Document::analyze(path, text) else {
+            return;
+        };
+
+        let mut diagnostics = HashMap::<String, Vec<Value>>::new();
+        diagnostics.insert(uri.to_owned(), Vec::new());
+
+        for diagnostic in document.analysis.diagnostics() {
+            let target = diagnostic
+                .location()
+                .map_or_else(|| uri.to_owned(), |location| path_to_uri(location.file()));
+
+            // Severities as defined by the LSP specification
+            let severity = match diagnostic.severity() {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            };
+
+            let mut lsp_diagnostic = json!({
+                "range": lsp_range(diagnostic.location()),
+                "severity": severity,
+                "code": diagnostic.code(),
+                "source": "jam1",
+                "message": diagnostic.message(),
+            });
+
+            if let Some(previous) = diagnostic.previous() {
+                lsp_diagnostic["relatedInformation"] = json!([{
+                    "location": lsp_location(previous),
+                    "message": "previous definition",
+                }]);
+            }
+
+            diagnostics.entry(target).or_default().push(lsp_diagnostic);
+        }
+
+        let published = self.published.entry(uri.to_owned()).or_default();
+        for stale in published.iter() {
+            diagnostics.entry(stale.clone()).or_default();
+        }
+
+        published.clear();
+        for (target, diagnostics) in diagnostics {
+            if !diagnostics.is_empty() {
+                published.insert(target.clone());
+            }
+
+            notifications.push(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": target, "diagnostics": diagnostics },
+            }));
+        }
+
+        self.documents.insert(uri.to_owned(), document);
+    }
+
+    fn close_document(&mut self, uri: &str, notifications: &mut Vec<Value>) {
+        self.documents.remove(uri);
+
+        for target in self.published.remove(uri).into_iter().flatten() {
+            notifications.push(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": target, "diagnostics": [] },
+            }));
+        }
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(document) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+
+        document
+            .identifier_at(&params["position"])
+            .and_then(|name| document.analysis.symbol(&name))
+            .map_or(Value::Null, |symbol| lsp_location(symbol.location()))
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(document) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+
+        let Some(name) = document.identifier_at(&params["position"]) else {
+            return Value::Null;
+        };
+        let Some(symbol) = document.analysis.symbol(&name) else {
+            return Value::Null;
+        };
+
+        let value = match symbol.value() {
+            Some(value) => format!("{name} = {value} (0x{value:X})"),
+            None => format!("{name} = ?"),
+        };
+
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```\n{value}\n```"),
+            },
+        })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        // Completion item kinds as defined by the LSP specification
+        const KIND_VARIABLE: u32 = 6;
+        const KIND_KEYWORD: u32 = 14;
+        const KIND_CONSTANT: u32 = 21;
+
+        let mut items: Vec<Value> = assembler::keywords()
+            .map(|(label, kind)| {
+                let (item_kind, detail) = match kind {
+                    KeywordKind::Directive => (KIND_KEYWORD, "directive"),
+                    KeywordKind::Register => (KIND_VARIABLE, "register"),
+                    KeywordKind::IoRegister => (KIND_VARIABLE, "I/O register"),
+                    KeywordKind::Mnemonic => (KIND_KEYWORD, "instruction"),
+                };
+
+                json!({ "label": label, "kind": item_kind, "detail": detail })
+            })
+            .collect();
+
+        if let Some(document) = self.documents.get(uri) {
+            for (name, symbol) in document.analysis.symbols() {
+                let detail = symbol
+                    .value()
+                    .map(|value| format!("{value} (0x{value:X})"))
+                    .unwrap_or_default();
+
+                items.push(json!({ "label": name, "kind": KIND_CONSTANT, "detail": detail }));
+            }
+        }
+
+        Value::Array(items)
+    }
+
+    fn handle_request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                },
+                "serverInfo": {
+                    "name": "jam1lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let mut notifications = Vec::new();
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        match method {
+            "textDocument/didOpen" => {
+                if let Some(text) = params["textDocument"]["text"].as_str() {
+                    self.update_document(uri, text, &mut notifications);
+                }
+            }
+            "textDocument/didChange" => {
+                // We only advertise full document sync, so the last change contains the whole text
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+
+                if let Some(text) = text {
+                    self.update_document(uri, text, &mut notifications);
+                }
+            }
+            "textDocument/didClose" => {
+                self.close_document(uri, &mut notifications);
+            }
+            _ => {}
+        }
+
+        notifications
+    }
+}
+
+/// Language server for jam-1 assembly, communicating over stdin/stdout
+fn main() -> io::Result<()> {
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut server = Server::new();
+
+    while let Some(message) = rpc::read_message(&mut stdin)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        if method == "exit" {
+            std::process::exit(if server.shutdown { 0 } else { 1 });
+        }
+
+        if let Some(id) = message.get("id") {
+            // Responses to requests sent by us carry an ID but no method
+            if message.get("method").is_none() {
+                continue;
+            }
+
+            let response = match server.handle_request(method, params) {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            };
+
+            rpc::write_message(&mut stdout, &response)?;
+        } else {
+            for notification in server.handle_notification(method, params) {
+                rpc::write_message(&mut stdout, &notification)?;
+            }
+        }
+    }
+
+    Ok(())
+}
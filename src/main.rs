@@ -1,19 +1,26 @@
 mod ansi_escaping;
 mod syntax_highlighting;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use crossterm::{cursor, style, terminal, ExecutableCommand, QueueableCommand};
 use egui_wgpu::winit::Painter;
 use jam1emu_lib::*;
+use serde::{Deserialize, Serialize};
 use spin_sleep_util::{Interval, RateReporter};
-use std::io::{self, Stdout, Write};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Stdout, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use winit::window::Window;
 
+/// Scrollback beyond this many bytes is dropped from the front, a line at a time
+const SCROLLBACK_LIMIT: usize = 1 << 20;
+
 struct NativeTerminal {
     stdout: Stdout,
+    /// Everything printed to the alternate screen, only kept if it is shown after quitting
+    scrollback: Option<String>,
 }
 
 impl vte::Perform for NativeTerminal {
@@ -24,8 +31,15 @@ impl vte::Perform for NativeTerminal {
         if c == '\x7F' {
             self.stdout.queue(cursor::MoveLeft(1)).unwrap();
             self.stdout.queue(Clear(ClearType::UntilNewLine)).unwrap();
+
+            if let Some(scrollback) = &mut self.scrollback {
+                if !scrollback.ends_with('\n') {
+                    scrollback.pop();
+                }
+            }
         } else {
             self.stdout.queue(Print(c)).unwrap();
+            self.push_scrollback(c);
         }
     }
 
@@ -36,6 +50,7 @@ impl vte::Perform for NativeTerminal {
             }
             b'\n' => {
                 self.stdout.queue(cursor::MoveDown(1)).unwrap();
+                self.push_scrollback('\n');
             }
             b'\x08' => {
                 self.stdout.queue(cursor::MoveLeft(1)).unwrap();
@@ -333,13 +348,41 @@ impl Terminal for NativeTerminal {
 }
 
 impl NativeTerminal {
-    fn new() -> Self {
+    fn new(keep_scrollback: bool) -> Self {
         terminal::enable_raw_mode().unwrap();
 
         let mut stdout = io::stdout();
         stdout.execute(terminal::EnterAlternateScreen).unwrap();
 
-        Self { stdout }
+        Self {
+            stdout,
+            scrollback: keep_scrollback.then(String::new),
+        }
+    }
+
+    fn push_scrollback(&mut self, c: char) {
+        let Some(scrollback) = &mut self.scrollback else {
+            return;
+        };
+
+        scrollback.push(c);
+        if scrollback.len() > SCROLLBACK_LIMIT {
+            let excess = scrollback.len() - SCROLLBACK_LIMIT;
+            let line_end = scrollback.as_bytes()[excess..]
+                .iter()
+                .position(|&byte| byte == b'\n');
+            let cut = match line_end {
+                Some(line_end) => excess + line_end + 1,
+                None => scrollback.len(),
+            };
+            scrollback.drain(..cut);
+        }
+    }
+
+    /// The text printed while the alternate screen was active, if it was kept
+    #[inline]
+    fn scrollback(&self) -> Option<&str> {
+        self.scrollback.as_deref()
     }
 
     fn quit(&mut self) -> io::Result<()> {
@@ -400,297 +443,1579 @@ fn process_terminal_input(system: &mut System<NativeTerminal>) {
     }
 }
 
-struct EmuState {
-    running: bool,
-    loop_interval: Interval,
-    loop_reporter: RateReporter,
-    fps: f64,
-    vga_texture: egui::TextureHandle,
-    code: String,
-    assembler_output: String,
-    syntax_highlighter: syntax_highlighting::Highlighter,
+fn parse_search_pattern(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+
+    if text.starts_with('"') {
+        let literal = assembler::parse_string_literal(text)?;
+        literal.chars().map(|c| u8::try_from(c).ok()).collect()
+    } else {
+        text.split_whitespace()
+            .map(|byte| {
+                let byte = byte
+                    .strip_prefix("0x")
+                    .or_else(|| byte.strip_prefix("0X"))
+                    .unwrap_or(byte);
+                u8::from_str_radix(byte, 16).ok()
+            })
+            .collect()
+    }
 }
 
-impl EmuState {
-    fn create(ui_context: &egui::Context) -> Self {
-        let loop_interval = spin_sleep_util::interval(Duration::from_secs_f64(1.0 / FRAME_RATE));
-        let loop_reporter = RateReporter::new(Duration::from_secs_f64(0.5));
-
-        let vga_image = egui::ColorImage::new(SCREEN_SIZE, egui::Color32::BLACK);
-        let vga_texture =
-            ui_context.load_texture("VGA Framebuffer", vga_image, egui::TextureOptions::NEAREST);
+struct MemorySearch {
+    text: String,
+    wrap_around: bool,
+    hit: Option<(u16, usize)>,
+    scroll_to_hit: bool,
+    not_found: bool,
+}
 
+impl MemorySearch {
+    fn new() -> Self {
         Self {
-            running: false,
-            loop_interval,
-            loop_reporter,
-            fps: 0.0,
-            vga_texture,
-            code: String::new(),
-            assembler_output: String::new(),
-            syntax_highlighter: Default::default(),
+            text: String::new(),
+            wrap_around: true,
+            hit: None,
+            scroll_to_hit: false,
+            not_found: false,
         }
     }
 
-    fn update(&mut self, system: &mut System<NativeTerminal>) {
-        self.loop_interval.tick();
+    fn find(&mut self, system: &System<NativeTerminal>, start: u16) {
+        let Some(pattern) = parse_search_pattern(&self.text) else {
+            self.hit = None;
+            self.not_found = true;
+            return;
+        };
 
-        if let Some(fps) = self.loop_reporter.increment_and_report() {
-            self.fps = fps;
+        let mut addr = system.find_in_memory(&pattern, start);
+        if addr.is_none() && self.wrap_around && (start > 0) {
+            addr = system.find_in_memory(&pattern, 0);
         }
 
-        process_terminal_input(system);
+        self.hit = addr.map(|addr| (addr, pattern.len()));
+        self.scroll_to_hit = addr.is_some();
+        self.not_found = addr.is_none();
+    }
+}
 
-        if self.running {
-            let break_point = system.clock_frame();
-            self.running = !break_point;
-        }
+// Bubbles in the pipeline are NOPs that don't match the memory contents, so only the operand
+// is read from memory
+fn disassemble_stage(
+    system: &System<NativeTerminal>,
+    opcode: u8,
+    address: u16,
+) -> disassembler::DisassembledInstruction {
+    let bytes = [opcode, system.peek(address.wrapping_add(1))];
+    disassembler::disassemble(&bytes).unwrap()
+}
 
-        let vga_image = egui::ColorImage::from_rgba_unmultiplied(SCREEN_SIZE, system.framebuffer());
-        self.vga_texture
-            .set(vga_image, egui::TextureOptions::NEAREST);
+// Describes what the last pipeline stage did in the last cycle, e.g. for single-stepping
+fn describe_microstep(system: &System<NativeTerminal>) -> String {
+    let microstep = system.cpu().microstep();
+    let instruction = disassemble_stage(system, microstep.opcode(), microstep.address());
+
+    let mut text = format!(
+        "Step {} of `{instruction}` at 0x{:0>4X}",
+        (microstep.step() as u32) + 1,
+        microstep.address()
+    );
+    if let Some(address) = microstep.bus_address() {
+        text.push_str(&format!(", memory access at 0x{address:0>4X}"));
     }
+    text
+}
 
-    fn draw(&mut self, system: &mut System<NativeTerminal>, ui: &mut egui::Ui) {
-        use egui::panel::*;
-        use egui::style::*;
-        use egui::*;
-
-        SidePanel::new(Side::Right, "code")
-            .default_width(400.0)
-            .show_inside(ui, |ui| {
-                TopBottomPanel::new(TopBottomSide::Bottom, "output")
-                    .show_separator_line(false)
-                    .show_inside(ui, |ui| {
-                        if ui
-                            .add_enabled(!self.running, Button::new("Assemble"))
-                            .clicked()
-                        {
-                            match assembler::assemble_code(&self.code, false) {
-                                Ok((base_addr, data)) => {
-                                    if let Err(_) = system.load_program(base_addr, &data) {
-                                        self.assembler_output =
-                                            "\x1B\x5B1m\x1B\x5B31mError\x1B\x5B39m: assembled binary is too big\x1B\x5B22m".to_owned();
-                                    } else {
-                                        self.assembler_output = String::new();
-                                    }
-                                }
-                                Err(output) => {
-                                    self.assembler_output = output;
-                                }
-                            }
-                        }
+// Table of the instructions currently in each pipeline stage
+fn show_pipeline(ui: &mut egui::Ui, system: &System<NativeTerminal>) {
+    use egui::*;
+
+    Grid::new("pipeline").striped(true).show(ui, |ui| {
+        ui.strong("Stage");
+        ui.strong("Address");
+        ui.strong("Instruction");
+        ui.strong("Step");
+        ui.end_row();
+
+        let names = ["Fetch", "Decode", "Execute"];
+        for (name, stage) in names.into_iter().zip(system.cpu().pipeline()) {
+            let instruction = disassemble_stage(system, stage.opcode(), stage.address());
+
+            ui.label(name);
+            ui.monospace(format!("{:0>4X}", stage.address()));
+            ui.monospace(format!("{:0>2X}  {instruction}", stage.opcode()));
+            ui.label(format!("{}", (stage.step() as u32) + 1));
+            ui.end_row();
+        }
+    });
 
-                        Frame::dark_canvas(ui.style()).show(ui, |ui| {
-                            ScrollArea::both()
-                                .min_scrolled_height(200.0)
-                                .show(ui, |ui| {
-                                    let mut layouter =
-                                        |ui: &Ui, string: &str, _: f32| {
-                                            ui.fonts(|fonts| {
-                                                fonts.layout_job(ansi_escaping::highlight(string))
-                                            })
-                                        };
+    if let Some(address) = system.cpu().microstep().bus_address() {
+        ui.label(format!("Memory access at 0x{address:0>4X}"));
+    }
+}
 
-                                    TextEdit::multiline(&mut self.assembler_output.as_str())
-                                        .desired_width(f32::INFINITY)
-                                        .layouter(&mut layouter)
-                                        .show(ui);
+// Rolling waveform of the recently played samples
+fn show_waveform(ui: &mut egui::Ui, system: &System<NativeTerminal>) {
+    use egui::*;
+
+    let (response, painter) = ui.allocate_painter(vec2(512.0, 128.0), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(16));
+    painter.hline(
+        rect.x_range(),
+        rect.center().y,
+        Stroke::new(1.0, Color32::from_gray(48)),
+    );
+
+    let samples: Vec<f32> = system.recent_samples().collect();
+    let step = rect.width() / ((samples.len() - 1) as f32);
+    let points = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &sample)| {
+            let y = rect.center().y - sample.clamp(-1.0, 1.0) * (rect.height() / 2.0);
+            pos2(rect.left() + (i as f32) * step, y)
+        })
+        .collect();
+    painter.add(Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+}
 
-                                    ui.allocate_space(ui.available_size());
-                                });
-                        });
-                    });
+// Parses `ADDR` or `ADDR=VALUE` in hex, with or without `0x` prefixes
+fn parse_watchpoint(text: &str) -> Option<(u16, Option<u8>)> {
+    fn strip_hex_prefix(text: &str) -> &str {
+        let text = text.trim();
+        text.strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .unwrap_or(text)
+    }
 
-                CentralPanel::default().show_inside(ui, |ui| {
-                    ui.allocate_ui(ui.available_size(), |ui| {
-                        Frame::dark_canvas(ui.style())
-                            .inner_margin(Margin {
-                                left: 4.0,
-                                right: 4.0,
-                                top: 3.0,
-                                bottom: 7.0,
-                            })
-                            .show(ui, |ui| {
-                                ScrollArea::vertical().show(ui, |ui| {
-                                    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
-                                        // `split` instead of `lines` to preserve the final newline
-                                        let code_line_count = self.code.split('\n').count().max(1);
+    let (addr, value) = match text.split_once('=') {
+        Some((addr, value)) => (addr, Some(value)),
+        None => (text, None),
+    };
 
-                                        ui.vertical(|ui| {
-                                            let max_line_number_size =
-                                                code_line_count.ilog10() as usize + 1;
-                                            let mut text = String::with_capacity(
-                                                code_line_count * (max_line_number_size + 1),
-                                            );
-                                            for line_number in 1..=code_line_count {
-                                                use std::fmt::Write;
+    let addr = u16::from_str_radix(strip_hex_prefix(addr), 16).ok()?;
+    let value = match value {
+        Some(value) => Some(u8::from_str_radix(strip_hex_prefix(value), 16).ok()?),
+        None => None,
+    };
 
-                                                if line_number > 1 {
-                                                    writeln!(text).unwrap();
-                                                }
+    Some((addr, value))
+}
 
-                                                write!(
-                                                    text,
-                                                    "{line_number:>width$}",
-                                                    width = max_line_number_size,
-                                                )
-                                                .unwrap();
-                                            }
+#[derive(Default)]
+struct WatchpointEditor {
+    text: String,
+    invalid: bool,
+}
 
-                                            Frame::none().inner_margin(Margin::same(2.0)).show(
-                                                ui,
-                                                |ui| {
-                                                    ui.label(WidgetText::from(text).weak());
-                                                },
-                                            );
-                                        });
+impl WatchpointEditor {
+    fn show(&mut self, ui: &mut egui::Ui, system: &mut System<NativeTerminal>) {
+        use egui::*;
 
-                                        Frame::none()
-                                            .inner_margin(Margin::symmetric(4.0, 0.0))
-                                            .show(ui, |ui| {
-                                                ScrollArea::horizontal().show(ui, |ui| {
-                                                    let mut layouter =
-                                                        |ui: &Ui, string: &str, _: f32| {
-                                                            ui.fonts(|fonts| {
-                                                                fonts.layout_job(self.syntax_highlighter.highlight(string))
-                                                            })
-                                                        };
+        ui.horizontal(|ui| {
+            let response = TextEdit::singleline(&mut self.text)
+                .hint_text("ADDR or ADDR=VALUE")
+                .desired_width(140.0)
+                .show(ui)
+                .response;
+            let submitted =
+                response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+
+            if ui.button("Watch").clicked() || submitted {
+                match parse_watchpoint(&self.text) {
+                    Some((addr, Some(value))) => {
+                        system.add_value_watchpoint(addr, value);
+                        self.text.clear();
+                        self.invalid = false;
+                    }
+                    Some((addr, None)) => {
+                        system.add_watchpoint(addr);
+                        self.text.clear();
+                        self.invalid = false;
+                    }
+                    None => self.invalid = true,
+                }
+            }
 
-                                                    TextEdit::multiline(&mut self.code)
-                                                        .lock_focus(true)
-                                                        .desired_width(f32::INFINITY)
-                                                        .desired_rows(code_line_count)
-                                                        .frame(false)
-                                                        .layouter(&mut layouter)
-                                                        .show(ui);
+            if self.invalid {
+                ui.colored_label(Color32::RED, "Invalid");
+            }
+        });
 
-                                                    ui.allocate_space(
-                                                        ui.available_size() - Vec2::new(8.0, 4.0),
-                                                    );
-                                                });
-                                            });
-                                    });
+        let mut removed = None;
+        for (addr, value) in system.watchpoints() {
+            ui.horizontal(|ui| {
+                if ui.small_button("x").clicked() {
+                    removed = Some((addr, value));
+                }
 
-                                    ui.allocate_space(ui.available_size());
-                                });
-                            });
-                    });
-                });
+                match value {
+                    Some(value) => ui.label(format!("0x{addr:0>4X} = 0x{value:0>2X}")),
+                    None => ui.label(format!("0x{addr:0>4X}")),
+                };
             });
+        }
 
-        SidePanel::new(Side::Right, "ctrl")
-            .show_separator_line(false)
-            .resizable(false)
-            .show_inside(ui, |ui| {
-                ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+        if let Some((addr, value)) = removed {
+            system.remove_watchpoint(addr, value);
+        }
+    }
+}
 
-                TopBottomPanel::new(TopBottomSide::Top, "Emulator Control").show_inside(ui, |ui| {
-                    if ui
-                        .add_enabled(!self.running, Button::new("Load Binary"))
-                        .clicked()
-                    {
-                        let dialog = rfd::FileDialog::new().add_filter("Binary files", &["bin"]);
-                        if let Some(program) = dialog.pick_file() {
-                            let data = std::fs::read(program).unwrap();
-                            system.load_program(0, &data).expect("binary is too big");
-                        }
-                    }
+const STEP_REGISTERS: [cpu::Register; 12] = [
+    cpu::Register::PC,
+    cpu::Register::RA,
+    cpu::Register::SP,
+    cpu::Register::SI,
+    cpu::Register::DI,
+    cpu::Register::TX,
+    cpu::Register::A,
+    cpu::Register::B,
+    cpu::Register::C,
+    cpu::Register::D,
+    cpu::Register::TL,
+    cpu::Register::TH,
+];
+
+// Lets register values be edited by clicking on them while the emulator is paused
+#[derive(Default)]
+struct RegisterEditor {
+    editing: Option<(cpu::Register, String)>,
+    // Values before the last step, the registers it changed are highlighted
+    before_step: Vec<(cpu::Register, u16)>,
+}
 
-                    if self.running {
-                        ui.label(format!(
-                            "{:.2} fps - {}",
-                            self.fps,
-                            format_clock_rate(self.fps * system.cycles_per_frame())
-                        ));
-                    } else {
-                        ui.label(format!("{:.2} fps", self.fps));
-                    }
+impl RegisterEditor {
+    fn begin_step(&mut self, system: &System<NativeTerminal>) {
+        self.before_step = STEP_REGISTERS
+            .iter()
+            .map(|&register| (register, system.get_reg(register)))
+            .collect();
+    }
 
-                    ui.with_layout(
-                        Layout {
-                            main_dir: Direction::LeftToRight,
-                            ..*ui.layout()
-                        },
-                        |ui| {
-                            if ui
-                                .button(if self.running { "Pause" } else { "Run" })
-                                .clicked()
-                            {
-                                self.running = !self.running;
-                            }
+    #[inline]
+    fn clear_highlight(&mut self) {
+        self.before_step.clear();
+    }
 
-                            if ui
-                                .add_enabled(!self.running, Button::new("Single Step"))
-                                .clicked()
-                            {
-                                system.clock(1);
-                            }
+    fn changed_by_step(&self, register: cpu::Register, value: u16) -> bool {
+        self.before_step
+            .iter()
+            .any(|&(before, before_value)| (before == register) && (before_value != value))
+    }
 
-                            if ui
-                                .add_enabled(!self.running, Button::new("Frame Step"))
-                                .clicked()
-                            {
-                                system.clock_frame();
-                            }
+    fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        system: &mut System<NativeTerminal>,
+        name: &str,
+        register: cpu::Register,
+        enabled: bool,
+    ) {
+        use egui::*;
 
-                            if ui.button("Reset").clicked() {
-                                self.running = false;
-                                system.reset();
-                            }
-                        },
-                    );
+        let width = if register.is_8_bit() { 2 } else { 4 };
+        let value = system.get_reg(register);
+        let name = format!("{:<4}", format!("{name}:"));
 
-                    ui.with_layout(
-                        Layout {
-                            main_dir: Direction::LeftToRight,
-                            ..*ui.layout()
-                        },
-                        |ui| {
-                            if ui
-                                .add_enabled(
-                                    self.running && (system.clock_rate() > 1_000.0),
-                                    Button::new("-- Clock Speed"),
-                                )
-                                .clicked()
-                            {
-                                system.set_clock_rate(system.clock_rate() * 0.5);
-                            }
+        let editing = matches!(&self.editing, Some((editing, _)) if *editing == register);
 
-                            if ui
-                                .add_enabled(
-                                    self.running && (system.clock_rate() < 16_000_000.0),
-                                    Button::new("++ Clock Speed"),
-                                )
-                                .clicked()
-                            {
-                                system.set_clock_rate(system.clock_rate() * 2.0);
-                            }
-                        },
-                    );
+        if editing && enabled {
+            let mut finished = false;
 
-                    ui.add_space(10.0);
-                });
+            ui.horizontal(|ui| {
+                ui.label(name);
 
-                TopBottomPanel::new(TopBottomSide::Top, "Register View").show_inside(ui, |ui| {
-                    SidePanel::new(Side::Left, "regs16")
-                        .show_separator_line(false)
-                        .resizable(false)
-                        .frame(Frame {
-                            inner_margin: Margin::symmetric(35.0, 10.0),
-                            ..Default::default()
-                        })
-                        .show_inside(ui, |ui| {
-                            ui.with_layout(ui.layout().with_cross_align(Align::Center), |ui| {
-                                ui.label("16 Bit Regs");
+                let Some((_, text)) = &mut self.editing else {
+                    return;
+                };
 
-                                ui.label(format!("PC: {:0>4X}", system.cpu().pc()));
-                                ui.label(format!("RA: {:0>4X}", system.cpu().ra()));
-                                ui.label(format!("SP: {:0>4X}", system.cpu().sp()));
-                                ui.label(format!("SI: {:0>4X}", system.cpu().si()));
-                                ui.label(format!("DI: {:0>4X}", system.cpu().di()));
-                                ui.label(format!("TX: {:0>4X}", system.cpu().tx()));
-                            });
+                let response = TextEdit::singleline(text)
+                    .desired_width(40.0)
+                    .char_limit(width)
+                    .show(ui)
+                    .response;
+                response.request_focus();
+
+                if ui.input(|input| input.key_pressed(Key::Enter)) {
+                    if let Ok(value) = u16::from_str_radix(text.trim(), 16) {
+                        // Values that don't fit are rejected by `set_reg` and leave the register unchanged
+                        let _ = system.set_reg(register, value);
+                    }
+                    finished = true;
+                } else if ui.input(|input| input.key_pressed(Key::Escape)) {
+                    finished = true;
+                }
+            });
+
+            if finished {
+                self.editing = None;
+            }
+        } else {
+            let mut label = RichText::new(format!("{name}{value:0>width$X}"));
+            if self.changed_by_step(register, value) {
+                label = label.color(Color32::YELLOW);
+            }
+            let response = ui.add(Label::new(label).sense(Sense::click()));
+
+            if enabled && response.on_hover_text("Click to edit").clicked() {
+                self.editing = Some((register, format!("{value:0>width$X}")));
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct EditorSettings {
+    auto_indent: bool,
+    match_brackets: bool,
+    // Allows assembling while running, the program is swapped in at the next frame
+    hot_reload: bool,
+    reload_resets_pc: bool,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            auto_indent: true,
+            match_brackets: true,
+            hot_reload: false,
+            reload_resets_pc: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct DisplaySettings {
+    integer_scale: bool,
+    keep_aspect: bool,
+    crt_filter: bool,
+    scanline_intensity: f32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            integer_scale: false,
+            keep_aspect: true,
+            crt_filter: false,
+            scanline_intensity: 0.4,
+        }
+    }
+}
+
+impl DisplaySettings {
+    // Darkens the lower half of every VGA line and adds a blurred copy of the image on top
+    fn paint_crt(&self, painter: &egui::Painter, rect: egui::Rect, bloom_texture: egui::TextureId) {
+        use egui::*;
+
+        const BLOOM_OFFSETS: [Vec2; 4] =
+            [Vec2::X, Vec2::Y, Vec2::new(-1.0, 0.0), Vec2::new(0.0, -1.0)];
+        const BLOOM_STRENGTH: u8 = 20;
+
+        let pixel_size = rect.size() / Vec2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+
+        // Zero alpha makes the blending additive
+        let bloom_tint =
+            Color32::from_rgba_premultiplied(BLOOM_STRENGTH, BLOOM_STRENGTH, BLOOM_STRENGTH, 0);
+        for offset in BLOOM_OFFSETS {
+            painter.image(
+                bloom_texture,
+                rect.translate(offset * pixel_size),
+                uv,
+                bloom_tint,
+            );
+        }
+
+        let scanline_color = Color32::from_black_alpha((self.scanline_intensity * 255.0) as u8);
+        for row in 0..SCREEN_HEIGHT {
+            let top = rect.top() + (pixel_size.y * ((row as f32) + 0.5));
+            let scanline =
+                Rect::from_x_y_ranges(rect.x_range(), top..=(top + (pixel_size.y * 0.5)));
+            painter.rect_filled(scanline, 0.0, scanline_color);
+        }
+    }
+
+    // Size of the VGA image inside the available space, the rest is letterboxed
+    fn image_size(&self, available: egui::Vec2) -> egui::Vec2 {
+        let screen_size = egui::Vec2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+
+        let mut scale = available / screen_size;
+        if self.keep_aspect {
+            scale = egui::Vec2::splat(scale.min_elem());
+        }
+        if self.integer_scale {
+            // Windows smaller than the screen can't be scaled to whole pixels
+            let whole = |f: f32| if f >= 1.0 { f.floor() } else { f };
+            scale = egui::Vec2::new(whole(scale.x), whole(scale.y));
+        }
+
+        screen_size * scale
+    }
+}
+
+// Non-printable bytes are shown as `.` in the ASCII column of the memory view
+fn ascii_char(byte: u8) -> char {
+    if byte.is_ascii_graphic() || (byte == b' ') {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct MemoryViewSettings {
+    ascii_column: bool,
+    bytes_per_row: usize,
+}
+
+impl Default for MemoryViewSettings {
+    fn default() -> Self {
+        Self {
+            ascii_column: true,
+            bytes_per_row: 16,
+        }
+    }
+}
+
+impl MemoryViewSettings {
+    const ROW_WIDTHS: [usize; 3] = [8, 16, 32];
+
+    // Rows have to divide the address space evenly, even if the config file was edited by hand
+    fn bytes_per_row(&self) -> usize {
+        if Self::ROW_WIDTHS.contains(&self.bytes_per_row) {
+            self.bytes_per_row
+        } else {
+            16
+        }
+    }
+}
+
+fn char_to_byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+// Replaces the selection with a new line that has the same indentation as the current one
+fn insert_indented_line(code: &mut String, state: &mut egui::text_edit::TextEditState) {
+    use egui::text::{CCursor, CCursorRange};
+
+    let Some(range) = state.cursor.char_range() else {
+        return;
+    };
+
+    let [start, end] = range.sorted();
+    let start_byte = char_to_byte_index(code, start.index);
+    let end_byte = char_to_byte_index(code, end.index);
+
+    let line_start = code[..start_byte].rfind('\n').map_or(0, |index| index + 1);
+    let indent: String = code[line_start..start_byte]
+        .chars()
+        .take_while(|&c| (c == ' ') || (c == '\t'))
+        .collect();
+
+    code.replace_range(start_byte..end_byte, &format!("\n{indent}"));
+
+    let cursor = CCursor::new(start.index + 1 + indent.chars().count());
+    state.cursor.set_char_range(Some(CCursorRange::one(cursor)));
+}
+
+// Selects a byte range in the code editor and scrolls it into view
+fn select_definition(
+    ui: &egui::Ui,
+    editor_id: egui::Id,
+    code: &str,
+    output: &egui::text_edit::TextEditOutput,
+    definition: std::ops::Range<usize>,
+) {
+    use egui::text::{CCursor, CCursorRange};
+
+    let start = CCursor::new(code[..definition.start].chars().count());
+    let end = CCursor::new(code[..definition.end].chars().count());
+
+    let mut state = output.state.clone();
+    state
+        .cursor
+        .set_char_range(Some(CCursorRange::two(start, end)));
+    state.store(ui.ctx(), editor_id);
+
+    let rect = output
+        .galley
+        .pos_from_ccursor(start)
+        .translate(output.galley_pos.to_vec2());
+    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+}
+
+fn mnemonic_doc_at(code: &str, char_index: usize) -> Option<assembler::MnemonicDoc> {
+    let mut column = char_index;
+    for line in code.split('\n') {
+        let line_len = line.chars().count();
+        if column <= line_len {
+            let mnemonic = assembler::mnemonic_at(line, column)?;
+            return assembler::mnemonic_doc(&mnemonic);
+        }
+
+        column -= line_len + 1;
+    }
+
+    None
+}
+
+// What the emulator does while the window is not focused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BackgroundMode {
+    Run,
+    Throttle,
+    Pause,
+}
+
+// Why the emulator is or is not running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunState {
+    Running,
+    Paused,
+    Breakpoint(u16),
+    Watchpoint(WatchHit),
+    // Stopped by a `break` instruction
+    Halted,
+    Faulted(Fault),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    InvalidInstruction(cpu::InvalidInstruction),
+    Stack(cpu::StackFault),
+}
+
+impl RunState {
+    // The state after clocking the system stopped, `break_point` being what the clock returned
+    fn after_clock(system: &System<NativeTerminal>, break_point: bool) -> Self {
+        if let Some(invalid_instruction) = system.invalid_instruction() {
+            Self::Faulted(Fault::InvalidInstruction(invalid_instruction))
+        } else if let Some(fault) = system.stack_fault() {
+            Self::Faulted(Fault::Stack(fault))
+        } else if let Some(hit) = system.watch_hit() {
+            Self::Watchpoint(hit)
+        } else if let Some(address) = system.breakpoint_hit() {
+            Self::Breakpoint(address)
+        } else if break_point {
+            Self::Halted
+        } else {
+            Self::Paused
+        }
+    }
+
+    #[inline]
+    fn is_running(self) -> bool {
+        self == Self::Running
+    }
+
+    fn show(self, ui: &mut egui::Ui) {
+        use egui::{Color32, RichText};
+
+        let (color, text) = match self {
+            Self::Running => (Color32::GREEN, "Running".to_owned()),
+            Self::Paused => (Color32::GRAY, "Paused".to_owned()),
+            Self::Breakpoint(address) => {
+                (Color32::YELLOW, format!("Breakpoint at 0x{address:0>4X}"))
+            }
+            Self::Watchpoint(hit) => (
+                Color32::YELLOW,
+                format!(
+                    "Watchpoint: 0x{:0>2X} written to 0x{:0>4X}",
+                    hit.value(),
+                    hit.address()
+                ),
+            ),
+            Self::Halted => (Color32::YELLOW, "Halted by `break`".to_owned()),
+            Self::Faulted(Fault::InvalidInstruction(invalid_instruction)) => (
+                Color32::RED,
+                format!(
+                    "Invalid instruction 0x{:0>2X} at 0x{:0>4X}",
+                    invalid_instruction.opcode(),
+                    invalid_instruction.address()
+                ),
+            ),
+            Self::Faulted(Fault::Stack(fault)) => (Color32::RED, format!("Stopped: {fault}")),
+        };
+
+        ui.label(RichText::new(text).color(color).strong());
+    }
+}
+
+// What "Frame Step" advances the system by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepUnit {
+    Frames,
+    Cycles,
+}
+
+impl StepUnit {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Frames => "frames",
+            Self::Cycles => "cycles",
+        }
+    }
+}
+
+// Overlay on top of the VGA display with timings for diagnosing stutters
+struct PerfHud {
+    visible: bool,
+    sample_start: Instant,
+    sample_cycles: u64,
+    cycles_per_second: f64,
+    // Time spent emulating the last frame, excluding drawing the UI
+    frame_time: Duration,
+}
+
+impl PerfHud {
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn new() -> Self {
+        Self {
+            visible: false,
+            sample_start: Instant::now(),
+            sample_cycles: 0,
+            cycles_per_second: 0.0,
+            frame_time: Duration::ZERO,
+        }
+    }
+
+    fn sample(&mut self, system: &System<NativeTerminal>) {
+        let elapsed = self.sample_start.elapsed();
+        if elapsed >= Self::SAMPLE_INTERVAL {
+            // The cycle counter starts over when the system is reset
+            let cycles = system.total_cycles().saturating_sub(self.sample_cycles);
+            self.cycles_per_second = (cycles as f64) / elapsed.as_secs_f64();
+
+            self.sample_start = Instant::now();
+            self.sample_cycles = system.total_cycles();
+        }
+    }
+
+    fn show(&self, ctx: &egui::Context, rect: egui::Rect, system: &System<NativeTerminal>) {
+        use egui::*;
+
+        Area::new(Id::new("perf_hud"))
+            .fixed_pos(rect.left_top() + Vec2::splat(8.0))
+            .order(Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+
+                    ui.label(format!(
+                        "Cycles: {}",
+                        format_clock_rate(self.cycles_per_second)
+                    ));
+                    ui.label(format!(
+                        "Frame: {:.2} ms",
+                        self.frame_time.as_secs_f64() * 1000.0
+                    ));
+                    ui.label(format!(
+                        "Memory view: {:.2} ms",
+                        system.memory_view_time().as_secs_f64() * 1000.0
+                    ));
+                    if system.audio_available() {
+                        ui.label(format!(
+                            "Audio buffer: {} samples",
+                            system.audio_buffer_len()
+                        ));
+                    } else {
+                        ui.label("Audio buffer: no output device");
+                    }
+                    ui.label(format!("UART input: {} bytes", system.input_queue_len()));
+                    if !system.gamepad_available() {
+                        ui.label("Gamepad: not supported");
+                    }
+                });
+            });
+    }
+}
+
+const BACKGROUND_FRAME_RATE: f64 = 10.0;
+const SAVE_STATE_EXTENSION: &str = "jam1state";
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    editor: EditorSettings,
+    display: DisplaySettings,
+    memory_view: MemoryViewSettings,
+    background_mode: BackgroundMode,
+    master_volume: f32,
+    audio_filter: bool,
+    bus_conflict_emulation: bool,
+    break_on_invalid: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            editor: EditorSettings::default(),
+            display: DisplaySettings::default(),
+            memory_view: MemoryViewSettings::default(),
+            background_mode: BackgroundMode::Throttle,
+            master_volume: 1.0,
+            audio_filter: true,
+            bus_conflict_emulation: true,
+            break_on_invalid: false,
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        let config_dir = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+                })
+        };
+
+        config_dir.map(|dir| dir.join("jam1emu").join("settings.json"))
+    }
+
+    // Missing or invalid settings fall back to the defaults
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let json = serde_json::to_string_pretty(self).expect("settings are always serializable");
+        std::fs::write(path, json)
+    }
+
+    fn apply(&self, system: &mut System<NativeTerminal>) {
+        system.set_master_volume(self.master_volume);
+        system.set_audio_filter(self.audio_filter);
+        system.set_bus_conflict_emulation(self.bus_conflict_emulation);
+        system.set_invalid_opcode_policy(if self.break_on_invalid {
+            InvalidOpcodePolicy::Halt
+        } else {
+            InvalidOpcodePolicy::Fault
+        });
+    }
+
+    // Returns whether any setting was changed
+    fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        use egui::*;
+
+        let mut changed = false;
+
+        ui.heading("Emulation");
+        changed |= ui
+            .checkbox(&mut self.break_on_invalid, "Break on invalid instruction")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.bus_conflict_emulation, "Emulate bus conflicts")
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("In background:");
+            changed |= ui
+                .radio_value(&mut self.background_mode, BackgroundMode::Run, "Run")
+                .changed();
+            changed |= ui
+                .radio_value(
+                    &mut self.background_mode,
+                    BackgroundMode::Throttle,
+                    "Throttle",
+                )
+                .changed();
+            changed |= ui
+                .radio_value(&mut self.background_mode, BackgroundMode::Pause, "Pause")
+                .changed();
+        });
+
+        ui.separator();
+        ui.heading("Audio");
+        changed |= ui
+            .add(Slider::new(&mut self.master_volume, 0.0..=1.0).text("Volume"))
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.audio_filter, "Low-pass filter")
+            .on_hover_text("Smooths the output to avoid aliasing, disable it for the raw sound")
+            .changed();
+
+        ui.separator();
+        ui.heading("Display");
+        changed |= ui
+            .checkbox(&mut self.display.integer_scale, "Integer scaling")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.display.keep_aspect, "Keep aspect ratio")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.display.crt_filter, "CRT filter")
+            .changed();
+        changed |= ui
+            .add_enabled(
+                self.display.crt_filter,
+                Slider::new(&mut self.display.scanline_intensity, 0.0..=1.0).text("Scanlines"),
+            )
+            .changed();
+
+        ui.separator();
+        ui.heading("Memory View");
+        changed |= ui
+            .checkbox(&mut self.memory_view.ascii_column, "ASCII column")
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Bytes per row:");
+            for width in MemoryViewSettings::ROW_WIDTHS {
+                changed |= ui
+                    .radio_value(
+                        &mut self.memory_view.bytes_per_row,
+                        width,
+                        width.to_string(),
+                    )
+                    .changed();
+            }
+        });
+
+        ui.separator();
+        ui.heading("Editor");
+        changed |= ui
+            .checkbox(&mut self.editor.auto_indent, "Auto-indent")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.editor.match_brackets, "Match brackets")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.editor.hot_reload, "Reload program while running")
+            .changed();
+        changed |= ui
+            .add_enabled(
+                self.editor.hot_reload,
+                Checkbox::new(
+                    &mut self.editor.reload_resets_pc,
+                    "Jump to entry point on reload",
+                ),
+            )
+            .changed();
+
+        changed
+    }
+}
+
+struct EmuState {
+    run_state: RunState,
+    focused: bool,
+    settings: Settings,
+    settings_open: bool,
+    pipeline_open: bool,
+    waveform_open: bool,
+    state_error: Option<String>,
+    loop_interval: Interval,
+    background_interval: Interval,
+    loop_reporter: RateReporter,
+    fps: f64,
+    vga_texture: egui::TextureHandle,
+    // Linearly filtered copy of the framebuffer for the CRT filter
+    vga_bloom_texture: egui::TextureHandle,
+    code: String,
+    assembler_output: String,
+    syntax_highlighter: syntax_highlighting::Highlighter,
+    memory_search: MemorySearch,
+    // Start of the region the memory view scrolls to when it is drawn next
+    scroll_to_region: Option<u16>,
+    register_editor: RegisterEditor,
+    watchpoint_editor: WatchpointEditor,
+    perf_hud: PerfHud,
+    // Result of the last successful assembly
+    program: Option<assembler::Program>,
+    // Assembled while running, loaded at the start of the next frame
+    reload_pending: bool,
+    reloaded_at: Option<Instant>,
+    symbol_query: String,
+    step_count: u32,
+    step_unit: StepUnit,
+}
+
+impl EmuState {
+    fn create(ui_context: &egui::Context, system: &mut System<NativeTerminal>) -> Self {
+        let settings = Settings::load();
+        settings.apply(system);
+
+        let loop_interval = spin_sleep_util::interval(Duration::from_secs_f64(1.0 / FRAME_RATE));
+        let background_interval =
+            spin_sleep_util::interval(Duration::from_secs_f64(1.0 / BACKGROUND_FRAME_RATE));
+        let loop_reporter = RateReporter::new(Duration::from_secs_f64(0.5));
+
+        let vga_image = egui::ColorImage::new(SCREEN_SIZE, egui::Color32::BLACK);
+        let vga_texture = ui_context.load_texture(
+            "VGA Framebuffer",
+            vga_image.clone(),
+            egui::TextureOptions::NEAREST,
+        );
+        let vga_bloom_texture =
+            ui_context.load_texture("VGA Bloom", vga_image, egui::TextureOptions::LINEAR);
+
+        Self {
+            run_state: RunState::Paused,
+            focused: true,
+            settings,
+            settings_open: false,
+            pipeline_open: false,
+            waveform_open: false,
+            state_error: None,
+            loop_interval,
+            background_interval,
+            loop_reporter,
+            fps: 0.0,
+            vga_texture,
+            vga_bloom_texture,
+            code: String::new(),
+            assembler_output: String::new(),
+            syntax_highlighter: Default::default(),
+            memory_search: MemorySearch::new(),
+            scroll_to_region: None,
+            register_editor: RegisterEditor::default(),
+            watchpoint_editor: WatchpointEditor::default(),
+            perf_hud: PerfHud::new(),
+            program: None,
+            reload_pending: false,
+            reloaded_at: None,
+            symbol_query: String::new(),
+            step_count: 1,
+            step_unit: StepUnit::Frames,
+        }
+    }
+
+    const RELOAD_TOAST_DURATION: Duration = Duration::from_secs(2);
+
+    // Looks up where a label ended up in the last assembled program
+    fn show_symbol_query(&mut self, ui: &mut egui::Ui) {
+        use egui::*;
+
+        ui.add_enabled(
+            self.program.is_some(),
+            TextEdit::singleline(&mut self.symbol_query)
+                .hint_text("Symbol")
+                .desired_width(120.0),
+        );
+
+        let name = self.symbol_query.trim();
+        if let Some(program) = self.program.as_ref().filter(|_| !name.is_empty()) {
+            match program.symbol(name) {
+                Some(value) => ui.label(format!("0x{value:0>4X} ({value})")),
+                None => ui.colored_label(Color32::RED, "undefined/cyclic"),
+            };
+        }
+    }
+
+    // Returns whether the program was loaded, the output shows the section sizes if it was
+    fn load_assembled(
+        &mut self,
+        system: &mut System<NativeTerminal>,
+        program: &assembler::Program,
+    ) -> bool {
+        // Fails instead of overwriting the monitor
+        if system.load_program(program.base(), program.data()).is_err() {
+            self.assembler_output =
+                "\x1B\x5B1m\x1B\x5B31mError\x1B\x5B39m: assembled binary is too big\x1B\x5B22m"
+                    .to_owned();
+            false
+        } else {
+            self.assembler_output = assembler::format_section_report(program.sections());
+            if let Some(warning) = assembler::monitor_warning(program.sections()) {
+                self.assembler_output
+                    .push_str(&warning.format(&langbox::FileServer::new()));
+            }
+            true
+        }
+    }
+
+    fn update(&mut self, system: &mut System<NativeTerminal>) {
+        // Throttled frames still emulate one VGA frame each, so programs keep running slower
+        let paused = match (self.focused, self.settings.background_mode) {
+            (true, _) | (false, BackgroundMode::Run) => {
+                self.loop_interval.tick();
+                false
+            }
+            (false, BackgroundMode::Throttle) => {
+                self.background_interval.tick();
+                false
+            }
+            (false, BackgroundMode::Pause) => {
+                self.background_interval.tick();
+                true
+            }
+        };
+
+        if let Some(fps) = self.loop_reporter.increment_and_report() {
+            self.fps = fps;
+        }
+
+        process_terminal_input(system);
+
+        // Swapping the program between frames keeps the CPU from running a partially written one
+        if std::mem::take(&mut self.reload_pending) {
+            if let Some(program) = self.program.take() {
+                if self.load_assembled(system, &program) {
+                    if self.settings.editor.reload_resets_pc {
+                        system.cpu_mut().set_pc(program.entry());
+                    }
+                    self.reloaded_at = Some(Instant::now());
+                }
+                self.program = Some(program);
+            }
+        }
+
+        if self.run_state.is_running() && !paused {
+            let start = Instant::now();
+            let break_point = system.clock_frame();
+            self.perf_hud.frame_time = start.elapsed();
+            if break_point {
+                self.run_state = RunState::after_clock(system, true);
+            }
+        }
+        self.perf_hud.sample(system);
+
+        let vga_image = egui::ColorImage::from_rgba_unmultiplied(SCREEN_SIZE, system.framebuffer());
+        if self.settings.display.crt_filter {
+            self.vga_bloom_texture
+                .set(vga_image.clone(), egui::TextureOptions::LINEAR);
+        }
+        self.vga_texture
+            .set(vga_image, egui::TextureOptions::NEAREST);
+    }
+
+    fn draw(&mut self, system: &mut System<NativeTerminal>, ui: &mut egui::Ui) {
+        use egui::panel::*;
+        use egui::style::*;
+        use egui::*;
+
+        // Shortcuts are ignored while typing, e.g. in the code editor
+        let shortcuts_enabled = !ui.ctx().wants_keyboard_input();
+        let shortcut = |modifiers: Modifiers, key: Key| {
+            shortcuts_enabled
+                && ui
+                    .ctx()
+                    .input_mut(|input| input.consume_key(modifiers, key))
+        };
+        let run_shortcut = shortcut(Modifiers::NONE, Key::F5);
+        let assemble_shortcut = shortcut(Modifiers::NONE, Key::F6);
+        let step_over_shortcut = shortcut(Modifiers::NONE, Key::F10);
+        let step_shortcut = shortcut(Modifiers::NONE, Key::F11);
+        let reset_shortcut = shortcut(Modifiers::COMMAND, Key::R);
+        if shortcut(Modifiers::NONE, Key::F3) {
+            self.perf_hud.visible = !self.perf_hud.visible;
+        }
+
+        let mut settings_changed = false;
+        Window::new("Settings")
+            .open(&mut self.settings_open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ui.ctx(), |ui| {
+                settings_changed = self.settings.show(ui);
+            });
+
+        Window::new("Pipeline")
+            .open(&mut self.pipeline_open)
+            .resizable(false)
+            .show(ui.ctx(), |ui| show_pipeline(ui, system));
+
+        Window::new("Audio")
+            .open(&mut self.waveform_open)
+            .resizable(false)
+            .show(ui.ctx(), |ui| show_waveform(ui, system));
+
+        if settings_changed {
+            self.settings.apply(system);
+            if let Err(err) = self.settings.save() {
+                log::warn!("failed to save settings: {err}");
+            }
+        }
+
+        SidePanel::new(Side::Right, "code")
+            .default_width(400.0)
+            .show_inside(ui, |ui| {
+                TopBottomPanel::new(TopBottomSide::Bottom, "output")
+                    .show_separator_line(false)
+                    .show_inside(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let can_assemble =
+                                !self.run_state.is_running() || self.settings.editor.hot_reload;
+                            let assemble_clicked = ui
+                                .add_enabled(can_assemble, Button::new("Assemble"))
+                                .on_hover_text("F6")
+                                .clicked();
+                            if assemble_clicked || (assemble_shortcut && can_assemble) {
+                                match assembler::assemble_code(&self.code, false) {
+                                    Ok(program) => {
+                                        log::debug!(
+                                            "assembled {} bytes at 0x{:0>4X}",
+                                            program.data().len(),
+                                            program.base()
+                                        );
+
+                                        if self.run_state.is_running() {
+                                            self.reload_pending = true;
+                                        } else {
+                                            self.load_assembled(system, &program);
+                                        }
+                                        self.program = Some(program);
+                                    }
+                                    Err(output) => {
+                                        log::warn!("assembly failed");
+                                        self.assembler_output = output;
+                                        self.program = None;
+                                    }
+                                }
+                            }
+
+                            if ui
+                                .add_enabled(!self.run_state.is_running(), Button::new("Format"))
+                                .clicked()
+                            {
+                                match assembler::format_source(&self.code) {
+                                    Ok(code) => {
+                                        self.code = code;
+                                        self.assembler_output = String::new();
+                                    }
+                                    Err(output) => {
+                                        self.assembler_output = output;
+                                    }
+                                }
+                            }
+
+                            ui.separator();
+                            self.show_symbol_query(ui);
+                        });
+
+                        Frame::dark_canvas(ui.style()).show(ui, |ui| {
+                            ScrollArea::both()
+                                .min_scrolled_height(200.0)
+                                .show(ui, |ui| {
+                                    let mut layouter =
+                                        |ui: &Ui, string: &str, _: f32| {
+                                            ui.fonts(|fonts| {
+                                                fonts.layout_job(ansi_escaping::highlight(string))
+                                            })
+                                        };
+
+                                    TextEdit::multiline(&mut self.assembler_output.as_str())
+                                        .desired_width(f32::INFINITY)
+                                        .layouter(&mut layouter)
+                                        .show(ui);
+
+                                    ui.allocate_space(ui.available_size());
+                                });
+                        });
+                    });
+
+                CentralPanel::default().show_inside(ui, |ui| {
+                    ui.allocate_ui(ui.available_size(), |ui| {
+                        Frame::dark_canvas(ui.style())
+                            .inner_margin(Margin {
+                                left: 4.0,
+                                right: 4.0,
+                                top: 3.0,
+                                bottom: 7.0,
+                            })
+                            .show(ui, |ui| {
+                                ScrollArea::vertical().show(ui, |ui| {
+                                    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+                                        // `split` instead of `lines` to preserve the final newline
+                                        let code_line_count = self.code.split('\n').count().max(1);
+
+                                        ui.vertical(|ui| {
+                                            let max_line_number_size =
+                                                code_line_count.ilog10() as usize + 1;
+                                            let mut text = String::with_capacity(
+                                                code_line_count * (max_line_number_size + 1),
+                                            );
+                                            for line_number in 1..=code_line_count {
+                                                use std::fmt::Write;
+
+                                                if line_number > 1 {
+                                                    writeln!(text).unwrap();
+                                                }
+
+                                                write!(
+                                                    text,
+                                                    "{line_number:>width$}",
+                                                    width = max_line_number_size,
+                                                )
+                                                .unwrap();
+                                            }
+
+                                            Frame::none().inner_margin(Margin::same(2.0)).show(
+                                                ui,
+                                                |ui| {
+                                                    ui.label(WidgetText::from(text).weak());
+                                                },
+                                            );
+                                        });
+
+                                        Frame::none()
+                                            .inner_margin(Margin::symmetric(4.0, 0.0))
+                                            .show(ui, |ui| {
+                                                ScrollArea::horizontal().show(ui, |ui| {
+                                                    let editor_id = Id::new("code_editor");
+
+                                                    // Enter has to be handled before the text edit sees it
+                                                    if self.settings.editor.auto_indent
+                                                        && ui.memory(|memory| memory.has_focus(editor_id))
+                                                        && ui.input_mut(|input| {
+                                                            input.consume_key(Modifiers::NONE, Key::Enter)
+                                                        })
+                                                    {
+                                                        if let Some(mut state) =
+                                                            TextEdit::load_state(ui.ctx(), editor_id)
+                                                        {
+                                                            insert_indented_line(&mut self.code, &mut state);
+                                                            state.store(ui.ctx(), editor_id);
+                                                        }
+                                                    }
+
+                                                    let mut marked = Vec::new();
+                                                    if self.settings.editor.match_brackets {
+                                                        let cursor = TextEdit::load_state(ui.ctx(), editor_id)
+                                                            .and_then(|state| state.cursor.char_range())
+                                                            .filter(|range| range.primary == range.secondary);
+
+                                                        if let Some(cursor) = cursor {
+                                                            let offset = char_to_byte_index(&self.code, cursor.primary.index);
+                                                            if let Some((open, close)) =
+                                                                assembler::matching_bracket(&self.code, offset)
+                                                            {
+                                                                marked.push(open..(open + 1));
+                                                                marked.push(close..(close + 1));
+                                                            }
+                                                        }
+                                                    }
+
+                                                    let mut layouter =
+                                                        |ui: &Ui, string: &str, _: f32| {
+                                                            ui.fonts(|fonts| {
+                                                                fonts.layout_job(self.syntax_highlighter.highlight(string, &marked))
+                                                            })
+                                                        };
+
+                                                    let output =
+                                                        TextEdit::multiline(&mut self.code)
+                                                            .id(editor_id)
+                                                            .lock_focus(true)
+                                                            .desired_width(f32::INFINITY)
+                                                            .desired_rows(code_line_count)
+                                                            .frame(false)
+                                                            .layouter(&mut layouter)
+                                                            .show(ui);
+
+                                                    // Ctrl-click selects the definition of the symbol under the cursor
+                                                    if output.response.clicked()
+                                                        && ui.input(|input| input.modifiers.command)
+                                                    {
+                                                        if let Some(range) = output.cursor_range {
+                                                            let offset = char_to_byte_index(&self.code, range.primary.ccursor.index);
+                                                            if let Some(definition) =
+                                                                assembler::definition_at(&self.code, offset)
+                                                            {
+                                                                select_definition(ui, editor_id, &self.code, &output, definition);
+                                                            }
+                                                        }
+                                                    }
+
+                                                    if let Some(pos) = output.response.hover_pos() {
+                                                        let cursor = output
+                                                            .galley
+                                                            .cursor_from_pos(pos - output.galley_pos);
+                                                        let doc = mnemonic_doc_at(
+                                                            &self.code,
+                                                            cursor.ccursor.index,
+                                                        );
+
+                                                        if let Some(doc) = doc {
+                                                            output.response.on_hover_ui_at_pointer(
+                                                                |ui| {
+                                                                    ui.label(
+                                                                        RichText::new(doc.to_string())
+                                                                            .monospace(),
+                                                                    );
+                                                                },
+                                                            );
+                                                        }
+                                                    }
+
+                                                    ui.allocate_space(
+                                                        ui.available_size() - Vec2::new(8.0, 4.0),
+                                                    );
+                                                });
+                                            });
+                                    });
+
+                                    ui.allocate_space(ui.available_size());
+                                });
+                            });
+                    });
+                });
+            });
+
+        SidePanel::new(Side::Right, "ctrl")
+            .show_separator_line(false)
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+
+                TopBottomPanel::new(TopBottomSide::Top, "Emulator Control").show_inside(ui, |ui| {
+                    if ui
+                        .add_enabled(!self.run_state.is_running(), Button::new("Load Binary"))
+                        .clicked()
+                    {
+                        let dialog = rfd::FileDialog::new().add_filter("Binary files", &["bin"]);
+                        if let Some(program) = dialog.pick_file() {
+                            let data = std::fs::read(program).unwrap();
+                            system.load_program(0, &data).expect("binary is too big");
+                        }
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(!self.run_state.is_running(), Button::new("Save State"))
+                            .clicked()
+                        {
+                            let dialog = rfd::FileDialog::new()
+                                .add_filter("Save states", &[SAVE_STATE_EXTENSION]);
+                            if let Some(path) = dialog.save_file() {
+                                if let Err(err) = std::fs::write(&path, system.save_state()) {
+                                    self.state_error = Some(err.to_string());
+                                }
+                            }
+                        }
+
+                        if ui
+                            .add_enabled(!self.run_state.is_running(), Button::new("Load State"))
+                            .clicked()
+                        {
+                            let dialog = rfd::FileDialog::new()
+                                .add_filter("Save states", &[SAVE_STATE_EXTENSION]);
+                            if let Some(path) = dialog.pick_file() {
+                                self.run_state = RunState::Paused;
+                                self.register_editor.clear_highlight();
+                                self.state_error = match std::fs::read(&path) {
+                                    Ok(data) => {
+                                        system.load_state(&data).err().map(|err| err.to_string())
+                                    }
+                                    Err(err) => Some(err.to_string()),
+                                };
+                            }
+                        }
+                    });
+
+                    if let Some(error) = &self.state_error {
+                        ui.colored_label(Color32::RED, error);
+                    }
+
+                    if self.run_state.is_running() {
+                        ui.label(format!(
+                            "{:.2} fps - {}",
+                            self.fps,
+                            format_clock_rate(self.fps * system.cycles_per_frame())
+                        ));
+                    } else {
+                        ui.label(format!("{:.2} fps", self.fps));
+                    }
+                    ui.label(format!("{} cycles", system.total_cycles()));
+
+                    let conflicts = system.conflict_stats();
+                    ui.label(format!("{} bus conflicts", conflicts.total()))
+                        .on_hover_text(format!(
+                            "Framebuffer: {}\nPalette: {}\nTile data: {}",
+                            conflicts.framebuffer(),
+                            conflicts.palette(),
+                            conflicts.tile_data()
+                        ));
+
+                    self.run_state.show(ui);
+
+                    ui.with_layout(
+                        Layout {
+                            main_dir: Direction::LeftToRight,
+                            ..*ui.layout()
+                        },
+                        |ui| {
+                            let run_clicked = ui
+                                .button(if self.run_state.is_running() {
+                                    "Pause"
+                                } else {
+                                    "Run"
+                                })
+                                .on_hover_text("F5")
+                                .clicked();
+                            if run_clicked || run_shortcut {
+                                self.run_state = if self.run_state.is_running() {
+                                    RunState::Paused
+                                } else {
+                                    self.register_editor.clear_highlight();
+                                    RunState::Running
+                                };
+                            }
+
+                            let step_clicked = ui
+                                .add_enabled(
+                                    !self.run_state.is_running(),
+                                    Button::new("Single Step"),
+                                )
+                                .on_hover_text("F11")
+                                .clicked();
+                            if step_clicked || (step_shortcut && !self.run_state.is_running()) {
+                                self.register_editor.begin_step(system);
+                                let break_point = system.clock(1);
+                                self.run_state = RunState::after_clock(system, break_point);
+                            }
+
+                            let step_over_clicked = ui
+                                .add_enabled(!self.run_state.is_running(), Button::new("Step Over"))
+                                .on_hover_text("Runs calls to completion (F10)")
+                                .clicked();
+                            if step_over_clicked
+                                || (step_over_shortcut && !self.run_state.is_running())
+                            {
+                                self.register_editor.begin_step(system);
+                                let (break_point, _) = system.step_over();
+                                self.run_state = RunState::after_clock(system, break_point);
+                            }
+
+                            if ui
+                                .add_enabled(
+                                    !self.run_state.is_running(),
+                                    Button::new("Frame Step"),
+                                )
+                                .on_hover_text("Stops early at breakpoints")
+                                .clicked()
+                            {
+                                self.register_editor.begin_step(system);
+                                let break_point = match self.step_unit {
+                                    StepUnit::Frames => {
+                                        (0..self.step_count).any(|_| system.clock_frame())
+                                    }
+                                    StepUnit::Cycles => system.clock(self.step_count as u64),
+                                };
+                                self.run_state = RunState::after_clock(system, break_point);
+                            }
+
+                            ui.add_enabled(
+                                !self.run_state.is_running(),
+                                DragValue::new(&mut self.step_count).range(1..=100_000),
+                            );
+                            ComboBox::from_id_source("step_unit")
+                                .selected_text(self.step_unit.name())
+                                .show_ui(ui, |ui| {
+                                    for unit in [StepUnit::Frames, StepUnit::Cycles] {
+                                        ui.selectable_value(&mut self.step_unit, unit, unit.name());
+                                    }
+                                });
+
+                            let reset_clicked =
+                                ui.button("Reset").on_hover_text("Ctrl+R").clicked();
+                            if reset_clicked || reset_shortcut {
+                                self.run_state = RunState::Paused;
+                                self.register_editor.clear_highlight();
+                                system.reset();
+                            }
+
+                            if ui.button("Pipeline").clicked() {
+                                self.pipeline_open = !self.pipeline_open;
+                            }
+
+                            if ui.button("Audio").clicked() {
+                                self.waveform_open = !self.waveform_open;
+                            }
+
+                            if ui.button("Settings").clicked() {
+                                self.settings_open = !self.settings_open;
+                            }
+                        },
+                    );
+
+                    ui.with_layout(
+                        Layout {
+                            main_dir: Direction::LeftToRight,
+                            ..*ui.layout()
+                        },
+                        |ui| {
+                            if ui
+                                .add_enabled(
+                                    self.run_state.is_running() && (system.clock_rate() > 1_000.0),
+                                    Button::new("-- Clock Speed"),
+                                )
+                                .clicked()
+                            {
+                                system.set_clock_rate(system.clock_rate() * 0.5);
+                            }
+
+                            if ui
+                                .add_enabled(
+                                    self.run_state.is_running()
+                                        && (system.clock_rate() < 16_000_000.0),
+                                    Button::new("++ Clock Speed"),
+                                )
+                                .clicked()
+                            {
+                                system.set_clock_rate(system.clock_rate() * 2.0);
+                            }
+                        },
+                    );
+
+                    self.watchpoint_editor.show(ui, system);
+
+                    if !self.run_state.is_running() {
+                        ui.label(describe_microstep(system));
+                    }
+
+                    ui.add_space(10.0);
+                });
+
+                TopBottomPanel::new(TopBottomSide::Top, "Register View").show_inside(ui, |ui| {
+                    SidePanel::new(Side::Left, "regs16")
+                        .show_separator_line(false)
+                        .resizable(false)
+                        .frame(Frame {
+                            inner_margin: Margin::symmetric(35.0, 10.0),
+                            ..Default::default()
+                        })
+                        .show_inside(ui, |ui| {
+                            ui.with_layout(ui.layout().with_cross_align(Align::Center), |ui| {
+                                ui.label("16 Bit Regs");
+
+                                let editable = !self.run_state.is_running();
+                                for (name, register) in [
+                                    ("PC", cpu::Register::PC),
+                                    ("RA", cpu::Register::RA),
+                                    ("SP", cpu::Register::SP),
+                                    ("SI", cpu::Register::SI),
+                                    ("DI", cpu::Register::DI),
+                                    ("TX", cpu::Register::TX),
+                                ] {
+                                    self.register_editor
+                                        .show(ui, system, name, register, editable);
+                                }
+                            });
                         });
 
                     SidePanel::new(Side::Left, "regs8")
@@ -704,12 +2029,18 @@ impl EmuState {
                             ui.with_layout(ui.layout().with_cross_align(Align::Center), |ui| {
                                 ui.label("8 Bit Regs");
 
-                                ui.label(format!("A:  {:0>2X}", system.cpu().a()));
-                                ui.label(format!("B:  {:0>2X}", system.cpu().b()));
-                                ui.label(format!("C:  {:0>2X}", system.cpu().c()));
-                                ui.label(format!("D:  {:0>2X}", system.cpu().d()));
-                                ui.label(format!("TL: {:0>2X}", system.cpu().tl()));
-                                ui.label(format!("TH: {:0>2X}", system.cpu().th()));
+                                let editable = !self.run_state.is_running();
+                                for (name, register) in [
+                                    ("A", cpu::Register::A),
+                                    ("B", cpu::Register::B),
+                                    ("C", cpu::Register::C),
+                                    ("D", cpu::Register::D),
+                                    ("TL", cpu::Register::TL),
+                                    ("TH", cpu::Register::TH),
+                                ] {
+                                    self.register_editor
+                                        .show(ui, system, name, register, editable);
+                                }
                             });
                         });
 
@@ -724,29 +2055,39 @@ impl EmuState {
                             ui.with_layout(ui.layout().with_cross_align(Align::Center), |ui| {
                                 ui.label("Flags");
 
-                                let overflow_val: u8 =
-                                    system.cpu().flags().contains(cpu::Flags::OVERFLOW).into();
-                                let sign_val: u8 =
-                                    system.cpu().flags().contains(cpu::Flags::SIGN).into();
-                                let zero_val: u8 =
-                                    system.cpu().flags().contains(cpu::Flags::ZERO).into();
-                                let carry_a_val: u8 =
-                                    system.cpu().flags().contains(cpu::Flags::CARRY_A).into();
-                                let carry_l_val: u8 =
-                                    system.cpu().flags().contains(cpu::Flags::CARRY_L).into();
-                                let flip_val: u8 =
-                                    system.cpu().flags().contains(cpu::Flags::PC_RA_FLIP).into();
-
                                 ui.label("F L C Z S O");
-                                ui.label(format!(
-                                    "{} {} {} {} {} {}",
-                                    flip_val,
-                                    carry_l_val,
-                                    carry_a_val,
-                                    zero_val,
-                                    sign_val,
-                                    overflow_val
-                                ));
+
+                                // The PC-RA flip is controlled by the pipeline and cannot be toggled
+                                let flags = system.cpu().flags();
+                                ui.horizontal(|ui| {
+                                    ui.spacing_mut().item_spacing.x = 0.0;
+
+                                    for (i, flag) in [
+                                        cpu::Flags::PC_RA_FLIP,
+                                        cpu::Flags::CARRY_L,
+                                        cpu::Flags::CARRY_A,
+                                        cpu::Flags::ZERO,
+                                        cpu::Flags::SIGN,
+                                        cpu::Flags::OVERFLOW,
+                                    ]
+                                    .into_iter()
+                                    .enumerate()
+                                    {
+                                        if i > 0 {
+                                            ui.label(" ");
+                                        }
+
+                                        let value: u8 = flags.contains(flag).into();
+                                        let toggleable = !self.run_state.is_running()
+                                            && (flag != cpu::Flags::PC_RA_FLIP);
+                                        let label =
+                                            Label::new(value.to_string()).sense(Sense::click());
+
+                                        if ui.add(label).clicked() && toggleable {
+                                            system.cpu_mut().set_flags(flags ^ flag);
+                                        }
+                                    }
+                                });
                             });
                         });
                 });
@@ -756,26 +2097,165 @@ impl EmuState {
                         ui.label("Memory")
                     });
 
-                    ui.label("ADDR | 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F");
+                    ui.with_layout(
+                        Layout {
+                            main_dir: Direction::LeftToRight,
+                            ..*ui.layout()
+                        },
+                        |ui| {
+                            let search = &mut self.memory_search;
+
+                            let response = TextEdit::singleline(&mut search.text)
+                                .hint_text("Hex bytes or \"string\"")
+                                .desired_width(160.0)
+                                .show(ui)
+                                .response;
+                            let submitted = response.lost_focus()
+                                && ui.input(|input| input.key_pressed(Key::Enter));
+
+                            if ui.button("Find").clicked() || submitted {
+                                search.find(system, 0);
+                            }
+
+                            if ui
+                                .add_enabled(search.hit.is_some(), Button::new("Find Next"))
+                                .clicked()
+                            {
+                                let start = search.hit.map_or(0, |(addr, _)| addr.wrapping_add(1));
+                                search.find(system, start);
+                            }
+
+                            ui.checkbox(&mut search.wrap_around, "Wrap");
+
+                            if search.not_found {
+                                ui.colored_label(Color32::RED, "Not found");
+                            }
+
+                            ComboBox::from_id_source("jump_to_region")
+                                .selected_text("Jump to")
+                                .show_ui(ui, |ui| {
+                                    for region in MEMORY_REGIONS {
+                                        if ui.selectable_label(false, region.name()).clicked() {
+                                            self.scroll_to_region = Some(region.start());
+                                        }
+                                    }
+                                });
+                        },
+                    );
+
+                    let ascii_column = self.settings.memory_view.ascii_column;
+                    let bytes_per_row = self.settings.memory_view.bytes_per_row();
+
+                    let mut header = String::from("ADDR |");
+                    for i in 0..bytes_per_row {
+                        use std::fmt::Write;
+
+                        write!(header, " {i:0>2X}").unwrap();
+                    }
+                    if ascii_column {
+                        header.push_str(" | ASCII");
+                    }
+
+                    ui.label(header);
                     ui.separator();
 
                     ScrollArea::new([false, true]).show(ui, |ui| {
-                        for addr in (u16::MIN..=u16::MAX).step_by(16) {
-                            use std::fmt::Write;
-
-                            // Length of one line is 6 characters for `ADDR |` + 3 characters for each byte.
-                            let mut line = String::with_capacity(6 + 16 * 3);
-                            write!(line, "{:0>4X} |", addr).unwrap();
-                            for i in 0..16 {
-                                write!(
-                                    line,
-                                    " {:0>2X}",
-                                    system.memory_view()[(addr as usize) + i],
-                                )
-                                .unwrap();
+                        use egui::text::{LayoutJob, TextFormat};
+
+                        let text_format = TextFormat {
+                            color: ui.visuals().text_color(),
+                            ..Default::default()
+                        };
+                        let hit_format = TextFormat {
+                            background: ui.visuals().selection.bg_fill,
+                            ..text_format.clone()
+                        };
+
+                        let hit = self.memory_search.hit.map(|(addr, len)| {
+                            let start = addr as usize;
+                            start..(start + len)
+                        });
+
+                        for addr in (u16::MIN..=u16::MAX).step_by(bytes_per_row) {
+                            let row = (addr as usize)..((addr as usize) + bytes_per_row);
+                            let row_hit = hit
+                                .as_ref()
+                                .filter(|hit| (hit.start < row.end) && (hit.end > row.start));
+
+                            // Regions start at page boundaries, so they always line up with a row
+                            let region = MEMORY_REGIONS
+                                .iter()
+                                .find(|region| row.start == (region.start() as usize));
+                            if let Some(region) = region {
+                                ui.separator();
+                                let response = ui.label(
+                                    RichText::new(format!(
+                                        "{} (0x{:0>4X}-0x{:0>4X})",
+                                        region.name(),
+                                        region.start(),
+                                        region.last()
+                                    ))
+                                    .strong(),
+                                );
+
+                                if self.scroll_to_region == Some(region.start()) {
+                                    response.scroll_to_me(Some(Align::TOP));
+                                    self.scroll_to_region = None;
+                                }
                             }
 
-                            ui.label(line);
+                            if let Some(hit) = row_hit {
+                                let mut job = LayoutJob::default();
+                                job.append(&format!("{:0>4X} |", addr), 0.0, text_format.clone());
+                                let format = |i: usize| {
+                                    if hit.contains(&i) {
+                                        hit_format.clone()
+                                    } else {
+                                        text_format.clone()
+                                    }
+                                };
+
+                                for i in row.clone() {
+                                    job.append(" ", 0.0, text_format.clone());
+                                    job.append(
+                                        &format!("{:0>2X}", system.memory_view()[i]),
+                                        0.0,
+                                        format(i),
+                                    );
+                                }
+
+                                if ascii_column {
+                                    job.append(" | ", 0.0, text_format.clone());
+                                    for i in row {
+                                        let c = ascii_char(system.memory_view()[i]);
+                                        job.append(c.encode_utf8(&mut [0; 4]), 0.0, format(i));
+                                    }
+                                }
+
+                                let response = ui.label(job);
+                                if self.memory_search.scroll_to_hit && (hit.start >= addr as usize)
+                                {
+                                    response.scroll_to_me(Some(Align::Center));
+                                    self.memory_search.scroll_to_hit = false;
+                                }
+                            } else {
+                                use std::fmt::Write;
+
+                                // Length of one line is 6 characters for `ADDR |` + 3 characters for each byte,
+                                // the ASCII column adds 2 characters for ` |` + 1 character for each byte.
+                                let mut line = String::with_capacity(6 + bytes_per_row * 4 + 2);
+                                write!(line, "{:0>4X} |", addr).unwrap();
+                                for i in row.clone() {
+                                    write!(line, " {:0>2X}", system.memory_view()[i]).unwrap();
+                                }
+
+                                if ascii_column {
+                                    line.push_str(" | ");
+                                    line.extend(row.map(|i| ascii_char(system.memory_view()[i])));
+                                }
+
+                                ui.label(line);
+                            }
                         }
                     });
                 });
@@ -790,19 +2270,52 @@ impl EmuState {
                 ..Default::default()
             }))
             .show_inside(ui, |ui| {
-                const SCREEN_SIZE: Vec2 = Vec2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
-
-                let xf = ui.available_width() / SCREEN_SIZE.x;
-                let yf = ui.available_height() / SCREEN_SIZE.y;
-                let f = f32::min(xf, yf);
-                let size = SCREEN_SIZE * f;
+                let size = self.settings.display.image_size(ui.available_size());
 
                 ui.centered_and_justified(|ui| {
-                    ui.image((self.vga_texture.id(), size));
+                    let response = ui.add(
+                        Image::new((self.vga_texture.id(), size))
+                            .maintain_aspect_ratio(self.settings.display.keep_aspect),
+                    );
+
+                    if self.settings.display.crt_filter {
+                        // The response covers the whole panel, the image is centered in it
+                        let rect = Rect::from_center_size(response.rect.center(), size);
+                        let painter = ui.painter_at(rect);
+                        self.settings.display.paint_crt(
+                            &painter,
+                            rect,
+                            self.vga_bloom_texture.id(),
+                        );
+                    }
+
+                    if self.perf_hud.visible {
+                        self.perf_hud.show(ui.ctx(), response.rect, system);
+                    }
+
+                    if let Some(reloaded_at) = self.reloaded_at {
+                        if reloaded_at.elapsed() < Self::RELOAD_TOAST_DURATION {
+                            Area::new(Id::new("reload_toast"))
+                                .pivot(Align2::RIGHT_TOP)
+                                .fixed_pos(response.rect.right_top() + vec2(-8.0, 8.0))
+                                .order(Order::Foreground)
+                                .interactable(false)
+                                .show(ui.ctx(), |ui| {
+                                    Frame::popup(ui.style()).show(ui, |ui| ui.label("Reloaded"));
+                                });
+                        } else {
+                            self.reloaded_at = None;
+                        }
+                    }
                 })
             });
     }
 
+    #[inline]
+    fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
     #[inline]
     fn quit(&mut self, system: &mut System<NativeTerminal>) {
         system.terminal().quit().unwrap();
@@ -816,6 +2329,356 @@ struct Args {
     /// Binary file to load and run
     #[clap(short, long, value_parser)]
     run: Option<PathBuf>,
+
+    /// Assembly file to check for errors without running the emulator
+    #[clap(long, value_parser)]
+    check: Option<PathBuf>,
+
+    /// Print the diagnostics of `--check` as JSON
+    #[clap(long, requires = "check")]
+    json: bool,
+
+    /// Print where every symbol is defined and used if `--check` succeeds
+    #[clap(long, requires = "check", conflicts_with = "json")]
+    xref: bool,
+
+    /// Warn about symbols that are never used in `--check`
+    #[clap(long, requires = "check")]
+    warn_unused: bool,
+
+    /// Print the base, size and free space of every section if `--check` succeeds
+    #[clap(long, requires = "check", conflicts_with = "json")]
+    sections: bool,
+
+    /// Write the program assembled by `--check` to this file
+    #[clap(short, long, value_parser, requires = "check")]
+    output: Option<PathBuf>,
+
+    /// Format of the file written to `--output`
+    #[clap(long, value_enum, default_value_t = OutputFormat::Bin, requires = "output")]
+    format: OutputFormat,
+
+    /// Pad the program written to `--output` to this many bytes, e.g. 0x2000 for an 8 KB ROM
+    #[clap(long, value_parser = parse_size, requires = "output")]
+    rom_size: Option<usize>,
+
+    /// Byte to pad the program with for `--rom-size`
+    #[clap(long, value_parser = parse_byte, default_value = "0xFF", requires = "rom_size")]
+    rom_fill: u8,
+
+    /// Write a checksum into the program written to `--output`, after padding it
+    #[clap(long, value_enum, requires = "output")]
+    checksum: Option<ChecksumArg>,
+
+    /// Offset of the checksum in the program, by default the last bytes
+    #[clap(long, value_parser = parse_size, requires = "checksum")]
+    checksum_at: Option<usize>,
+
+    /// Write a line for every executed instruction to this file
+    #[clap(long, value_parser)]
+    trace: Option<PathBuf>,
+
+    /// Print everything the program wrote to the terminal after quitting, or write it to FILE
+    #[clap(long, value_name = "FILE", num_args = 0..=1)]
+    keep_output: Option<Option<PathBuf>>,
+
+    /// Write every byte sent to or received from the UART to this file
+    #[clap(long, value_parser)]
+    uart_log: Option<PathBuf>,
+
+    /// Monitor ROM image to use instead of the built-in one
+    #[clap(long, value_parser)]
+    monitor: Option<PathBuf>,
+
+    /// Stop if a push or pop moves SP outside of START..END, END being SP with an empty stack
+    #[clap(long, value_parser = parse_range)]
+    stack_bounds: Option<std::ops::Range<u16>>,
+
+    /// Resume from a saved state instead of booting the monitor
+    #[clap(long, value_parser, conflicts_with = "run")]
+    load_state: Option<PathBuf>,
+
+    /// Binary file to print the disassembly of
+    #[clap(long, value_parser)]
+    disasm: Option<PathBuf>,
+
+    /// Address the binary of `--disasm` is loaded at
+    #[clap(long, value_parser = parse_size, default_value = "0", requires = "disasm")]
+    base: usize,
+
+    /// File with lines of `name = value` to label the addresses in `--disasm`
+    #[clap(long, value_parser, requires = "disasm")]
+    symbols: Option<PathBuf>,
+
+    /// Print which opcodes were never executed when the emulator exits
+    #[clap(long)]
+    coverage: bool,
+
+    /// Emulate this many frames without a window, then print how fast they ran
+    #[clap(long, value_parser)]
+    bench: Option<u32>,
+}
+
+// Reads `name = value` lines like constant definitions, ignoring empty lines and `;` comments
+fn read_symbols(path: &Path) -> Result<HashMap<u16, String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+
+    let mut symbols = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = || format!("{}:{}: expected `name = address`", path.display(), i + 1);
+        let (name, value) = line.split_once('=').ok_or_else(invalid)?;
+        let address = parse_size(value.trim())
+            .ok()
+            .and_then(|value| u16::try_from(value).ok())
+            .ok_or_else(invalid)?;
+
+        // The first name wins if several symbols share an address
+        symbols
+            .entry(address)
+            .or_insert_with(|| name.trim().to_owned());
+    }
+
+    Ok(symbols)
+}
+
+fn disassemble_file(path: &Path, base: usize, symbols: Option<&Path>) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    if (base + bytes.len()) > 0x10000 {
+        return Err(format!(
+            "{}: {} bytes at 0x{base:0>4X} don't fit into the address space",
+            path.display(),
+            bytes.len()
+        ));
+    }
+
+    let symbols = match symbols {
+        Some(symbols) => read_symbols(symbols)?,
+        None => HashMap::new(),
+    };
+
+    let lines = disassembler::disassemble_program(&bytes, base as u16);
+    print!("{}", disassembler::format_listing(&lines, &symbols));
+    Ok(())
+}
+
+fn bench(args: &Args, frames: u32) -> Result<(), String> {
+    use jam1emu_lib::capture::CaptureTerminal;
+
+    let mut system = System::create(CaptureTerminal::new());
+    // Neither the audio device nor the terminal should influence the measurement
+    system.set_deterministic_audio(true);
+
+    if let Some(path) = args.monitor.as_deref() {
+        let monitor = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        system
+            .set_monitor(&monitor)
+            .map_err(|_| "monitor image is too big".to_owned())?;
+    }
+    system.reset();
+
+    if let Some(path) = args.run.as_deref() {
+        let program = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        system
+            .load_program(0, &program)
+            .map_err(|_| "binary is too big".to_owned())?;
+        system.execute_program();
+    }
+
+    if args.coverage {
+        system.start_coverage();
+    }
+
+    let start_cycles = system.total_cycles();
+    let start = Instant::now();
+    for _ in 0..frames {
+        system.clock_frame();
+    }
+    let elapsed = start.elapsed();
+    let cycles = system.total_cycles() - start_cycles;
+
+    println!(
+        "{frames} frames, {cycles} cycles in {:.3} s ({}, {:.1} frames/s)",
+        elapsed.as_secs_f64(),
+        format_clock_rate((cycles as f64) / elapsed.as_secs_f64()),
+        (frames as f64) / elapsed.as_secs_f64(),
+    );
+    if let Some(coverage) = system.coverage() {
+        println!("{coverage}");
+    }
+    Ok(())
+}
+
+// Accepts decimal numbers as well as hex numbers with a `0x` prefix
+fn parse_size(text: &str) -> Result<usize, String> {
+    let result = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+fn parse_range(text: &str) -> Result<std::ops::Range<u16>, String> {
+    let (start, end) = text
+        .split_once("..")
+        .ok_or_else(|| "expected START..END".to_owned())?;
+    let parse = |text: &str| {
+        let value = parse_size(text)?;
+        u16::try_from(value).map_err(|_| format!("{value} is not a valid address"))
+    };
+
+    let range = parse(start)?..parse(end)?;
+    if range.is_empty() {
+        return Err("the range is empty".to_owned());
+    }
+    Ok(range)
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    let value = parse_size(text)?;
+    u8::try_from(value).map_err(|_| format!("{value} does not fit into a byte"))
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ChecksumArg {
+    /// One byte that makes all bytes add up to zero
+    Sum,
+    /// CRC-16/CCITT-FALSE, low byte first
+    Crc16,
+}
+
+impl From<ChecksumArg> for assembler::ChecksumKind {
+    fn from(arg: ChecksumArg) -> Self {
+        match arg {
+            ChecksumArg::Sum => Self::SumToZero,
+            ChecksumArg::Crc16 => Self::Crc16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OutputFormat {
+    /// Raw bytes, like the ones loaded with `--run`
+    Bin,
+    /// C definitions of the bytes and their base address
+    Carray,
+    /// Rust constants `BASE` and `DATA` for use with `include!`
+    Rust,
+    /// UART input that loads the program through the monitor's `writebin` command
+    Monitor,
+}
+
+fn write_output(mut program: assembler::Program, path: &Path, args: &Args) -> bool {
+    if let Some(size) = args.rom_size {
+        if let Err(len) = program.pad(size, args.rom_fill) {
+            eprintln!(
+                "{}: error: program of {len} bytes does not fit into {size} bytes",
+                path.display()
+            );
+            return false;
+        }
+    }
+
+    if let Some(kind) = args.checksum.map(assembler::ChecksumKind::from) {
+        let len = program.data().len();
+        let at = args
+            .checksum_at
+            .unwrap_or_else(|| len.saturating_sub(kind.size()));
+        if (at + kind.size()) > len {
+            eprintln!(
+                "{}: error: checksum at {at} does not fit into the program of {len} bytes",
+                path.display()
+            );
+            return false;
+        }
+
+        program.apply_checksum(kind, at);
+    }
+
+    let result = match args.format {
+        OutputFormat::Bin => std::fs::write(path, program.data()),
+        OutputFormat::Carray => std::fs::write(
+            path,
+            assembler::to_c_array(&program, &assembler::c_identifier(path)),
+        ),
+        OutputFormat::Rust => std::fs::write(path, assembler::to_rust_module(&program)),
+        OutputFormat::Monitor => std::fs::write(
+            path,
+            assembler::to_monitor_loader_stream(program.base(), program.data()),
+        ),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}: error: {err}", path.display());
+        return false;
+    }
+    true
+}
+
+fn check_file(
+    path: &PathBuf,
+    json: bool,
+    xref: bool,
+    warn_unused: bool,
+) -> Option<assembler::Program> {
+    use langbox::FileServer;
+
+    let mut file_server = FileServer::new();
+    let file = match file_server.register_file(path) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("{}: error: {err}", path.display());
+            return None;
+        }
+    };
+
+    let (program, diagnostics) =
+        match assembler::assemble_with_warnings(&mut file_server, file, true, warn_unused) {
+            Ok((program, warnings)) => (Some(program), warnings),
+            Err(errors) => (None, errors),
+        };
+
+    if json {
+        println!(
+            "{}",
+            assembler::diagnostics_to_json(&diagnostics, &file_server)
+        );
+    } else if io::stderr().is_terminal() {
+        for diagnostic in diagnostics {
+            eprint!(
+                "{}",
+                diagnostic.format_styled(&file_server, assembler::DEFAULT_TAB_WIDTH, true)
+            );
+        }
+    } else {
+        // Redirected output is more likely to be read by tools, which expect one line per diagnostic
+        for diagnostic in diagnostics {
+            eprint!("{}", diagnostic.format_plain(&file_server));
+        }
+    }
+
+    if program.is_some() && xref {
+        if let Ok(xref) = assembler::assemble_xref(&mut file_server, file, true) {
+            print!("{}", assembler::format_xref(&xref, &file_server));
+        }
+    }
+
+    program
+}
+
+fn write_scrollback(scrollback: &str, path: Option<&Path>) -> io::Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, scrollback),
+        None => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(scrollback.as_bytes())?;
+            stdout.flush()
+        }
+    }
 }
 
 struct AppState {
@@ -835,7 +2698,51 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     use winit::event_loop::{ControlFlow, EventLoop};
     use winit::window::WindowBuilder;
 
+    // Timestamped logs on stderr, filtered through `RUST_LOG`
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
     let args = Args::parse();
+    if let Some(path) = &args.check {
+        let success = match check_file(path, args.json, args.xref, args.warn_unused) {
+            Some(program) => {
+                if args.sections {
+                    print!("{}", assembler::format_section_report(program.sections()));
+                }
+
+                match &args.output {
+                    Some(output) => write_output(program, output, &args),
+                    None => true,
+                }
+            }
+            None => false,
+        };
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
+    if let Some(path) = &args.disasm {
+        if let Err(err) = disassemble_file(path, args.base, args.symbols.as_deref()) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(frames) = args.bench {
+        if let Err(err) = bench(&args, frames) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let saved_state = match args.load_state.as_deref().map(std::fs::read).transpose() {
+        Ok(saved_state) => saved_state,
+        Err(err) => {
+            eprintln!("{}: error: {err}", args.load_state.unwrap().display());
+            std::process::exit(1);
+        }
+    };
+
     let event_loop = EventLoop::new()?;
     let mut app_state = None;
 
@@ -880,8 +2787,43 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     ..Default::default()
                 });
 
-                let mut system = System::create(NativeTerminal::new());
-                system.reset();
+                let mut system = System::create(NativeTerminal::new(args.keep_output.is_some()));
+                if let Some(path) = args.monitor.as_deref() {
+                    system
+                        .set_monitor(&std::fs::read(path).unwrap())
+                        .expect("monitor image is too big");
+                }
+
+                match &saved_state {
+                    Some(state) => {
+                        if let Err(err) = system.load_state(state) {
+                            let path = args.load_state.as_deref().unwrap();
+                            eprintln!("{}: error: {err}", path.display());
+                            std::process::exit(1);
+                        }
+                    }
+                    None => system.reset(),
+                }
+
+                if let Some(path) = args.trace.as_deref() {
+                    system
+                        .set_trace_file(path)
+                        .expect("failed to create trace file");
+                }
+
+                if let Some(path) = args.uart_log.as_deref() {
+                    system
+                        .set_uart_log(path)
+                        .expect("failed to create UART log file");
+                }
+
+                if let Some(bounds) = args.stack_bounds.clone() {
+                    system.set_stack_bounds(bounds);
+                }
+
+                if args.coverage {
+                    system.start_coverage();
+                }
 
                 if let Some(program) = args.run.as_deref() {
                     system
@@ -895,7 +2837,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 )
                 .unwrap();
 
-                let emu_state = EmuState::create(&ui_context);
+                let emu_state = EmuState::create(&ui_context, &mut system);
 
                 app_state = Some(AppState {
                     window,
@@ -918,6 +2860,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 WindowEvent::CloseRequested => {
                                     window_target.exit();
                                     app_state.emu_state.quit(&mut app_state.system);
+
+                                    if let Some(path) = &args.keep_output {
+                                        let terminal = app_state.system.terminal();
+                                        let scrollback = terminal.scrollback().unwrap_or_default();
+                                        if let Err(err) =
+                                            write_scrollback(scrollback, path.as_deref())
+                                        {
+                                            eprintln!("error: failed to keep the output: {err}");
+                                        }
+                                    }
+
+                                    if let Some(coverage) = app_state.system.coverage() {
+                                        println!("{coverage}");
+                                    }
+                                }
+                                WindowEvent::Focused(focused) => {
+                                    app_state.emu_state.set_focused(focused);
                                 }
                                 WindowEvent::Resized(size) => {
                                     let width =
@@ -0,0 +1,279 @@
+use std::fmt::Display;
+
+const MAGIC: &[u8; 4] = b"JAM1";
+
+/// Version of the save state format, bumped whenever the layout changes
+pub const STATE_VERSION: u16 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with a save state header
+    InvalidHeader,
+    /// The state was written by an incompatible version of the emulator
+    UnsupportedVersion(u16),
+    /// The data ended before the state was complete
+    Truncated,
+    /// The data contains a value the emulator can't be in
+    InvalidData,
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "not a save state"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {version} is not supported (expected {STATE_VERSION})"
+            ),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::InvalidData => write!(f, "save state is corrupted"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+// Runs of at least this many equal bytes are encoded as a repeat
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 130;
+const MAX_LITERAL: usize = 128;
+
+// PackBits style encoding: a control byte below 0x80 is followed by that many plus one literal
+// bytes, a control byte of 0x80 or above repeats the next byte `control - 0x80 + MIN_RUN` times.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    let flush_literals = |output: &mut Vec<u8>, literals: &[u8]| {
+        for chunk in literals.chunks(MAX_LITERAL) {
+            output.push((chunk.len() - 1) as u8);
+            output.extend_from_slice(chunk);
+        }
+    };
+
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&b| b == byte)
+            .count();
+
+        if run >= MIN_RUN {
+            flush_literals(&mut output, &data[literal_start..i]);
+            output.push((0x80 + run - MIN_RUN) as u8);
+            output.push(byte);
+
+            i += run;
+            literal_start = i;
+        } else {
+            i += run;
+        }
+    }
+
+    flush_literals(&mut output, &data[literal_start..]);
+    output
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, StateError> {
+    let mut output = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+
+        if control < 0x80 {
+            let len = control + 1;
+            let literals = data.get(i..(i + len)).ok_or(StateError::Truncated)?;
+            output.extend_from_slice(literals);
+            i += len;
+        } else {
+            let byte = *data.get(i).ok_or(StateError::Truncated)?;
+            output.resize(output.len() + control - 0x80 + MIN_RUN, byte);
+            i += 1;
+        }
+    }
+
+    Ok(output)
+}
+
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    #[inline]
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    #[inline]
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    #[inline]
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    #[inline]
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    #[inline]
+    pub fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+
+    #[inline]
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Prepends the header and compresses the payload
+    pub fn finish(self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(MAGIC.len() + 2);
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        output.extend_from_slice(&compress(&self.data));
+        output
+    }
+}
+
+pub(crate) struct StateReader {
+    data: Vec<u8>,
+    offset: usize,
+}
+
+impl StateReader {
+    /// Validates the header and decompresses the payload
+    pub fn new(data: &[u8]) -> Result<Self, StateError> {
+        let payload = data.strip_prefix(MAGIC).ok_or(StateError::InvalidHeader)?;
+        let version = payload.get(..2).ok_or(StateError::InvalidHeader)?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            data: decompress(&payload[2..])?,
+            offset: 0,
+        })
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    #[inline]
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    #[inline]
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidData),
+        }
+    }
+
+    #[inline]
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    #[inline]
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    #[inline]
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    #[inline]
+    pub fn f32(&mut self) -> Result<f32, StateError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    #[inline]
+    pub fn f64(&mut self) -> Result<f64, StateError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&[u8], StateError> {
+        let bytes = self
+            .data
+            .get(self.offset..(self.offset + len))
+            .ok_or(StateError::Truncated)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        compressed
+    }
+
+    #[test]
+    fn short_runs_stay_literal() {
+        assert!(round_trip(&[]).is_empty());
+        assert_eq!(round_trip(&[1, 2, 2, 3]), [3, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn runs_are_repeated() {
+        assert_eq!(round_trip(&[7; 3]), [0x80, 7]);
+        assert_eq!(round_trip(&[1, 7, 7, 7, 7, 2]), [0, 1, 0x81, 7, 0, 2]);
+
+        // Longer runs are split into several repeats
+        assert_eq!(
+            round_trip(&[0; 0x10000]).len(),
+            0x10000usize.div_ceil(MAX_RUN) * 2
+        );
+    }
+
+    #[test]
+    fn long_literals_are_split() {
+        let data: Vec<u8> = (0..=255).collect();
+        let compressed = round_trip(&data);
+        assert_eq!(compressed.len(), data.len() + 2);
+        assert_eq!(compressed[0] as usize, MAX_LITERAL - 1);
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        assert_eq!(decompress(&[3, 1, 2]), Err(StateError::Truncated));
+        assert_eq!(decompress(&[0x80]), Err(StateError::Truncated));
+    }
+}
@@ -1,4 +1,8 @@
 use egui::text::LayoutJob;
+use jam1emu_lib::assembler;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, Hash, PartialEq)]
@@ -39,7 +43,7 @@ contexts:
   main:
     - match: \.[a-zA-Z_][a-zA-Z0-9_]*
       scope: keyword.directive.jam1asm
-    - match: (?i)\b((nop)|(mov)|(inc)|(incc)|(dec)|(in)|(out)|(break)|(lodsb)|(stosb)|(call)|(ret)|(callbd)|(retbd)|(jmp)|(jo)|(jno)|(js)|(jns)|(jz)|(jnz)|(je)|(jne)|(jc)|(jnc)|(jnae)|(jb)|(jae)|(jnb)|(jbe)|(jna)|(ja)|(jnbe)|(jl)|(jnge)|(jge)|(jnl)|(jle)|(jng)|(jg)|(jnle)|(jlc)|(jnlc)|(push)|(pop)|(clc)|(shl)|(shr)|(add)|(addc)|(addac)|(sub)|(subb)|(subae)|(and)|(or)|(xor)|(not)|(cmp)|(test))\b
+    - match: (?i)\b((nop)|(mov)|(inc)|(incc)|(dec)|(in)|(out)|(break)|(lodsb)|(stosb)|(call)|(ret)|(reti)|(callbd)|(retbd)|(jmp)|(jo)|(jno)|(js)|(jns)|(jz)|(jnz)|(je)|(jne)|(jc)|(jnc)|(jnae)|(jb)|(jae)|(jnb)|(jbe)|(jna)|(ja)|(jnbe)|(jl)|(jnge)|(jge)|(jnl)|(jle)|(jng)|(jg)|(jnle)|(jlc)|(jnlc)|(push)|(pop)|(clc)|(shl)|(shr)|(add)|(addc)|(addac)|(sub)|(subb)|(subae)|(and)|(or)|(xor)|(not)|(cmp)|(test))\b
       scope: keyword.instruction.jam1asm
     - match: \b([0-9][a-zA-Z0-9_]*)\b
       scope: constant.numeric.jam1asm
@@ -68,6 +72,8 @@ contexts:
 pub struct Highlighter {
     ps: syntect::parsing::SyntaxSet,
     ts: syntect::highlighting::ThemeSet,
+    // Parsing results per line of code, so only edited lines have to be parsed again
+    invalid_operands: RefCell<HashMap<String, Option<Range<usize>>>>,
 }
 
 impl Default for Highlighter {
@@ -78,13 +84,15 @@ impl Default for Highlighter {
         Self {
             ps: builder.build(),
             ts: syntect::highlighting::ThemeSet::load_defaults(),
+            invalid_operands: RefCell::default(),
         }
     }
 }
 
 impl Highlighter {
-    pub fn highlight(&self, code: &str) -> LayoutJob {
-        self.highlight_impl(code).unwrap_or_else(|| {
+    /// Highlights the code, additionally marking the given byte ranges with a background color
+    pub fn highlight(&self, code: &str, marked: &[Range<usize>]) -> LayoutJob {
+        self.highlight_impl(code, marked).unwrap_or_else(|| {
             // Fallback:
             LayoutJob::simple(
                 code.into(),
@@ -95,7 +103,7 @@ impl Highlighter {
         })
     }
 
-    fn highlight_impl(&self, text: &str) -> Option<LayoutJob> {
+    fn highlight_impl(&self, text: &str, marked: &[Range<usize>]) -> Option<LayoutJob> {
         use syntect::easy::HighlightLines;
         use syntect::highlighting::FontStyle;
         use syntect::util::LinesWithEndings;
@@ -112,7 +120,25 @@ impl Highlighter {
             ..Default::default()
         };
 
+        let mut invalid_operands = self.invalid_operands.borrow_mut();
+        let mut new_invalid_operands = HashMap::with_capacity(invalid_operands.len());
+        let mut error_ranges = Vec::new();
+
         for line in LinesWithEndings::from(text) {
+            let line_code = line.trim_end_matches(['\r', '\n']);
+            let line_error = match new_invalid_operands.get(line_code) {
+                Some(line_error) => Option::clone(line_error),
+                None => invalid_operands
+                    .remove(line_code)
+                    .unwrap_or_else(|| assembler::find_invalid_operands(line_code)),
+            };
+
+            if let Some(range) = &line_error {
+                let line_start = as_byte_range(text, line).start;
+                error_ranges.push((line_start + range.start)..(line_start + range.end));
+            }
+            new_invalid_operands.insert(line_code.to_owned(), line_error);
+
             for (style, range) in h.highlight_line(line, &self.ps).ok()? {
                 let fg = style.foreground;
                 let text_color = egui::Color32::from_rgb(fg.r, fg.g, fg.b);
@@ -137,10 +163,64 @@ impl Highlighter {
             }
         }
 
+        *invalid_operands = new_invalid_operands;
+
+        for range in error_ranges {
+            modify_range(&mut job, range, |format| {
+                format.underline = egui::Stroke::new(1.0, egui::Color32::RED);
+            });
+        }
+
+        for range in marked {
+            modify_range(&mut job, range.clone(), |format| {
+                format.background = egui::Color32::from_gray(80);
+            });
+        }
+
         Some(job)
     }
 }
 
+// Changes the format of a byte range, splitting sections where necessary
+fn modify_range(
+    job: &mut LayoutJob,
+    range: Range<usize>,
+    modify: impl Fn(&mut egui::text::TextFormat),
+) {
+    use egui::text::LayoutSection;
+
+    let sections = std::mem::take(&mut job.sections);
+    for section in sections {
+        let byte_range = section.byte_range.clone();
+
+        if (range.start < byte_range.end) && (range.end > byte_range.start) {
+            let start = range.start.max(byte_range.start);
+            let end = range.end.min(byte_range.end);
+
+            let mut modified_format = section.format.clone();
+            modify(&mut modified_format);
+
+            let parts = [
+                (byte_range.start..start, section.format.clone()),
+                (start..end, modified_format),
+                (end..byte_range.end, section.format),
+            ];
+
+            for (byte_range, format) in parts {
+                if !byte_range.is_empty() {
+                    job.sections.push(LayoutSection {
+                        leading_space: 0.0,
+                        byte_range,
+                        format,
+                    });
+                }
+            }
+        } else {
+            job.sections.push(section);
+        }
+    }
+}
+
 fn as_byte_range(whole: &str, range: &str) -> std::ops::Range<usize> {
     let whole_start = whole.as_ptr() as usize;
     let range_start = range.as_ptr() as usize;
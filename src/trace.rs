@@ -0,0 +1,323 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The CPU registers at the time an instruction was fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterState {
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    tx: u16,
+    si: u16,
+    di: u16,
+    sp: u16,
+    ra: u16,
+    flags: u8,
+}
+
+impl RegisterState {
+    pub(crate) fn capture(cpu: &crate::cpu::Cpu) -> Self {
+        Self {
+            a: cpu.a(),
+            b: cpu.b(),
+            c: cpu.c(),
+            d: cpu.d(),
+            tx: cpu.tx(),
+            si: cpu.si(),
+            di: cpu.di(),
+            sp: cpu.sp(),
+            ra: cpu.ra(),
+            flags: cpu.flags().bits(),
+        }
+    }
+
+    #[inline]
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    #[inline]
+    pub fn b(&self) -> u8 {
+        self.b
+    }
+
+    #[inline]
+    pub fn c(&self) -> u8 {
+        self.c
+    }
+
+    #[inline]
+    pub fn d(&self) -> u8 {
+        self.d
+    }
+
+    #[inline]
+    pub fn tx(&self) -> u16 {
+        self.tx
+    }
+
+    #[inline]
+    pub fn si(&self) -> u16 {
+        self.si
+    }
+
+    #[inline]
+    pub fn di(&self) -> u16 {
+        self.di
+    }
+
+    #[inline]
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    #[inline]
+    pub fn ra(&self) -> u16 {
+        self.ra
+    }
+
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+}
+
+impl Display for RegisterState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A={:0>2X} B={:0>2X} C={:0>2X} D={:0>2X} TX={:0>4X} SI={:0>4X} DI={:0>4X} SP={:0>4X} RA={:0>4X} F={:0>2X}",
+            self.a,
+            self.b,
+            self.c,
+            self.d,
+            self.tx,
+            self.si,
+            self.di,
+            self.sp,
+            self.ra,
+            self.flags,
+        )
+    }
+}
+
+/// One line of an instruction trace, in the format
+///
+/// `CYCLE ADDR: BYTES INSTRUCTION A=.. B=.. C=.. D=.. TX=.... SI=.... DI=.... SP=.... RA=.... F=..`
+///
+/// where the cycle is decimal and all other values are hexadecimal. The instruction bytes are
+/// padded to 5 characters and the instruction to 20, registers show the state at the time
+/// of the fetch and `F` holds the raw flag bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    cycle: u64,
+    address: u16,
+    bytes: Vec<u8>,
+    instruction: String,
+    registers: RegisterState,
+}
+
+impl TraceEntry {
+    pub(crate) fn new(
+        cycle: u64,
+        address: u16,
+        bytes: Vec<u8>,
+        instruction: String,
+        registers: RegisterState,
+    ) -> Self {
+        Self {
+            cycle,
+            address,
+            bytes,
+            instruction,
+            registers,
+        }
+    }
+
+    #[inline]
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    #[inline]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    #[inline]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    #[inline]
+    pub fn instruction(&self) -> &str {
+        &self.instruction
+    }
+
+    #[inline]
+    pub fn registers(&self) -> &RegisterState {
+        &self.registers
+    }
+
+    /// Whether two entries describe the same machine state, ignoring the cycle count
+    pub fn same_state(&self, other: &Self) -> bool {
+        (self.address == other.address) && (self.registers == other.registers)
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<_> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:0>2X}"))
+            .collect();
+
+        write!(
+            f,
+            "{} {:0>4X}: {:<5} {:<20} {}",
+            self.cycle,
+            self.address,
+            bytes.join(" "),
+            self.instruction,
+            self.registers,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTraceError(String);
+
+impl Display for ParseTraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid trace line: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseTraceError {}
+
+fn parse_hex<T: TryFrom<u32>>(text: &str, what: &str) -> Result<T, ParseTraceError> {
+    u32::from_str_radix(text, 16)
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| ParseTraceError(format!("`{text}` is not a valid {what}")))
+}
+
+impl FromStr for TraceEntry {
+    type Err = ParseTraceError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace().peekable();
+
+        let cycle = tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| ParseTraceError("missing cycle count".to_owned()))?;
+        let address = tokens
+            .next()
+            .and_then(|token| token.strip_suffix(':'))
+            .ok_or_else(|| ParseTraceError("missing address".to_owned()))?;
+        let address = parse_hex(address, "address")?;
+
+        // Bytes are always two uppercase hex digits, mnemonics are lowercase
+        let mut bytes = Vec::new();
+        while let Some(token) = tokens.next_if(|token| {
+            (token.len() == 2) && token.chars().all(|c| matches!(c, '0'..='9' | 'A'..='F'))
+        }) {
+            bytes.push(parse_hex(token, "byte")?);
+        }
+
+        let mut instruction = Vec::new();
+        while let Some(token) = tokens.next_if(|token| !token.starts_with("A=")) {
+            instruction.push(token);
+        }
+
+        let mut registers = [None; 10];
+        const NAMES: [&str; 10] = ["A", "B", "C", "D", "TX", "SI", "DI", "SP", "RA", "F"];
+        for token in tokens {
+            let (name, value) = token
+                .split_once('=')
+                .ok_or_else(|| ParseTraceError(format!("unexpected `{token}`")))?;
+            let index = NAMES
+                .iter()
+                .position(|&n| n == name)
+                .ok_or_else(|| ParseTraceError(format!("unknown register `{name}`")))?;
+            registers[index] = Some(parse_hex::<u16>(value, "register value")?);
+        }
+
+        let [a, b, c, d, tx, si, di, sp, ra, flags] = registers;
+        let register = |value: Option<u16>, name: &str| {
+            value.ok_or_else(|| ParseTraceError(format!("missing register `{name}`")))
+        };
+        let byte_register = |value: Option<u16>, name: &str| {
+            u8::try_from(register(value, name)?)
+                .map_err(|_| ParseTraceError(format!("register `{name}` is out of range")))
+        };
+
+        Ok(Self {
+            cycle,
+            address,
+            bytes,
+            instruction: instruction.join(" "),
+            registers: RegisterState {
+                a: byte_register(a, "A")?,
+                b: byte_register(b, "B")?,
+                c: byte_register(c, "C")?,
+                d: byte_register(d, "D")?,
+                tx: register(tx, "TX")?,
+                si: register(si, "SI")?,
+                di: register(di, "DI")?,
+                sp: register(sp, "SP")?,
+                ra: register(ra, "RA")?,
+                flags: byte_register(flags, "F")?,
+            },
+        })
+    }
+}
+
+/// The first point at which a run differs from a reference trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    line: usize,
+    expected: TraceEntry,
+    actual: TraceEntry,
+}
+
+impl Divergence {
+    pub(crate) fn new(line: usize, expected: TraceEntry, actual: TraceEntry) -> Self {
+        Self {
+            line,
+            expected,
+            actual,
+        }
+    }
+
+    /// The one-based line in the reference trace
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[inline]
+    pub fn expected(&self) -> &TraceEntry {
+        &self.expected
+    }
+
+    #[inline]
+    pub fn actual(&self) -> &TraceEntry {
+        &self.actual
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "divergence at cycle {} (reference line {})",
+            self.actual.cycle, self.line
+        )?;
+        writeln!(f, "expected: {}", self.expected)?;
+        write!(f, "actual:   {}", self.actual)
+    }
+}
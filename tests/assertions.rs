@@ -0,0 +1,80 @@
+use jam1emu_lib::assembler::{self, MemoryFileProvider};
+use langbox::FileServer;
+
+fn assemble(code: &str) -> assembler::Program {
+    assembler::assemble_code(code, false).unwrap_or_else(|output| panic!("{output}"))
+}
+
+fn error_output(code: &str) -> String {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("main.asm", code.to_owned())
+        .unwrap();
+    let Err(errors) =
+        assembler::assemble_with_provider(&mut file_server, file, &MemoryFileProvider::new())
+    else {
+        panic!("`{code}` was accepted");
+    };
+
+    errors
+        .iter()
+        .map(|error| error.format_styled(&file_server, assembler::DEFAULT_TAB_WIDTH, false))
+        .collect()
+}
+
+#[test]
+fn passing_assert_emits_nothing() {
+    let program = assemble(".section \"code\" 0\n    nop\n    .assert 1 + 1 == 2\n    nop\n");
+    assert_eq!(program.data(), &[0x00, 0x00]);
+}
+
+#[test]
+fn failing_assert_is_an_error() {
+    let output = error_output(".section \"code\" 0\n    nop\n    .assert 1 + 1 == 3\n");
+    assert!(output.contains("assertion failed"), "{output}");
+
+    let output = error_output(".section \"code\" 0\n    nop\n    .assert $ < 1, \"too large\"\n");
+    assert!(output.contains("assertion failed: too large"), "{output}");
+}
+
+#[test]
+fn current_address_in_the_middle_of_a_section() {
+    let code = ".section \"code\" 0x100
+    mov a, 0x2A
+    .assert $ == 0x102
+    mov b, ($ - 0x100) & 0xFF
+    nop
+    mov c, $ >> 8
+END:
+    .assert $ == END
+";
+    let program = assemble(code);
+    assert_eq!(program.data(), &[0x01, 0x2A, 0x02, 0x02, 0x00, 0x03, 0x01]);
+}
+
+#[test]
+fn comparison_operators() {
+    let cases = [
+        ("1 < 2", true),
+        ("2 < 2", false),
+        ("2 <= 2", true),
+        ("3 <= 2", false),
+        ("3 > 2", true),
+        ("2 > 2", false),
+        ("2 >= 2", true),
+        ("1 >= 2", false),
+        ("2 == 2", true),
+        ("1 == 2", false),
+        ("1 != 2", true),
+        ("2 != 2", false),
+    ];
+
+    for (condition, holds) in cases {
+        let code = format!(".section \"code\" 0\n    nop\n    .assert {condition}\n");
+        assert_eq!(
+            assembler::assemble_code(&code, false).is_ok(),
+            holds,
+            "`.assert {condition}`"
+        );
+    }
+}
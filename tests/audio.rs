@@ -0,0 +1,77 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+// Sets channel 0 to full volume with a period of 0x10 audio cycles and spins forever
+const TONE_PROGRAM: &str = ".section \"code\" 0
+    mov a, 0
+    out audio_data, a
+    mov a, 0x10
+    out audio_data, a
+    mov a, 0x00
+    out audio_data, a
+spin:
+    jmp spin
+";
+
+fn tone_program() -> assembler::Program {
+    assembler::assemble_code(TONE_PROGRAM, false).unwrap_or_else(|output| panic!("{output}"))
+}
+
+// Samples at 44.1 kHz of a square wave toggling every 16 cycles of the 230.4 kHz audio clock
+const REFERENCE: &[f32] = &[
+    -0.380797, -0.380797, -0.380797, 0.380797, 0.380797, 0.380797, -0.380797, -0.380797, -0.380797,
+    0.380797, 0.380797, 0.380797, -0.380797, -0.380797, -0.380797, 0.380797,
+];
+
+#[test]
+fn tone_samples_match_reference() {
+    let mut system = System::create(CaptureTerminal::new());
+    system.reset();
+    system.set_deterministic_audio(true);
+    system.load_program(0, tone_program().data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+
+    // The channel is only configured once the program has run, so skip the samples before that
+    system.clock(100);
+    let skip = system.audio_samples().len();
+
+    while system.audio_samples().len() < (skip + REFERENCE.len()) {
+        system.clock(100);
+    }
+
+    let samples = &system.audio_samples()[skip..(skip + REFERENCE.len())];
+    for (i, (&actual, &expected)) in samples.iter().zip(REFERENCE).enumerate() {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "sample {i} is {actual}, expected {expected}"
+        );
+    }
+}
+
+#[test]
+fn take_audio_samples_clears_recorded_samples() {
+    let mut system = System::create(CaptureTerminal::new());
+    system.reset();
+    system.set_deterministic_audio(true);
+    system.load_program(0, tone_program().data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+
+    system.clock(100);
+    system.take_audio_samples();
+
+    let mut samples = Vec::new();
+    while samples.len() < REFERENCE.len() {
+        system.clock(100);
+        samples.extend(system.take_audio_samples());
+        assert!(system.audio_samples().is_empty());
+    }
+
+    for (i, (&actual, &expected)) in samples.iter().zip(REFERENCE).enumerate() {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "sample {i} is {actual}, expected {expected}"
+        );
+    }
+}
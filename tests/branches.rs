@@ -0,0 +1,91 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+// Operand pairs covering equal, unsigned below/above, sign differences and signed overflow
+const OPERANDS: &[(u8, u8)] = &[
+    (5, 5),
+    (3, 7),
+    (7, 3),
+    (0x10, 0xF0),
+    (0xF0, 0x10),
+    (0x80, 0x01),
+    (0x7F, 0xFF),
+    (0x00, 0x00),
+];
+
+// Runs `cmp a, b` followed by the branch and returns whether it was taken
+fn branch_taken(mnemonic: &str, a: u8, b: u8) -> bool {
+    let code = format!(
+        ".section \"code\" 0
+    mov a, {a}
+    mov b, {b}
+    mov c, 0
+    cmp a, b
+    {mnemonic} taken
+    break
+taken:
+    mov c, 1
+    break
+"
+    );
+    let program =
+        assembler::assemble_code(&code, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+
+    assert!(system.clock(1000), "`{mnemonic}` never reached a break");
+    system.cpu().c() == 1
+}
+
+fn check(mnemonics: &[&str], condition: impl Fn(u8, u8) -> bool) {
+    for &(a, b) in OPERANDS {
+        for mnemonic in mnemonics {
+            assert_eq!(
+                branch_taken(mnemonic, a, b),
+                condition(a, b),
+                "`{mnemonic}` after `cmp 0x{a:0>2X}, 0x{b:0>2X}`"
+            );
+        }
+    }
+}
+
+fn signed(value: u8) -> i8 {
+    value as i8
+}
+
+#[test]
+fn equality() {
+    check(&["je", "jz"], |a, b| a == b);
+    check(&["jne", "jnz"], |a, b| a != b);
+}
+
+#[test]
+fn unsigned() {
+    // `cmp` subtracts by adding the complement, so carry is set if nothing was borrowed
+    check(&["jae", "jnb", "jc"], |a, b| a >= b);
+    check(&["jb", "jnae", "jnc"], |a, b| a < b);
+    check(&["jbe", "jna"], |a, b| a <= b);
+    check(&["ja", "jnbe"], |a, b| a > b);
+}
+
+#[test]
+fn signed_comparisons() {
+    check(&["jl", "jnge"], |a, b| signed(a) < signed(b));
+    check(&["jge", "jnl"], |a, b| signed(a) >= signed(b));
+    check(&["jle", "jng"], |a, b| signed(a) <= signed(b));
+    check(&["jg", "jnle"], |a, b| signed(a) > signed(b));
+}
+
+#[test]
+fn sign_and_overflow() {
+    check(&["js"], |a, b| signed(a.wrapping_sub(b)) < 0);
+    check(&["jns"], |a, b| signed(a.wrapping_sub(b)) >= 0);
+    check(&["jo"], |a, b| signed(a).checked_sub(signed(b)).is_none());
+    check(&["jno"], |a, b| signed(a).checked_sub(signed(b)).is_some());
+}
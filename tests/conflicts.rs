@@ -0,0 +1,61 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+// Keeps writing to `address` for as long as it runs
+fn write_loop(address: u16) -> System<CaptureTerminal> {
+    let code = format!(
+        ".section \"code\" 0
+    mov di, {address}
+    mov tx, loop
+loop:
+    mov [di], a
+    inc a
+    jmp tx
+"
+    );
+    let program =
+        assembler::assemble_code(&code, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+    system
+}
+
+// Runs for two frames, so one frame has been completed entirely inside the loop
+fn run_frames(system: &mut System<CaptureTerminal>) {
+    let cycles = (system.cycles_per_frame() * 2.0).ceil() as u64;
+    assert!(!system.clock(cycles));
+}
+
+#[test]
+fn framebuffer_writes_during_display_are_counted() {
+    let mut system = write_loop(0xC000);
+    assert_eq!(system.conflict_stats().total(), 0);
+
+    run_frames(&mut system);
+    let stats = system.conflict_stats();
+    assert!(stats.framebuffer() > 0);
+    assert_eq!(stats.palette(), 0);
+    assert_eq!(stats.tile_data(), 0);
+    assert_eq!(stats.total(), stats.framebuffer());
+}
+
+#[test]
+fn writes_outside_vram_are_not_counted() {
+    let mut system = write_loop(0x4000);
+    run_frames(&mut system);
+    assert_eq!(system.conflict_stats().total(), 0);
+}
+
+#[test]
+fn nothing_is_counted_without_conflict_emulation() {
+    let mut system = write_loop(0xC000);
+    system.set_bus_conflict_emulation(false);
+    run_frames(&mut system);
+    assert_eq!(system.conflict_stats().total(), 0);
+}
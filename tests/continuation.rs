@@ -0,0 +1,41 @@
+use jam1emu_lib::assembler::{self, MemoryFileProvider};
+use langbox::FileServer;
+
+#[test]
+fn statement_continues_over_three_lines() {
+    let program = assembler::assemble_code(
+        ".section \"code\" 0\n    mov a, 1 + \\\n        2 + \\\n        3\n    mov b, 4\n",
+        false,
+    )
+    .unwrap_or_else(|output| panic!("{output}"));
+
+    assert_eq!(program.data(), &[0x01, 0x06, 0x02, 0x04]);
+}
+
+#[test]
+fn errors_point_at_physical_lines() {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory(
+            "main.asm",
+            ".section \"code\" 0\n    mov a, 1 + \\\n        2 + \\\n        first\n    mov b, second\n",
+        )
+        .unwrap();
+    let Err(errors) =
+        assembler::assemble_with_provider(&mut file_server, file, &MemoryFileProvider::new())
+    else {
+        panic!("undefined symbols were accepted");
+    };
+
+    let mut lines: Vec<_> = errors
+        .iter()
+        .map(|error| {
+            let diagnostic = error.to_diagnostic(&file_server);
+            let location = diagnostic.location().expect("error without location");
+            (location.start().line(), location.start().column())
+        })
+        .collect();
+    lines.sort();
+    // Lines and columns start at 0, `first` is on the last line of the continued statement
+    assert_eq!(lines, [(3, 8), (4, 11)]);
+}
@@ -0,0 +1,41 @@
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::{assembler, System};
+
+#[test]
+fn records_executed_opcodes() {
+    let code = ".section \"code\" 0
+    mov a, 1
+    mov b, a
+    break
+";
+    let program = assembler::assemble_code(code, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+
+    system.start_coverage();
+    assert!(system.clock(1000));
+    let coverage = system.stop_coverage().unwrap();
+
+    assert!(coverage.executed(program.data()[0]));
+    assert!(coverage.executed(program.data()[2]));
+    assert!(!coverage.executed(0x02), "`mov b, imm8` was never executed");
+    assert!(coverage.missing().contains(&0x02));
+    assert_eq!(
+        coverage.executed_count() + coverage.missing().len(),
+        coverage.defined_count()
+    );
+
+    let report = coverage.to_string();
+    assert!(report.starts_with(&format!(
+        "executed {} of {} defined opcodes",
+        coverage.executed_count(),
+        coverage.defined_count()
+    )));
+    assert!(report.contains("    02  mov b, imm8\n"));
+    assert!(system.coverage().is_none());
+}
@@ -0,0 +1,51 @@
+use jam1emu_lib::disassembler;
+use std::collections::HashMap;
+
+#[test]
+fn listing_with_symbols() {
+    let bytes = [0x01, 0x2A, 0x00, 0x01];
+    let lines = disassembler::disassemble_program(&bytes, 0x100);
+    let symbols = HashMap::from([(0x102, "loop".to_owned())]);
+
+    assert_eq!(
+        disassembler::format_listing(&lines, &symbols),
+        "    0100  01 2A  mov a, 0x2A\n\
+         loop:\n    \
+             0102  00     nop\n    \
+             0103  01     (invalid 0x01)\n"
+    );
+}
+
+#[test]
+fn every_byte_pair_advances() {
+    for opcode in 0..=0xFF {
+        for operand in 0..=0xFF {
+            let instruction = disassembler::disassemble(&[opcode, operand]).unwrap();
+            assert!((1..=2).contains(&instruction.length()));
+        }
+
+        // A truncated stream must not be decoded past its end
+        let instruction = disassembler::disassemble(&[opcode]).unwrap();
+        assert_eq!(instruction.length(), 1);
+    }
+}
+
+#[test]
+fn around_starts_on_an_instruction_boundary() {
+    // `mov a, imm8` everywhere, so only every other address starts an instruction
+    let read = |address: u16| if (address & 1) == 0 { 0x01 } else { 0x2A };
+
+    let lines = disassembler::disassemble_around(read, 0x100, 6);
+    let addresses: Vec<_> = lines.iter().map(|line| line.address()).collect();
+    assert_eq!(addresses, [0xFA, 0xFC, 0xFE, 0x100, 0x102, 0x104]);
+}
+
+#[test]
+fn around_is_limited() {
+    let lines = disassembler::disassemble_around(|_| 0x00, 0x8000, u32::MAX);
+    assert_eq!(lines.len(), disassembler::MAX_AROUND_LINES as usize);
+    assert_eq!(
+        lines[(disassembler::MAX_AROUND_LINES / 2) as usize].address(),
+        0x8000
+    );
+}
@@ -0,0 +1,69 @@
+use jam1emu_lib::assembler;
+use std::path::Path;
+
+#[test]
+fn c_identifier_from_file_name() {
+    assert_eq!(assembler::c_identifier(Path::new("out/prog.h")), "prog");
+    assert_eq!(
+        assembler::c_identifier(Path::new("my-prog.v2.c")),
+        "my_prog_v2"
+    );
+    assert_eq!(assembler::c_identifier(Path::new("1st.h")), "_1st");
+}
+
+fn program() -> assembler::Program {
+    assembler::assemble_code(".section \"code\" 0x100\n    mov a, 0x2A\n", false)
+        .unwrap_or_else(|output| panic!("{output}"))
+}
+
+#[test]
+fn c_array() {
+    let program = program();
+
+    assert_eq!(
+        assembler::to_c_array(&program, "prog"),
+        "#include <stdint.h>\n\
+         \n\
+         const uint16_t prog_base = 0x0100;\n\
+         const uint8_t prog[] = {\n    0x01, 0x2A,\n};\n"
+    );
+}
+
+#[test]
+fn rust_module() {
+    assert_eq!(
+        assembler::to_rust_module(&program()),
+        "pub const BASE: u16 = 0x0100;\n\
+         pub const DATA: &[u8] = &[\n    0x01, 0x2A,\n];\n"
+    );
+}
+
+#[test]
+fn sum_to_zero_checksum() {
+    let mut data = vec![0x12, 0x34, 0xFF, 0x00];
+    assembler::apply_checksum(&mut data, assembler::ChecksumKind::SumToZero, 3);
+
+    assert_eq!(data[3], 0xBB);
+    assert_eq!(
+        data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)),
+        0
+    );
+}
+
+#[test]
+fn crc16_checksum() {
+    // The checksum bytes are left out, so the result is the standard check value
+    let mut data = b"1234\x00\x0056789".to_vec();
+    assembler::apply_checksum(&mut data, assembler::ChecksumKind::Crc16, 4);
+
+    assert_eq!(&data[4..6], &0x29B1u16.to_le_bytes());
+}
+
+#[test]
+fn monitor_loader_stream() {
+    assert_eq!(
+        assembler::to_monitor_loader_stream(0x100, &[0x01, 0x2A]),
+        b"writebin 100 2\rBAKC"
+    );
+    assert!(assembler::to_monitor_loader_stream(0x100, &[]).is_empty());
+}
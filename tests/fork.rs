@@ -0,0 +1,41 @@
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+fn booted() -> System<CaptureTerminal> {
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    for _ in 0..10 {
+        system.clock_frame();
+    }
+    system
+}
+
+#[test]
+fn fork_runs_like_the_original() {
+    let mut system = booted();
+    let mut fork = system.fork(CaptureTerminal::new());
+    assert_eq!(fork.save_state(), system.save_state());
+
+    for _ in 0..10 {
+        system.clock_frame();
+        fork.clock_frame();
+    }
+
+    assert_eq!(fork.total_cycles(), system.total_cycles());
+    assert_eq!(fork.save_state(), system.save_state());
+    assert_eq!(fork.framebuffer(), system.framebuffer());
+}
+
+#[test]
+fn fork_shares_no_state() {
+    let system = booted();
+    let pc = system.get_reg(Register::PC);
+
+    let mut fork = system.fork(CaptureTerminal::new());
+    fork.set_reg(Register::PC, pc.wrapping_add(1)).unwrap();
+    fork.clock(100);
+
+    assert_eq!(system.get_reg(Register::PC), pc);
+}
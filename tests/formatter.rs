@@ -0,0 +1,50 @@
+use jam1emu_lib::assembler;
+
+fn format(code: &str) -> String {
+    assembler::format_source(code).unwrap_or_else(|output| panic!("{output}"))
+}
+
+#[test]
+fn spacing_and_case_are_normalized() {
+    assert_eq!(
+        format(".section \"code\" 0\n  MOV A,0x2A\n    mov b,[si]\n"),
+        ".section \"code\" 0\n    mov     a, 0x2A\n    mov     b, [si]\n"
+    );
+}
+
+#[test]
+fn tabs_between_tokens_are_replaced() {
+    assert_eq!(
+        format(".section \"code\" 0\n\tmov\ta,\t0x2A\n"),
+        ".section \"code\" 0\n    mov     a, 0x2A\n"
+    );
+}
+
+#[test]
+fn tabs_in_literals_and_comments_are_kept() {
+    assert_eq!(
+        format(".section \"co\tde\" 0\n\tnop\t; a\tcomment\n"),
+        ".section \"co\tde\" 0\n    nop ; a\tcomment\n"
+    );
+}
+
+#[test]
+fn comments_and_blank_lines_are_kept() {
+    assert_eq!(
+        format("\t; indented\n; first column\n.section \"code\" 0\nloop:\n  JMP loop   ; spin\n\n"),
+        "    ; indented\n; first column\n.section \"code\" 0\nloop:\n    jmp     loop ; spin\n\n"
+    );
+}
+
+#[test]
+fn continued_lines_are_merged() {
+    assert_eq!(
+        format(".section \"code\" 0\n    mov a, 1 + \\\n        (2 * -3)\n"),
+        ".section \"code\" 0\n    mov     a, 1 + (2 * -3)\n"
+    );
+}
+
+#[test]
+fn invalid_code_is_an_error() {
+    assert!(assembler::format_source(".section \"code\" 0\n    mov a,\n").is_err());
+}
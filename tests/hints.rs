@@ -0,0 +1,67 @@
+use jam1emu_lib::assembler::{self, MemoryFileProvider};
+use langbox::FileServer;
+
+fn strip_colors(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+fn error_output(code: &str) -> String {
+    match assembler::assemble_code(code, false) {
+        Ok(_) => panic!("`{code}` was accepted"),
+        Err(output) => strip_colors(&output),
+    }
+}
+
+#[test]
+fn single_line_hint() {
+    let output = error_output(".section \"code\" 0\n    mov a, undefined\n");
+
+    assert!(output.contains(
+        "  |\r\n\
+         2 |      mov a, undefined\r\n  \
+           |             ^^^^^^^^^\r\n  \
+           |\r\n"
+    ));
+}
+
+#[test]
+fn multi_line_hint() {
+    // The line continuation makes the unknown statement span two lines
+    let output = error_output(".section \"code\" 0\n    1 + \\\n    2\n");
+
+    assert!(output.contains(
+        "  |\r\n\
+         2 |      1 + \\\r\n  \
+           |      ^^^^^\r\n\
+         3 |      2\r\n  \
+           |      ^\r\n  \
+           |\r\n"
+    ));
+}
+
+#[test]
+fn uncolored_output() {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("main.asm", ".section \"code\" 0\n    mov a, undefined\n")
+        .unwrap();
+    let Err(errors) =
+        assembler::assemble_with_provider(&mut file_server, file, &MemoryFileProvider::new())
+    else {
+        panic!("undefined symbol was accepted");
+    };
+
+    let colored = errors[0].format(&file_server);
+    let uncolored = errors[0].format_styled(&file_server, assembler::DEFAULT_TAB_WIDTH, false);
+    assert!(colored.contains('\x1B'));
+    assert_eq!(uncolored, strip_colors(&colored));
+}
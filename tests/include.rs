@@ -0,0 +1,31 @@
+use jam1emu_lib::assembler::{self, MemoryFileProvider};
+use langbox::FileServer;
+
+const MAIN: &str = r#"
+.section "code" 0
+.include "lib/defs.asm"
+    mov a, VALUE
+"#;
+
+#[test]
+fn include_resolves_in_memory() {
+    let mut files = MemoryFileProvider::new();
+    files.add_file("lib/defs.asm", "VALUE = 0x2A\n");
+
+    let mut file_server = FileServer::new();
+    let file = file_server.register_file_memory("main.asm", MAIN).unwrap();
+
+    let program = assembler::assemble_with_provider(&mut file_server, file, &files)
+        .unwrap_or_else(|errors| panic!("{errors:?}"));
+    assert_eq!(program.data(), &[0x01, 0x2A]);
+}
+
+#[test]
+fn missing_include_is_an_error() {
+    let files = MemoryFileProvider::new();
+
+    let mut file_server = FileServer::new();
+    let file = file_server.register_file_memory("main.asm", MAIN).unwrap();
+
+    assert!(assembler::assemble_with_provider(&mut file_server, file, &files).is_err());
+}
@@ -0,0 +1,111 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+const HANDLER: u16 = 0x100;
+
+// Counts its iterations in `b`. TX and the flags are live across most of the loop, losing either
+// of them makes it stop at the `break` or jump into the handler. The handler counts its calls
+// in `c` and changes both TX and the flags in its delay loop.
+const PROGRAM: &str = ".section \"code\" 0
+    mov tl, 0x00
+    mov th, 0x80
+    mov sp, tx
+main:
+    inc b
+    mov a, 0
+    test a
+    mov a, 1
+    jnz fail
+    mov tx, main
+    inc a
+    jmp tx
+fail:
+    break
+
+.section \"handler\" 0x100
+    mov d, 0x20
+delay:
+    dec d
+    jnz delay
+    inc c
+    reti
+";
+
+fn start() -> System<CaptureTerminal> {
+    let program =
+        assembler::assemble_code(PROGRAM, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+    system.clock(100);
+
+    // Only room for one return address, so nested handlers overflow the stack
+    system.set_stack_bounds(0x7FFE..0x8000);
+    system.set_interrupts_enabled(true);
+    system
+}
+
+fn in_handler(system: &System<CaptureTerminal>) -> bool {
+    system.get_reg(Register::PC) >= HANDLER
+}
+
+#[test]
+fn handler_returns_to_interrupted_code() {
+    let mut system = start();
+
+    system.cpu_mut().request_interrupt(HANDLER);
+    system.clock(20);
+    assert!(in_handler(&system));
+    assert!(system.cpu().in_interrupt());
+
+    assert!(!system.clock(2000));
+    assert!(!in_handler(&system));
+    assert!(!system.cpu().in_interrupt());
+    assert_eq!(system.cpu().c(), 1);
+    assert_eq!(system.get_reg(Register::SP), 0x8000);
+    assert_eq!(system.stack_fault(), None);
+}
+
+#[test]
+fn interrupts_do_not_nest() {
+    let mut system = start();
+
+    system.cpu_mut().request_interrupt(HANDLER);
+    system.clock(20);
+    assert!(in_handler(&system));
+
+    // Taken once the first handler has returned instead of interrupting it
+    system.cpu_mut().request_interrupt(HANDLER);
+    assert!(!system.clock(4000));
+    assert!(!in_handler(&system));
+    assert_eq!(system.cpu().c(), 2);
+    assert_eq!(system.get_reg(Register::SP), 0x8000);
+    assert_eq!(system.stack_fault(), None);
+}
+
+#[test]
+fn tx_and_flags_survive_the_interrupt() {
+    // Interrupt every cycle of the loop once
+    for offset in 0..40 {
+        let mut system = start();
+        system.clock(offset);
+        let iterations = system.cpu().b();
+
+        system.cpu_mut().request_interrupt(HANDLER);
+        assert!(
+            !system.clock(1500),
+            "stopped after interrupt at offset {offset}"
+        );
+        assert_eq!(system.cpu().c(), 1, "no interrupt at offset {offset}");
+        assert!(!in_handler(&system), "lost at offset {offset}");
+        assert!(
+            system.cpu().b().wrapping_sub(iterations) > 10,
+            "stuck after interrupt at offset {offset}"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use jam1emu_lib::{assembler, disassembler};
+
+// Every IO register with whether `in` can read it and `out` can write it
+const IO_REGISTERS: &[(&str, bool, bool)] = &[
+    ("uart_data", true, true),
+    ("uart_ctrl", true, false),
+    ("audio_data", false, true),
+    ("cntrl_data", true, false),
+    ("vga", true, false),
+    ("gpio", true, true),
+];
+
+// Assembles the instruction and returns its disassembly if it was accepted
+fn assemble(instruction: &str) -> Option<String> {
+    let code = format!(".section \"code\" 0\n    {instruction}\n");
+    let program = assembler::assemble_code(&code, false).ok()?;
+    let disassembled = disassembler::disassemble(program.data())?;
+    Some(disassembled.text().to_owned())
+}
+
+#[test]
+fn in_accepts_readable_registers() {
+    for &(register, readable, _) in IO_REGISTERS {
+        let instruction = format!("in a, {register}");
+        let expected = readable.then(|| instruction.clone());
+        assert_eq!(assemble(&instruction), expected, "`{instruction}`");
+    }
+}
+
+#[test]
+fn out_accepts_writable_registers() {
+    for &(register, _, writable) in IO_REGISTERS {
+        let instruction = format!("out {register}, a");
+        let expected = writable.then(|| instruction.clone());
+        assert_eq!(assemble(&instruction), expected, "`{instruction}`");
+    }
+}
+
+#[test]
+fn only_a_is_transferred() {
+    assert_eq!(assemble("in b, uart_data"), None);
+    assert_eq!(assemble("out uart_data, b"), None);
+}
@@ -0,0 +1,30 @@
+use jam1emu_lib::assembler;
+
+// Inputs the assembler used to panic on or silently accept instead of reporting errors
+fn assert_rejected(code: &str) {
+    assert!(
+        assembler::assemble_code(code, false).is_err(),
+        "`{code}` was accepted"
+    );
+}
+
+#[test]
+fn surrogate_escape() {
+    assert_rejected(".section \"\\uD800\" 0\n");
+}
+
+#[test]
+fn signed_escape() {
+    assert_rejected(".section \"\\x+1\" 0\n");
+    assert_rejected(".section \"\\u+041\" 0\n");
+}
+
+#[test]
+fn align_past_address_space() {
+    assert_rejected(".section \"code\" 0\n.offset 0x8001\n.align 0x8000\n");
+}
+
+#[test]
+fn overlap_with_oversized_section() {
+    assert_rejected(".section \"a\" 0xFFFF\n.offset 0x10\n.section \"b\" 0\n    nop\n");
+}
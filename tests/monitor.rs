@@ -0,0 +1,100 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::System;
+
+// Upper bound of emulated time for the monitor to boot, about two seconds
+const MAX_BOOT_FRAMES: u32 = 120;
+
+#[test]
+fn monitor_boots_to_prompt() {
+    let mut system = System::create(CaptureTerminal::new());
+    // Collects the samples instead of opening an audio device
+    system.set_deterministic_audio(true);
+    system.reset();
+
+    for _ in 0..MAX_BOOT_FRAMES {
+        system.clock_frame();
+
+        if system.terminal().displayed().contains('>') {
+            return;
+        }
+    }
+
+    panic!(
+        "no prompt after {MAX_BOOT_FRAMES} frames, displayed {:?}",
+        system.terminal().displayed()
+    );
+}
+
+fn cycle_budget(system: &System<CaptureTerminal>) -> u64 {
+    (system.cycles_per_frame() * (MAX_BOOT_FRAMES as f64)) as u64
+}
+
+#[test]
+fn command_response_is_awaited() {
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+
+    let budget = cycle_budget(&system);
+    assert!(system.run_until_output(b">", budget), "no prompt");
+
+    system.write_line("help");
+    assert!(system.run_until_output(b"This message", budget));
+    assert!(system.terminal().displayed().contains("Commands:"));
+}
+
+#[test]
+fn output_wait_gives_up() {
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+
+    assert!(!system.run_until_output(b"never printed", 10_000));
+}
+
+#[test]
+fn program_loads_through_uart() {
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+
+    let budget = cycle_budget(&system);
+    assert!(system.run_until_output(b">", budget), "no prompt");
+
+    let data: Vec<u8> = (0..=255).rev().collect();
+    let stream = assembler::to_monitor_loader_stream(0x1234, &data);
+    system.write_str(std::str::from_utf8(&stream).unwrap());
+
+    // The monitor prompts again once all bytes have been read
+    assert!(
+        system.run_until_output(b">", budget),
+        "no prompt after loading"
+    );
+    assert!(system
+        .terminal()
+        .displayed()
+        .ends_with("writebin 1234 100\n->"));
+
+    let loaded: Vec<u8> = (0x1234..)
+        .take(data.len())
+        .map(|addr| system.peek(addr))
+        .collect();
+    assert_eq!(loaded, data);
+}
+
+#[test]
+fn short_monitor_clears_the_rest_of_the_rom() {
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    assert!((0xE010..=0xFFFF).any(|addr| system.peek(addr) != 0));
+
+    let monitor = [0x01, 0x2A, 0x3F, 0x00];
+    system.set_monitor(&monitor).unwrap();
+    system.reset();
+
+    let rom: Vec<_> = (0xE000..=0xFFFF).map(|addr| system.peek(addr)).collect();
+    assert_eq!(rom[..monitor.len()], monitor);
+    assert!(rom[monitor.len()..].iter().all(|&byte| byte == 0));
+}
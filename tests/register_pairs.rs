@@ -0,0 +1,121 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+struct Pairs {
+    ab: u16,
+    cd: u16,
+    carry: bool,
+}
+
+const CARRY: u16 = 0x100;
+
+// Runs `instruction` on the register pairs and stops at a different `break` depending on the carry
+fn run(instruction: &str, ab: u16, cd: u16) -> Pairs {
+    let [a, b] = ab.to_le_bytes();
+    let [c, d] = cd.to_le_bytes();
+    let code = format!(
+        ".section \"code\" 0
+    mov a, {a}
+    mov b, {b}
+    mov c, {c}
+    mov d, {d}
+    {instruction}
+    jc carry
+    break
+
+.section \"carry\" 0x100
+carry:
+    break
+"
+    );
+    let program =
+        assembler::assemble_code(&code, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+
+    assert!(system.clock(1000), "`{instruction}` never reached a break");
+    let carry = system.get_reg(Register::PC) >= CARRY;
+
+    let cpu = system.cpu();
+    Pairs {
+        ab: u16::from_le_bytes([cpu.a(), cpu.b()]),
+        cd: u16::from_le_bytes([cpu.c(), cpu.d()]),
+        carry,
+    }
+}
+
+#[test]
+fn pair_forms_emit_two_instructions() {
+    for (instruction, encoding) in [
+        ("add ab, cd", [0x89, 0x99]),
+        ("add cd, ab", [0x8E, 0x9E]),
+        ("sub ab, cd", [0xA9, 0xB9]),
+        ("sub cd, ab", [0xAE, 0xBE]),
+    ] {
+        let program =
+            assembler::assemble_code(&format!(".section \"code\" 0\n{instruction}"), false)
+                .unwrap_or_else(|output| panic!("{output}"));
+        assert_eq!(program.data(), encoding, "`{instruction}`");
+    }
+}
+
+#[test]
+fn add_carries_into_the_high_byte() {
+    let result = run("add ab, cd", 0x12FF, 0x0001);
+    assert_eq!(result.ab, 0x1300);
+    assert_eq!(result.cd, 0x0001);
+    assert!(!result.carry);
+
+    let result = run("add cd, ab", 0x01F0, 0x0220);
+    assert_eq!(result.ab, 0x01F0);
+    assert_eq!(result.cd, 0x0410);
+    assert!(!result.carry);
+
+    let result = run("add ab, cd", 0x1234, 0x1111);
+    assert_eq!(result.ab, 0x2345);
+    assert!(!result.carry);
+}
+
+#[test]
+fn add_sets_the_carry_when_the_pair_overflows() {
+    let result = run("add ab, cd", 0xFFFF, 0x0001);
+    assert_eq!(result.ab, 0x0000);
+    assert!(result.carry);
+
+    let result = run("add cd, ab", 0x8000, 0x8001);
+    assert_eq!(result.cd, 0x0001);
+    assert!(result.carry);
+}
+
+#[test]
+fn sub_borrows_from_the_high_byte() {
+    // The carry is set if nothing was borrowed from outside the pair
+    let result = run("sub ab, cd", 0x1300, 0x0001);
+    assert_eq!(result.ab, 0x12FF);
+    assert_eq!(result.cd, 0x0001);
+    assert!(result.carry);
+
+    let result = run("sub cd, ab", 0x00F0, 0x0210);
+    assert_eq!(result.ab, 0x00F0);
+    assert_eq!(result.cd, 0x0120);
+
+    let result = run("sub ab, cd", 0x5678, 0x1234);
+    assert_eq!(result.ab, 0x4444);
+}
+
+#[test]
+fn sub_wraps_below_zero() {
+    let result = run("sub ab, cd", 0x0000, 0x0001);
+    assert_eq!(result.ab, 0xFFFF);
+    assert!(!result.carry);
+
+    let result = run("sub cd, ab", 0x0100, 0x00FF);
+    assert_eq!(result.cd, 0xFFFF);
+    assert!(!result.carry);
+}
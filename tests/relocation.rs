@@ -0,0 +1,118 @@
+use jam1emu_lib::assembler::{self, Relocation};
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+// Reads the immediate of the last instruction through `si`, calls a subroutine and jumps over
+// a store, all of which use absolute addresses. The distance between two labels stays the same
+// wherever the program is loaded.
+const PROGRAM: &str = ".section \"code\" 0
+start:
+    mov tl, 0x00
+    mov th, 0x80
+    mov sp, tx
+    mov si, value + 1
+    lodsb
+    call double
+    mov c, value - start
+    jmp done
+    mov c, 0xFF
+done:
+    break
+
+double:
+    mov b, a
+    add a, b
+    ret
+
+value:
+    mov a, 0x15
+";
+
+fn assemble() -> assembler::Program {
+    assembler::assemble_code(PROGRAM, false).unwrap_or_else(|output| panic!("{output}"))
+}
+
+fn address(data: &[u8], entry: &Relocation) -> u16 {
+    u16::from_le_bytes([
+        data[entry.low() as usize],
+        entry.high().map_or(0, |high| data[high as usize]),
+    ])
+}
+
+#[test]
+fn address_references_are_recorded() {
+    let program = assemble();
+    let relocations = program.relocations();
+    assert_eq!(relocations.base(), 0);
+
+    // `mov si, value + 1`, `call double` and `jmp done`, the label difference is not an address
+    let entries = relocations.entries();
+    assert_eq!(entries.len(), 3, "{entries:?}");
+    assert_eq!(entries[0].low(), 6);
+    assert_eq!(entries[0].high(), Some(8));
+
+    let targets: Vec<_> = entries
+        .iter()
+        .map(|entry| address(program.data(), entry) as i64)
+        .collect();
+    let symbols: Vec<_> = ["value", "double", "done"]
+        .into_iter()
+        .map(|name| program.symbol(name).unwrap())
+        .collect();
+    let symbols = [symbols[0] + 1, symbols[1], symbols[2]];
+    assert_eq!(targets, symbols);
+}
+
+#[test]
+fn apply_moves_only_the_addresses() {
+    let program = assemble();
+    let relocations = program.relocations();
+
+    let mut data = program.data().to_vec();
+    relocations.apply(&mut data, 0);
+    assert_eq!(data, program.data());
+
+    relocations.apply(&mut data, 0x1234);
+    for entry in relocations.entries() {
+        assert_eq!(
+            address(&data, entry),
+            address(program.data(), entry) + 0x1234
+        );
+    }
+
+    let mut moved = data.clone();
+    for entry in relocations.entries() {
+        moved[entry.low() as usize] = program.data()[entry.low() as usize];
+        if let Some(high) = entry.high() {
+            moved[high as usize] = program.data()[high as usize];
+        }
+    }
+    assert_eq!(moved, program.data(), "bytes other than addresses changed");
+}
+
+#[test]
+fn relocated_program_runs_at_another_base() {
+    const BASE: u16 = 0x1000;
+
+    let program = assemble();
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system
+        .load_program_relocated(BASE, program.data(), program.relocations())
+        .unwrap();
+    system.set_reg(Register::PC, BASE).unwrap();
+
+    assert!(system.clock(1000), "program never stopped");
+    assert_eq!(system.cpu().a(), 0x2A);
+    assert_eq!(system.cpu().b(), 0x15);
+    assert_eq!(system.cpu().c() as i64, program.symbol("value").unwrap());
+
+    // Stopped at the relocated `break` instead of running off into memory
+    let pc = system.get_reg(Register::PC);
+    assert!(
+        (BASE..(BASE + program.data().len() as u16)).contains(&pc),
+        "0x{pc:0>4X}"
+    );
+}
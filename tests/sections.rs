@@ -0,0 +1,96 @@
+use jam1emu_lib::assembler;
+
+const CODE: &str = r#"
+.section "code" 0x100
+    mov a, 0x2A
+    nop
+.section "data" 0x200
+    nop
+"#;
+
+#[test]
+fn section_layouts() {
+    let program = assembler::assemble_code(CODE, false).unwrap_or_else(|output| panic!("{output}"));
+    let sections: Vec<_> = program
+        .sections()
+        .iter()
+        .map(|section| {
+            (
+                section.name(),
+                section.base(),
+                section.size(),
+                section.headroom(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        sections,
+        [("code", 0x100, 3, 0xFD), ("data", 0x200, 1, 0xDDFF)]
+    );
+    assert!(assembler::monitor_warning(program.sections()).is_none());
+}
+
+#[test]
+fn warning_close_to_monitor() {
+    let program = assembler::assemble_code(".section \"code\" 0xDFF0\n    nop\n", false)
+        .unwrap_or_else(|output| panic!("{output}"));
+
+    assert!(assembler::monitor_warning(program.sections()).is_some());
+}
+
+fn assemble(code: &str) -> assembler::Program {
+    assembler::assemble_code(code, false).unwrap_or_else(|output| panic!("{output}"))
+}
+
+#[test]
+fn origin_places_sections_without_base() {
+    let program = assemble(
+        r#"
+.origin 0x300
+.section "code"
+    nop
+.section "fixed" 0x100
+    nop
+.section "data"
+    nop
+"#,
+    );
+
+    let bases: Vec<_> = program
+        .sections()
+        .iter()
+        .map(|section| (section.name(), section.base()))
+        .collect();
+    assert_eq!(bases, [("fixed", 0x100), ("code", 0x300), ("data", 0x301)]);
+}
+
+#[test]
+fn origin_after_section_is_an_error() {
+    let code = ".section \"code\"\n    nop\n.origin 0x100\n";
+    assert!(assembler::assemble_code(code, false).is_err());
+}
+
+#[test]
+fn end_ignores_the_rest() {
+    let program = assemble(".section \"code\" 0\n    nop\n.end\n    this is not assembly $%&\n");
+    assert_eq!(program.data(), &[0x00]);
+}
+
+#[test]
+fn end_sets_entry() {
+    let program = assemble(".section \"code\" 0x100\n    nop\nstart:\n    break\n.end start\n");
+    assert_eq!(program.entry(), 0x101);
+
+    let code = ".section \"code\" 0\n.entry start\nstart:\n    nop\n.end start\n";
+    assert!(assembler::assemble_code(code, false).is_err());
+}
+
+#[test]
+fn format_keeps_text_after_end() {
+    let code = ".section \"code\" 0\n  NOP\n.end\n  not   formatted $%&\n";
+    assert_eq!(
+        assembler::format_source(code).unwrap(),
+        ".section \"code\" 0\n    nop\n.end\n  not   formatted $%&\n"
+    );
+}
@@ -0,0 +1,56 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::{Register, StackFault};
+use jam1emu_lib::System;
+
+// Sets up an empty stack at 0x8000 before running `body`
+fn run(body: &str) -> System<CaptureTerminal> {
+    let code = format!(
+        ".section \"code\" 0
+    mov tl, 0x00
+    mov th, 0x80
+    mov sp, tx
+{body}
+    break
+"
+    );
+    let program =
+        assembler::assemble_code(&code, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.set_stack_bounds(0x7FFE..0x8000);
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+
+    assert!(system.clock(1000), "program never stopped");
+    system
+}
+
+#[test]
+fn balanced_stack_runs_through() {
+    let system = run("    push a\n    push b\n    pop b\n    pop a");
+    assert_eq!(system.stack_fault(), None);
+}
+
+#[test]
+fn push_below_bounds_is_an_overflow() {
+    let system = run("    push a\n    push a\n    push a");
+    assert_eq!(system.stack_fault(), Some(StackFault::Overflow(0x7FFD)));
+}
+
+#[test]
+fn pop_from_empty_stack_is_an_underflow() {
+    let system = run("    pop a");
+    assert_eq!(system.stack_fault(), Some(StackFault::Underflow(0x8001)));
+}
+
+#[test]
+fn bounds_are_kept_when_loading_a_state() {
+    let mut system = run("    push a");
+    let state = system.save_state();
+
+    system.load_state(&state).unwrap();
+    assert_eq!(system.stack_bounds(), Some(0x7FFE..0x8000));
+}
@@ -0,0 +1,61 @@
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::state::StateError;
+use jam1emu_lib::System;
+
+fn booted() -> System<CaptureTerminal> {
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    for _ in 0..10 {
+        system.clock_frame();
+    }
+    system
+}
+
+#[test]
+fn state_survives_a_round_trip() {
+    let mut system = booted();
+    let state = system.save_state();
+
+    let mut restored = System::create(CaptureTerminal::new());
+    restored.set_deterministic_audio(true);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    assert_eq!(restored.total_cycles(), system.total_cycles());
+
+    // Both continue from exactly the same point
+    for _ in 0..10 {
+        system.clock_frame();
+        restored.clock_frame();
+    }
+    assert_eq!(restored.save_state(), system.save_state());
+    assert_eq!(restored.framebuffer(), system.framebuffer());
+}
+
+#[test]
+fn state_is_compressed() {
+    // Most of the memory is still zeroed after booting
+    let state = booted().save_state();
+    assert!(state.len() < 0x10000 / 4, "state is {} bytes", state.len());
+}
+
+#[test]
+fn broken_states_are_rejected() {
+    let mut system = booted();
+    let state = system.save_state();
+    let cycles = system.total_cycles();
+
+    assert_eq!(system.load_state(b"JAM0"), Err(StateError::InvalidHeader));
+    assert!(matches!(
+        system.load_state(&[b"JAM1".as_slice(), &[0xFF, 0xFF]].concat()),
+        Err(StateError::UnsupportedVersion(0xFFFF))
+    ));
+    assert_eq!(
+        system.load_state(&state[..(state.len() - 1)]),
+        Err(StateError::Truncated)
+    );
+
+    // Failed loads leave the system untouched
+    assert_eq!(system.total_cycles(), cycles);
+    assert_eq!(system.save_state(), state);
+}
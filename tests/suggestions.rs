@@ -0,0 +1,50 @@
+use jam1emu_lib::assembler::{self, MemoryFileProvider};
+use langbox::FileServer;
+
+fn error_output(code: &str) -> String {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("main.asm", code.to_owned())
+        .unwrap();
+    let Err(errors) =
+        assembler::assemble_with_provider(&mut file_server, file, &MemoryFileProvider::new())
+    else {
+        panic!("`{code}` was accepted");
+    };
+
+    errors
+        .iter()
+        .map(|error| error.format_styled(&file_server, assembler::DEFAULT_TAB_WIDTH, false))
+        .collect()
+}
+
+#[test]
+fn misspelled_mnemonic() {
+    let output = error_output(".section \"code\" 0\nstart:\n    jpm start\n");
+
+    assert!(output.contains("unknown mnemonic"));
+    assert!(output.contains("did you mean `jmp`?"));
+}
+
+#[test]
+fn misspelled_directive() {
+    let output = error_output(".section \"code\" 0\n.ofset 4\n");
+
+    assert!(output.contains("did you mean `.offset`?"));
+}
+
+#[test]
+fn misspelled_register() {
+    let output = error_output(".section \"code\" 0\n    mov a, spp\n");
+
+    assert!(output.contains("symbol is not defined"));
+    assert!(output.contains("did you mean the register `sp`?"));
+}
+
+#[test]
+fn no_suggestion_for_labels() {
+    // Labels close to a mnemonic must not be mistaken for one
+    let output = error_output(".section \"code\" 0\njpm:\n    mov a, undefined\n");
+
+    assert!(!output.contains("did you mean"));
+}
@@ -0,0 +1,53 @@
+use jam1emu_lib::assembler;
+
+const CODE: &str = ".section \"code\" 0\n\tmov a,\t\tundefined\n";
+
+fn strip_colors(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+// Returns the column of the symbol in the printed line and the column of the caret below it
+fn hint_columns(tab_width: usize) -> (usize, usize) {
+    let Err(output) = assembler::assemble_code_with_tab_width(CODE, false, tab_width) else {
+        panic!("undefined symbol was accepted");
+    };
+
+    let output = strip_colors(&output);
+    let lines: Vec<_> = output.split("\r\n").collect();
+    let code_line = lines
+        .iter()
+        .find(|line| line.contains("undefined"))
+        .unwrap();
+    let hint_line = lines.iter().find(|line| line.contains('^')).unwrap();
+
+    assert!(!code_line.contains('\t'));
+    (
+        code_line.find("undefined").unwrap(),
+        hint_line.find('^').unwrap(),
+    )
+}
+
+#[test]
+fn hint_lines_up_with_tabs() {
+    for tab_width in [2, 4, 8] {
+        let (symbol, hint) = hint_columns(tab_width);
+        assert_eq!(symbol, hint, "tab width {tab_width}");
+    }
+}
+
+#[test]
+fn tabs_advance_to_tab_stops() {
+    // The code is printed after `2 |  `. `mov a,` ends at column 10 with a tab width of 4,
+    // so the tabs advance to 12 and 16. With a tab width of 8 they advance to 16 and 24.
+    assert_eq!(hint_columns(4).0, 5 + 16);
+    assert_eq!(hint_columns(8).0, 5 + 24);
+}
@@ -0,0 +1,168 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::trace::TraceEntry;
+use jam1emu_lib::System;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+const PROGRAM: &str = ".section \"code\" 0
+    mov a, 5
+    mov b, a
+    inc b
+    break
+";
+
+fn start() -> System<CaptureTerminal> {
+    let program =
+        assembler::assemble_code(PROGRAM, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+    system
+}
+
+// Registers are the ones at the time of the fetch, the pipeline fetches past the `break`
+const TRACE: &str = "\
+1 0000: 01 05 mov a, 0x05          A=00 B=00 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=04
+3 0002: 0A    mov b, a             A=05 B=00 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=04
+4 0003: A1    inc b                A=05 B=00 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=04
+5 0004: 00    nop                  A=05 B=05 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=04
+6 0005: 3F    break                A=05 B=06 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=00
+7 0006: 00    nop                  A=05 B=06 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=00
+8 0007: 00    nop                  A=05 B=06 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=00
+";
+
+// A file in the temp directory that is removed again when the test ends
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("jam1emu-{}-{name}", std::process::id())))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn trace_has_a_line_per_instruction() {
+    let file = TempFile::new("trace.txt");
+    let mut system = start();
+    system.set_trace_file(&file.0).unwrap();
+    assert!(system.clock(100));
+    system.stop_trace().unwrap();
+
+    let trace = std::fs::read_to_string(&file.0).unwrap();
+    assert_eq!(trace, TRACE);
+}
+
+#[test]
+fn nothing_is_traced_after_stopping() {
+    let file = TempFile::new("stopped.txt");
+    let mut system = start();
+    system.set_trace_file(&file.0).unwrap();
+    system.clock(2);
+    system.stop_trace().unwrap();
+    system.clock(100);
+
+    let trace = std::fs::read_to_string(&file.0).unwrap();
+    assert_eq!(trace.lines().count(), 1, "{trace}");
+}
+
+#[test]
+fn matching_trace_has_no_divergence() {
+    let mut system = start();
+    let divergence = system.run_compare(TRACE.as_bytes(), 100).unwrap();
+    assert_eq!(divergence, None);
+}
+
+#[test]
+fn first_difference_is_reported() {
+    // `inc b` is changed to increment by two
+    let reference = TRACE.replace(
+        "6 0005: 3F    break                A=05 B=06",
+        "6 0005: 3F    break                A=05 B=07",
+    );
+
+    let mut system = start();
+    let divergence = system
+        .run_compare(reference.as_bytes(), 100)
+        .unwrap()
+        .expect("no divergence");
+
+    assert_eq!(divergence.line(), 5);
+    assert_eq!(divergence.expected().address(), 0x0005);
+    assert_eq!(divergence.expected().registers().b(), 0x07);
+    assert_eq!(divergence.actual().registers().b(), 0x06);
+    assert_eq!(divergence.actual().cycle(), 6);
+}
+
+#[test]
+fn cycle_counts_are_not_compared() {
+    let reference: String = TRACE.lines().map(|line| format!("1{line}\n")).collect();
+
+    let mut system = start();
+    assert_eq!(system.run_compare(reference.as_bytes(), 100).unwrap(), None);
+}
+
+#[test]
+fn malformed_reference_is_an_error() {
+    let reference = TRACE.replace("3 0002: 0A    mov b, a ", "3 0002 0A    mov b, a ");
+
+    let mut system = start();
+    let err = system.run_compare(reference.as_bytes(), 100).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("line 2:"), "{err}");
+}
+
+#[test]
+fn entries_round_trip_through_text() {
+    for line in TRACE.lines() {
+        let entry: TraceEntry = line.parse().unwrap();
+        assert_eq!(entry.to_string(), line);
+    }
+
+    let entry: TraceEntry = TRACE.lines().next().unwrap().parse().unwrap();
+    assert_eq!(entry.cycle(), 1);
+    assert_eq!(entry.address(), 0x0000);
+    assert_eq!(entry.bytes(), &[0x01, 0x05]);
+    assert_eq!(entry.instruction(), "mov a, 0x05");
+    assert_eq!(entry.registers().flags(), 0x04);
+}
+
+#[test]
+fn malformed_entries_are_rejected() {
+    const REGISTERS: &str = "A=00 B=00 C=00 D=00 TX=0000 SI=0000 DI=0000 SP=0000 RA=0000 F=00";
+
+    let errors = [
+        ("", "missing cycle count"),
+        ("x 0000: 00 nop", "missing cycle count"),
+        ("1 0000 00 nop", "missing address"),
+        ("1 G000: 00 nop", "`G000` is not a valid address"),
+        ("1 10000: 00 nop", "`10000` is not a valid address"),
+        ("1 0000: 00 nop A=00", "missing register `B`"),
+        ("1 0000: 00 nop A=100", "register `A` is out of range"),
+        ("1 0000: 00 nop A=00 X=00", "unknown register `X`"),
+        ("1 0000: 00 nop A=00 B", "unexpected `B`"),
+        ("1 0000: 00 nop A=ZZ", "`ZZ` is not a valid register value"),
+    ];
+
+    for (line, message) in errors {
+        let err = line.parse::<TraceEntry>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("invalid trace line: {message}"),
+            "`{line}`"
+        );
+    }
+
+    let line = format!("1 0000: 00 nop {REGISTERS}");
+    assert!(line.parse::<TraceEntry>().is_ok());
+}
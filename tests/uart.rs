@@ -0,0 +1,161 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+use std::path::PathBuf;
+
+// Sends two bytes and reads them back into `c` and `d` once both have been received
+const ECHO: &str = ".section \"code\" 0
+    mov a, 0x41
+    out uart_data, a
+    mov a, 0x42
+    out uart_data, a
+wait:
+    in a, uart_ctrl
+    mov b, 0x0F
+    and a, b
+    mov b, 2
+    cmp a, b
+    jne wait
+    in a, uart_data
+    mov c, a
+    in a, uart_data
+    mov d, a
+    break
+";
+
+// Sends ten bytes one after another without reading any of them, then stores the
+// control register in `c` and the first received byte in `b`
+const FLOOD: &str = ".section \"code\" 0
+    mov a, 0
+    mov d, 10
+send:
+    out uart_data, a
+    inc a
+    mov c, a
+wait:
+    in a, uart_ctrl
+    mov b, 0xF0
+    and a, b
+    jnz wait
+    mov a, c
+    dec d
+    jnz send
+    in a, uart_ctrl
+    mov c, a
+    in a, uart_data
+    mov b, a
+    break
+";
+
+// A file in the temp directory that is removed again when the test ends
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("jam1emu-{}-{name}", std::process::id())))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn start(code: &str) -> System<CaptureTerminal> {
+    let program = assembler::assemble_code(code, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+    system
+}
+
+#[test]
+fn loopback_receives_own_bytes() {
+    let mut system = start(ECHO);
+    system.set_uart_loopback(true);
+    system.write_str("x");
+
+    assert!(system.clock(100_000), "bytes never came back");
+    assert_eq!(system.cpu().c(), 0x41);
+    assert_eq!(system.cpu().d(), 0x42);
+
+    // The host neither sees the bytes nor gets to send its own
+    assert_eq!(system.terminal().displayed(), "");
+    assert_eq!(system.input_queue_len(), 1);
+}
+
+#[test]
+fn bytes_go_to_the_host_without_loopback() {
+    let mut system = start(ECHO);
+
+    assert!(!system.clock(100_000));
+    assert_eq!(system.terminal().displayed(), "AB");
+}
+
+#[test]
+fn loopback_overruns_the_receive_fifo() {
+    let mut system = start(FLOOD);
+    system.set_uart_loopback(true);
+
+    assert!(system.clock(200_000), "program never finished");
+    assert_eq!(system.cpu().c(), 0x08);
+    assert_eq!(system.cpu().b(), 0x00);
+}
+
+#[test]
+fn log_has_both_directions() {
+    let file = TempFile::new("uart.log");
+    let mut system = start(ECHO);
+    system.set_uart_log(&file.0).unwrap();
+    system.write_str("xy");
+
+    assert!(system.clock(100_000), "bytes were never received");
+    system.stop_uart_log().unwrap();
+    assert_eq!(system.cpu().c(), b'x');
+    assert_eq!(system.cpu().d(), b'y');
+
+    let log = std::fs::read_to_string(&file.0).unwrap();
+    let entries: Vec<_> = log
+        .lines()
+        .map(|line| {
+            let [cycle, direction, byte] = line.split(' ').collect::<Vec<_>>()[..] else {
+                panic!("malformed line `{line}`");
+            };
+            (cycle.parse::<u64>().unwrap(), direction, byte)
+        })
+        .collect();
+
+    assert!(
+        entries.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+        "{log}"
+    );
+    let sent: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.1 == "<")
+        .map(|entry| entry.2)
+        .collect();
+    let received: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.1 == ">")
+        .map(|entry| entry.2)
+        .collect();
+    assert_eq!(sent, ["41", "42"], "{log}");
+    assert_eq!(received, ["78", "79"], "{log}");
+}
+
+#[test]
+fn loopback_is_not_logged() {
+    let file = TempFile::new("loopback.log");
+    let mut system = start(ECHO);
+    system.set_uart_log(&file.0).unwrap();
+    system.set_uart_loopback(true);
+
+    assert!(system.clock(100_000), "bytes never came back");
+    system.stop_uart_log().unwrap();
+    assert_eq!(std::fs::read_to_string(&file.0).unwrap(), "");
+}
@@ -0,0 +1,65 @@
+use jam1emu_lib::assembler::{self, AssemblerError, Program, Severity};
+use langbox::FileServer;
+
+const UNUSED: &str = ".section \"code\" 0
+start:
+    jmp start
+unused:
+    break
+";
+
+fn assemble(
+    code: &str,
+    warn_unused: bool,
+) -> Result<(Program, Vec<AssemblerError>), Vec<AssemblerError>> {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("main.asm", code.to_owned())
+        .unwrap();
+    assembler::assemble_with_warnings(&mut file_server, file, false, warn_unused)
+}
+
+#[test]
+fn unused_symbol_is_a_warning() {
+    let (program, warnings) = assemble(UNUSED, true).unwrap_or_else(|errors| panic!("{errors:?}"));
+
+    assert_eq!(program.symbol("unused"), Some(6));
+    assert!(
+        matches!(warnings[..], [AssemblerError::UnusedSymbol { .. }]),
+        "{warnings:?}"
+    );
+    assert_eq!(warnings[0].severity(), Severity::Warning);
+}
+
+#[test]
+fn unused_symbols_are_opt_in() {
+    let (_, warnings) = assemble(UNUSED, false).unwrap_or_else(|errors| panic!("{errors:?}"));
+    assert!(warnings.is_empty(), "{warnings:?}");
+}
+
+#[test]
+fn program_close_to_the_monitor_is_a_warning() {
+    let (program, warnings) = assemble(".section \"code\" 0xDFF0\n    nop\n", false)
+        .unwrap_or_else(|errors| panic!("{errors:?}"));
+
+    assert_eq!(program.data(), &[0x00]);
+    assert!(
+        matches!(warnings[..], [AssemblerError::CloseToMonitor { .. }]),
+        "{warnings:?}"
+    );
+}
+
+#[test]
+fn warnings_are_returned_with_errors() {
+    let errors = match assemble(&format!("{UNUSED}    jmp nowhere\n"), true) {
+        Ok(_) => panic!("undefined symbol was accepted"),
+        Err(errors) => errors,
+    };
+
+    let severities: Vec<_> = errors.iter().map(AssemblerError::severity).collect();
+    assert_eq!(
+        severities,
+        [Severity::Error, Severity::Warning],
+        "{errors:?}"
+    );
+}
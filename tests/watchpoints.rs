@@ -0,0 +1,76 @@
+use jam1emu_lib::assembler;
+use jam1emu_lib::capture::CaptureTerminal;
+use jam1emu_lib::cpu::Register;
+use jam1emu_lib::System;
+
+const ADDRESS: u16 = 0x4000;
+
+// Counts from 1 to 5 in `a`, writing every value to `ADDRESS`
+const PROGRAM: &str = ".section \"code\" 0
+    mov di, 0x4000
+    mov a, 0
+    mov b, 5
+loop:
+    inc a
+    mov [di], a
+    cmp a, b
+    jne loop
+    break
+";
+
+fn start() -> System<CaptureTerminal> {
+    let program =
+        assembler::assemble_code(PROGRAM, false).unwrap_or_else(|output| panic!("{output}"));
+
+    let mut system = System::create(CaptureTerminal::new());
+    system.set_deterministic_audio(true);
+    system.reset();
+    system.load_program(0, program.data()).unwrap();
+    system.set_reg(Register::PC, 0).unwrap();
+    system
+}
+
+fn hit(system: &System<CaptureTerminal>) -> Option<(u16, u8)> {
+    system.watch_hit().map(|hit| (hit.address(), hit.value()))
+}
+
+#[test]
+fn value_watchpoint_fires_on_the_matching_value() {
+    let mut system = start();
+    assert!(system.add_value_watchpoint(ADDRESS, 3));
+
+    assert!(system.clock(1000));
+    assert_eq!(hit(&system), Some((ADDRESS, 3)));
+    assert_eq!(system.peek(ADDRESS), 3);
+
+    // The other values are written without stopping
+    assert!(system.clock(1000));
+    assert_eq!(hit(&system), None);
+    assert_eq!(system.cpu().a(), 5);
+}
+
+#[test]
+fn value_watchpoint_ignores_other_values() {
+    let mut system = start();
+    assert!(system.add_value_watchpoint(ADDRESS, 0x10));
+    assert!(system.add_value_watchpoint(ADDRESS + 1, 3));
+
+    assert!(system.clock(1000));
+    assert_eq!(hit(&system), None);
+    assert_eq!(system.peek(ADDRESS), 5);
+}
+
+#[test]
+fn address_watchpoint_fires_on_any_value() {
+    let mut system = start();
+    assert!(system.add_watchpoint(ADDRESS));
+    assert!(!system.add_watchpoint(ADDRESS));
+
+    assert!(system.clock(1000));
+    assert_eq!(hit(&system), Some((ADDRESS, 1)));
+
+    assert!(system.remove_watchpoint(ADDRESS, None));
+    assert!(system.clock(1000));
+    assert_eq!(hit(&system), None);
+    assert_eq!(system.cpu().a(), 5);
+}
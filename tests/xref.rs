@@ -0,0 +1,72 @@
+use jam1emu_lib::assembler;
+use langbox::{FileServer, TextSpan};
+
+const CODE: &str = ".section \"code\" 0
+    jmp loop
+loop:
+    dec a
+    jnz loop
+unused:
+    break
+";
+
+// The zero based line and column where a span starts
+fn position(file_server: &FileServer, span: TextSpan) -> (u32, u32) {
+    span.start_pos().line_column(file_server)
+}
+
+#[test]
+fn labels_know_their_definition_and_uses() {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("main.asm", CODE.to_owned())
+        .unwrap();
+    let xref = assembler::assemble_xref(&mut file_server, file, false).unwrap();
+
+    let (definition, uses) = &xref["loop"];
+    assert_eq!(position(&file_server, *definition), (2, 0));
+    assert_eq!(definition.text(&file_server), "loop");
+
+    let uses: Vec<_> = uses
+        .iter()
+        .map(|&span| {
+            assert_eq!(span.text(&file_server), "loop");
+            position(&file_server, span)
+        })
+        .collect();
+    assert_eq!(uses, [(1, 8), (4, 8)]);
+
+    let (definition, uses) = &xref["unused"];
+    assert_eq!(position(&file_server, *definition), (5, 0));
+    assert!(uses.is_empty());
+}
+
+#[test]
+fn report_lists_every_use() {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory("main.asm", CODE.to_owned())
+        .unwrap();
+    let xref = assembler::assemble_xref(&mut file_server, file, false).unwrap();
+
+    assert_eq!(
+        assembler::format_xref(&xref, &file_server),
+        "loop (main.asm:3:1, 2 uses)
+    main.asm:2:9
+    main.asm:5:9
+unused (main.asm:6:1, 0 uses)
+"
+    );
+}
+
+#[test]
+fn invalid_code_has_no_xref() {
+    let mut file_server = FileServer::new();
+    let file = file_server
+        .register_file_memory(
+            "main.asm",
+            ".section \"code\" 0\n    jmp nowhere\n".to_owned(),
+        )
+        .unwrap();
+    assert!(assembler::assemble_xref(&mut file_server, file, false).is_err());
+}